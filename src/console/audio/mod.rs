@@ -0,0 +1,132 @@
+use super::screen::Screen;
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+mod resampler;
+mod sdl;
+mod wav;
+use resampler::Resampler;
+use sdl::SDLSink;
+use wav::WavSink;
+
+//the SPU makes samples at 44.1kHz
+pub const SAMPLE_RATE: u32 = 44_100;
+
+//where the emulated audio goes
+#[derive(Clone, Debug, PartialEq)]
+pub enum AudioOutput {
+    SDL,
+    //discards samples but plays them back in real time so pacing still works
+    Null,
+    Wav(String),
+}
+
+impl AudioOutput {
+    //anything other than sdl or null is the name of a WAV file
+    pub fn parse(name: Option<&String>) -> Self {
+        match name.map(|name| name.as_str()) {
+            None | Some("sdl") => AudioOutput::SDL,
+            Some("null") => AudioOutput::Null,
+            Some(filename) => AudioOutput::Wav(filename.to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AudioConfig {
+    pub output: AudioOutput,
+    //wait for the audio device to play each frame instead of running as fast as
+    // possible
+    pub sync: bool,
+}
+
+trait Sink {
+    //the sample rate that the sink expects
+    fn rate(&self) -> u32;
+    fn queue(&mut self, samples: &[[i16; 2]]);
+    //the number of samples that have been queued but not played yet
+    fn buffered(&self) -> usize;
+}
+
+//plays samples in real time without a sound card
+struct NullSink {
+    start: Instant,
+    queued: u64,
+}
+
+impl NullSink {
+    fn new() -> Self {
+        NullSink {
+            start: Instant::now(),
+            queued: 0,
+        }
+    }
+
+    fn played(&self) -> u64 {
+        (self.start.elapsed().as_secs_f64() * SAMPLE_RATE as f64) as u64
+    }
+}
+
+impl Sink for NullSink {
+    fn rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn queue(&mut self, samples: &[[i16; 2]]) {
+        //catch up if the emulator fell behind
+        self.queued = self.queued.max(self.played()) + samples.len() as u64;
+    }
+
+    fn buffered(&self) -> usize {
+        self.queued.saturating_sub(self.played()) as usize
+    }
+}
+
+pub struct Audio {
+    sink: Box<dyn Sink>,
+    resampler: Resampler,
+    sync: bool,
+}
+
+impl Audio {
+    //about two frames of audio
+    const LATENCY: u32 = 30;
+
+    pub fn new(screen: &Screen, config: &AudioConfig) -> io::Result<Self> {
+        let sink: Box<dyn Sink> = match &config.output {
+            //machines without a sound card can still run with the null sink
+            AudioOutput::SDL => match SDLSink::new(screen.sdl()) {
+                Ok(sink) => Box::new(sink),
+                Err(e) => {
+                    println!("Unable to open an audio device ({}), audio is disabled", e);
+                    Box::new(NullSink::new())
+                },
+            },
+            AudioOutput::Null => Box::new(NullSink::new()),
+            AudioOutput::Wav(filename) => Box::new(WavSink::new(filename)?),
+        };
+        let resampler = Resampler::new(SAMPLE_RATE, sink.rate());
+        Ok(Audio {
+            sink,
+            resampler,
+            sync: config.sync,
+        })
+    }
+
+    pub fn queue(&mut self, samples: &[[i16; 2]]) {
+        let samples = self.resampler.resample(samples);
+        self.sink.queue(&samples);
+    }
+
+    //with audio sync this blocks until the sink is close to running out of samples
+    pub fn wait(&self) {
+        if !self.sync {
+            return
+        }
+        let target = (self.sink.rate() / Audio::LATENCY) as usize;
+        while self.sink.buffered() > target {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
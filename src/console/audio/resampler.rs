@@ -0,0 +1,40 @@
+//converts between sample rates by linearly interpolating between input samples
+pub struct Resampler {
+    //the number of input samples per output sample
+    step: f64,
+    //the position of the next output sample relative to the previous input sample
+    position: f64,
+    previous: [i16; 2],
+}
+
+impl Resampler {
+    pub fn new(input_rate: u32, output_rate: u32) -> Self {
+        Resampler {
+            step: input_rate as f64 / output_rate as f64,
+            position: 0.0,
+            previous: [0, 0],
+        }
+    }
+
+    pub fn resample(&mut self, samples: &[[i16; 2]]) -> Vec<[i16; 2]> {
+        if self.step == 1.0 {
+            return samples.to_vec()
+        }
+        let mut output = Vec::with_capacity((samples.len() as f64 / self.step) as usize + 1);
+        for &sample in samples {
+            while self.position < 1.0 {
+                let mut interpolated = [0; 2];
+                for side in 0..2 {
+                    let previous = self.previous[side] as f64;
+                    let current = sample[side] as f64;
+                    interpolated[side] = (previous + (current - previous) * self.position) as i16;
+                }
+                output.push(interpolated);
+                self.position += self.step;
+            }
+            self.position -= 1.0;
+            self.previous = sample;
+        }
+        output
+    }
+}
@@ -0,0 +1,91 @@
+use super::{Sink, SAMPLE_RATE};
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+//the ring buffer shared between the emulator and SDL's audio thread
+type RingBuffer = Arc<Mutex<VecDeque<[i16; 2]>>>;
+
+struct Callback {
+    buffer: RingBuffer,
+    channels: usize,
+    //repeated when the buffer runs dry to avoid popping
+    last: [i16; 2],
+}
+
+impl AudioCallback for Callback {
+    type Channel = i16;
+
+    fn callback(&mut self, out: &mut [i16]) {
+        let mut buffer = self.buffer.lock().unwrap();
+        for frame in out.chunks_mut(self.channels) {
+            if let Some(sample) = buffer.pop_front() {
+                self.last = sample;
+            }
+            match frame {
+                [mono] => *mono = ((self.last[0] as i32 + self.last[1] as i32) / 2) as i16,
+                _ => {
+                    for (i, channel) in frame.iter_mut().enumerate() {
+                        *channel = self.last.get(i).copied().unwrap_or(0);
+                    }
+                },
+            }
+        }
+    }
+}
+
+pub struct SDLSink {
+    //the device stops playing when it's dropped
+    _device: AudioDevice<Callback>,
+    buffer: RingBuffer,
+    rate: u32,
+}
+
+impl SDLSink {
+    pub fn new(sdl: &sdl2::Sdl) -> Result<Self, String> {
+        let audio_subsystem = sdl.audio()?;
+        let desired = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE as i32),
+            channels: Some(2),
+            samples: Some(1024),
+        };
+        let buffer: RingBuffer = Default::default();
+        let device = audio_subsystem.open_playback(None, &desired, |spec| Callback {
+            buffer: buffer.clone(),
+            channels: spec.channels.max(1) as usize,
+            last: [0, 0],
+        })?;
+        let rate = device.spec().freq as u32;
+        device.resume();
+        Ok(SDLSink {
+            _device: device,
+            buffer,
+            rate,
+        })
+    }
+
+    //at most a quarter of a second is kept so latency doesn't build up when the
+    // emulator runs faster than real time
+    fn capacity(&self) -> usize {
+        (self.rate / 4) as usize
+    }
+}
+
+impl Sink for SDLSink {
+    fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    fn queue(&mut self, samples: &[[i16; 2]]) {
+        let capacity = self.capacity();
+        let mut buffer = self.buffer.lock().unwrap();
+        buffer.extend(samples);
+        while buffer.len() > capacity {
+            buffer.pop_front();
+        }
+    }
+
+    fn buffered(&self) -> usize {
+        self.buffer.lock().unwrap().len()
+    }
+}
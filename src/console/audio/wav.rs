@@ -0,0 +1,79 @@
+use super::{Sink, SAMPLE_RATE};
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+
+//records the audio to a 16-bit stereo WAV file
+pub struct WavSink {
+    writer: BufWriter<File>,
+    samples: u32,
+}
+
+impl WavSink {
+    const HEADER_LEN: u32 = 44;
+    const BYTES_PER_SAMPLE: u32 = 4;
+
+    pub fn new(filename: &str) -> io::Result<Self> {
+        let mut sink = WavSink {
+            writer: BufWriter::new(File::create(filename)?),
+            samples: 0,
+        };
+        sink.write_header()?;
+        Ok(sink)
+    }
+
+    //the sizes in the header are filled in again when the file is closed
+    fn write_header(&mut self) -> io::Result<()> {
+        let data_len = self.samples * WavSink::BYTES_PER_SAMPLE;
+        let w = &mut self.writer;
+        w.write_all(b"RIFF")?;
+        w.write_all(&(WavSink::HEADER_LEN - 8 + data_len).to_le_bytes())?;
+        w.write_all(b"WAVEfmt ")?;
+        //PCM with 2 channels and 16 bits per sample
+        w.write_all(&16u32.to_le_bytes())?;
+        w.write_all(&1u16.to_le_bytes())?;
+        w.write_all(&2u16.to_le_bytes())?;
+        w.write_all(&SAMPLE_RATE.to_le_bytes())?;
+        w.write_all(&(SAMPLE_RATE * WavSink::BYTES_PER_SAMPLE).to_le_bytes())?;
+        w.write_all(&(WavSink::BYTES_PER_SAMPLE as u16).to_le_bytes())?;
+        w.write_all(&16u16.to_le_bytes())?;
+        w.write_all(b"data")?;
+        w.write_all(&data_len.to_le_bytes())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.write_header()?;
+        self.writer.flush()
+    }
+}
+
+impl Sink for WavSink {
+    fn rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn queue(&mut self, samples: &[[i16; 2]]) {
+        for sample in samples {
+            let bytes = [sample[0].to_le_bytes(), sample[1].to_le_bytes()].concat();
+            if let Err(e) = self.writer.write_all(&bytes) {
+                println!("Unable to write audio: {}", e);
+                return
+            }
+            self.samples += 1;
+        }
+    }
+
+    //samples are written as soon as they're queued
+    fn buffered(&self) -> usize {
+        0
+    }
+}
+
+impl Drop for WavSink {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            println!("Unable to finish writing audio: {}", e);
+        }
+    }
+}
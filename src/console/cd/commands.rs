@@ -0,0 +1,295 @@
+use super::disc::TrackMode;
+use super::{CDEvent, Disc, DriveState, SecondResponse, CD};
+use crate::console::scheduler::{Event, Scheduler};
+
+fn to_bcd(value: u32) -> u8 {
+    (((value / 10) << 4) | (value % 10)) as u8
+}
+
+fn from_bcd(value: u8) -> u32 {
+    ((value >> 4) * 10 + (value & 0xf)) as u32
+}
+
+//converts a sector number to minutes, seconds and sectors in BCD
+fn to_msf(sector: u32) -> [u8; 3] {
+    [
+        to_bcd(sector / (60 * 75)),
+        to_bcd((sector / 75) % 60),
+        to_bcd(sector % 75),
+    ]
+}
+
+impl CD {
+    //the date and version of the CD controller's firmware
+    const VERSION: [u8; 4] = [0x94, 0x09, 0x19, 0xc0];
+
+    fn parameter(&mut self) -> u8 {
+        self.parameter_buffer.pop_front().unwrap_or(0)
+    }
+
+    fn error(&mut self, code: u8) {
+        let stat = self.stat() | 1;
+        self.interrupt(5, vec![stat, code]);
+    }
+
+    fn schedule_second_response(
+        &mut self, response: SecondResponse, delay: u64, scheduler: &mut Scheduler,
+    ) {
+        self.second_response = Some(response);
+        scheduler.schedule(Event::CD(CDEvent::SecondResponse), delay);
+    }
+
+    //moves to the Setloc position if there is one and starts reading or playing
+    // from there
+    fn start_reading(&mut self, state: DriveState, scheduler: &mut Scheduler) {
+        let seek_time = match self.seek_target.take() {
+            Some(target) => {
+                let time = self.seek_time(target);
+                self.position = target;
+                time
+            },
+            None => 0,
+        };
+        self.state = state;
+        self.motor_on = true;
+        scheduler.schedule(Event::CD(CDEvent::Sector), seek_time + self.read_period());
+    }
+
+    fn stop_reading(&mut self, scheduler: &mut Scheduler) {
+        self.state = DriveState::Idle;
+        scheduler.cancel(Event::CD(CDEvent::Sector));
+    }
+
+    pub(super) fn exec_command(&mut self, command: u8, scheduler: &mut Scheduler) {
+        let stat = self.stat();
+        match command {
+            //GetStat
+            0x01 => self.interrupt(3, vec![stat]),
+            //Setloc
+            0x02 => {
+                let minutes = from_bcd(self.parameter());
+                let seconds = from_bcd(self.parameter());
+                let sectors = from_bcd(self.parameter());
+                self.seek_target = Some((minutes * 60 + seconds) * 75 + sectors);
+                self.interrupt(3, vec![stat]);
+            },
+            //Play
+            0x03 => {
+                //an optional parameter picks the track to play from
+                let track = from_bcd(self.parameter());
+                let start = self.disc.as_ref().and_then(|disc| {
+                    disc.tracks()
+                        .iter()
+                        .find(|t| track != 0 && t.number() as u32 == track)
+                        .map(|t| t.start())
+                });
+                if let Some(start) = start {
+                    self.seek_target = Some(start + Disc::LEAD_IN);
+                }
+                self.interrupt(3, vec![stat]);
+                self.start_reading(DriveState::Playing, scheduler);
+            },
+            //ReadN and ReadS
+            0x06 | 0x1b => {
+                self.interrupt(3, vec![stat]);
+                self.start_reading(DriveState::Reading, scheduler);
+            },
+            //Stop
+            0x08 => {
+                self.interrupt(3, vec![stat]);
+                self.stop_reading(scheduler);
+                //the motor takes a while to spin down
+                self.schedule_second_response(
+                    SecondResponse::Stop,
+                    CD::CYCLES_PER_SECOND / 10,
+                    scheduler,
+                );
+            },
+            //Pause
+            0x09 => {
+                //pausing takes about as long as reading a sector
+                let delay = match self.state {
+                    DriveState::Idle => 7_000,
+                    _ => self.read_period(),
+                };
+                self.interrupt(3, vec![stat]);
+                self.stop_reading(scheduler);
+                self.schedule_second_response(SecondResponse::Pause, delay, scheduler);
+            },
+            //Init
+            0x0a => {
+                self.mode = 0x20;
+                self.motor_on = true;
+                self.stop_reading(scheduler);
+                self.interrupt(3, vec![self.stat()]);
+                self.schedule_second_response(SecondResponse::Init, 20_000, scheduler);
+            },
+            //Mute
+            0x0b => {
+                self.muted = true;
+                self.interrupt(3, vec![stat]);
+            },
+            //Demute
+            0x0c => {
+                self.muted = false;
+                self.interrupt(3, vec![stat]);
+            },
+            //Setfilter
+            0x0d => {
+                self.filter_file = self.parameter();
+                self.filter_channel = self.parameter();
+                self.xa_decoder.reset();
+                self.interrupt(3, vec![stat]);
+            },
+            //Setmode
+            0x0e => {
+                self.mode = self.parameter() as u32;
+                self.interrupt(3, vec![stat]);
+            },
+            //GetlocL
+            0x10 => {
+                let header = self.sector_header();
+                self.interrupt(3, header);
+            },
+            //GetlocP
+            0x11 => {
+                let lba = self.position.saturating_sub(Disc::LEAD_IN);
+                let (number, index, relative) = self.location(lba);
+                let mut response = vec![number, index];
+                response.extend_from_slice(&to_msf(relative));
+                response.extend_from_slice(&to_msf(self.position));
+                self.interrupt(3, response);
+            },
+            //GetTN
+            0x13 => {
+                let (first, last) = self.disc.as_ref().map_or((1, 1), |disc| {
+                    let numbers = disc.tracks().iter().map(|track| track.number());
+                    (numbers.clone().min().unwrap_or(1), numbers.max().unwrap_or(1))
+                });
+                self.interrupt(3, vec![stat, to_bcd(first as u32), to_bcd(last as u32)]);
+            },
+            //GetTD
+            0x14 => {
+                let track = from_bcd(self.parameter());
+                let start = self.disc.as_ref().and_then(|disc| match track {
+                    //track 0 is the end of the disc
+                    0 => Some(disc.end()),
+                    _ => disc
+                        .tracks()
+                        .iter()
+                        .find(|t| t.number() as u32 == track)
+                        .map(|t| t.start()),
+                });
+                match start {
+                    Some(start) => {
+                        let msf = to_msf(start + Disc::LEAD_IN);
+                        self.interrupt(3, vec![stat, msf[0], msf[1]]);
+                    },
+                    None => self.error(0x10),
+                }
+            },
+            //SeekL and SeekP
+            0x15 | 0x16 => {
+                self.stop_reading(scheduler);
+                let target = self.seek_target.take().unwrap_or(self.position);
+                let delay = self.seek_time(target);
+                self.position = target;
+                self.state = DriveState::Seeking;
+                self.interrupt(3, vec![self.stat()]);
+                self.schedule_second_response(SecondResponse::Seek, delay, scheduler);
+            },
+            //Test
+            0x19 => match self.parameter() {
+                0x20 => self.interrupt(3, CD::VERSION.to_vec()),
+                sub_function => {
+                    println!("CD test sub function {:#x} is not implemented", sub_function);
+                    self.error(0x10);
+                },
+            },
+            //GetID
+            0x1a => {
+                self.interrupt(3, vec![stat]);
+                self.schedule_second_response(SecondResponse::GetID, 20_000, scheduler);
+            },
+            //ReadTOC
+            0x1e => {
+                self.interrupt(3, vec![stat]);
+                self.schedule_second_response(
+                    SecondResponse::ReadTOC,
+                    CD::CYCLES_PER_SECOND / 2,
+                    scheduler,
+                );
+            },
+            _ => {
+                println!("CD command {:#x} is not implemented", command);
+                self.error(0x40);
+            },
+        }
+        self.parameter_buffer.clear();
+    }
+
+    //the track number in BCD, the index and the time relative to the start of the
+    // track
+    fn location(&self, lba: u32) -> (u8, u8, u32) {
+        let track = self.disc.as_ref().and_then(|disc| disc.track_at(lba));
+        //the relative position counts down to 0 during the pregap
+        let (number, index, relative) = match track {
+            Some(track) if lba < track.start() => (track.number(), 0, track.start() - lba),
+            Some(track) => (track.number(), 1, lba - track.start()),
+            None => (1, 1, lba),
+        };
+        (to_bcd(number as u32), index, relative)
+    }
+
+    //the INT1 that report mode sends while playing audio
+    //reports alternate between the absolute and relative times where relative
+    // times have bit 7 of the seconds set
+    pub(super) fn report_response(&self, lba: u32, peak: u16) -> Vec<u8> {
+        let (number, index, relative) = self.location(lba);
+        let msf = if lba % 32 < 16 {
+            to_msf(lba + Disc::LEAD_IN)
+        } else {
+            let mut msf = to_msf(relative);
+            msf[1] |= 0x80;
+            msf
+        };
+        let [peak_low, peak_high] = peak.to_le_bytes();
+        vec![
+            self.stat(),
+            number,
+            index,
+            msf[0],
+            msf[1],
+            msf[2],
+            peak_low,
+            peak_high,
+        ]
+    }
+
+    pub(super) fn finish_command(&mut self, response: SecondResponse) {
+        match response {
+            SecondResponse::Seek => {
+                self.state = DriveState::Idle;
+                self.interrupt(2, vec![self.stat()]);
+            },
+            SecondResponse::Stop => {
+                self.motor_on = false;
+                self.interrupt(2, vec![self.stat()]);
+            },
+            SecondResponse::Pause | SecondResponse::Init | SecondResponse::ReadTOC => {
+                self.interrupt(2, vec![self.stat()])
+            },
+            SecondResponse::GetID => match &self.disc {
+                Some(disc) if disc.tracks()[0].mode() == TrackMode::Audio => {
+                    self.interrupt(5, vec![self.stat(), 0x90, 0, 0, 0, 0, 0, 0])
+                },
+                //a licensed NTSC-U disc
+                Some(_) => self.interrupt(
+                    2,
+                    vec![self.stat(), 0x00, 0x20, 0x00, b'S', b'C', b'E', b'A'],
+                ),
+                None => self.interrupt(5, vec![0x08, 0x40, 0, 0, 0, 0, 0, 0]),
+            },
+        }
+    }
+}
@@ -0,0 +1,408 @@
+use std::fs::File;
+use std::io;
+use std::io::{Error, ErrorKind, Read};
+use std::path::Path;
+
+//the kind of data stored in each sector of a track
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TrackMode {
+    Audio,
+    Mode1,
+    Mode2,
+}
+
+#[derive(Debug)]
+pub struct Track {
+    number: u8,
+    mode: TrackMode,
+    //the number of bytes per sector in the image file
+    sector_size: usize,
+    file: usize,
+    //the byte offset of the first sector of this track in its file
+    file_offset: usize,
+    //the first sector of the pregap including any PREGAP that isn't in the file
+    pregap_start: u32,
+    //the first sector stored in the file, either INDEX 00 or INDEX 01
+    first: u32,
+    //INDEX 01
+    start: u32,
+    //the number of sectors stored in the file
+    length: u32,
+}
+
+impl Track {
+    pub fn number(&self) -> u8 {
+        self.number
+    }
+
+    pub fn mode(&self) -> TrackMode {
+        self.mode
+    }
+
+    pub fn start(&self) -> u32 {
+        self.start
+    }
+
+    pub fn end(&self) -> u32 {
+        self.first + self.length
+    }
+}
+
+//a disc image made up of one or more tracks spread across one or more files
+//sectors are addressed by LBA where LBA 0 is at 00:02:00 after the lead-in
+pub struct Disc {
+    files: Vec<Box<[u8]>>,
+    tracks: Vec<Track>,
+}
+
+impl Disc {
+    pub const SECTOR_SIZE: usize = 2352;
+    //the first 2 seconds of the disc are the lead-in
+    pub const LEAD_IN: u32 = 150;
+    const SYNC: [u8; 12] = [
+        0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00,
+    ];
+
+    pub fn is_image(filename: &str) -> bool {
+        let extension = Path::new(filename)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_lowercase());
+        matches!(
+            extension.as_deref(),
+            Some("cue") | Some("bin") | Some("img") | Some("iso")
+        )
+    }
+
+    pub fn open(filename: &str) -> io::Result<Self> {
+        let path = Path::new(filename);
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_lowercase());
+        match extension.as_deref() {
+            Some("cue") => Disc::from_cue(path),
+            //ISO images only contain the 2048 bytes of user data in each sector
+            Some("iso") => Disc::single_track(path, TrackMode::Mode1, 2048),
+            _ => Disc::single_track(path, TrackMode::Mode2, Disc::SECTOR_SIZE),
+        }
+    }
+
+    fn load_file(path: &Path) -> io::Result<Box<[u8]>> {
+        let mut buffer = Vec::new();
+        File::open(path)?.read_to_end(&mut buffer)?;
+        Ok(buffer.into_boxed_slice())
+    }
+
+    fn single_track(path: &Path, mode: TrackMode, sector_size: usize) -> io::Result<Self> {
+        let file = Disc::load_file(path)?;
+        let length = (file.len() / sector_size) as u32;
+        Ok(Disc {
+            files: vec![file],
+            tracks: vec![Track {
+                number: 1,
+                mode,
+                sector_size,
+                file: 0,
+                file_offset: 0,
+                pregap_start: 0,
+                first: 0,
+                start: 0,
+                length,
+            }],
+        })
+    }
+
+    fn from_cue(path: &Path) -> io::Result<Self> {
+        let mut cue = String::new();
+        File::open(path)?.read_to_string(&mut cue)?;
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        Disc::parse_cue(&cue, |name| Disc::load_file(&directory.join(name)))
+    }
+
+    //the files named in the sheet are loaded with load_file
+    fn parse_cue(
+        cue: &str, mut load_file: impl FnMut(&str) -> io::Result<Box<[u8]>>,
+    ) -> io::Result<Self> {
+        let invalid = |msg: &str| Error::new(ErrorKind::InvalidData, msg.to_string());
+        let mut files = Vec::new();
+        //the tracks in each file are laid out before the sizes of the sectors are known
+        let mut tracks: Vec<Track> = Vec::new();
+        //INDEX 00 and INDEX 01 of each track relative to the start of its file
+        let mut indices: Vec<(Option<u32>, Option<u32>)> = Vec::new();
+        let mut pregaps: Vec<u32> = Vec::new();
+        for line in cue.lines() {
+            let line = line.trim();
+            let mut words = line.split_whitespace();
+            match words.next().map(|word| word.to_uppercase()).as_deref() {
+                Some("FILE") => {
+                    //file names may contain spaces so they're quoted
+                    let name = match (line.find('"'), line.rfind('"')) {
+                        (Some(start), Some(end)) if start < end => &line[start + 1..end],
+                        _ => words.next().ok_or_else(|| invalid("missing FILE name"))?,
+                    };
+                    files.push(load_file(name)?);
+                },
+                Some("TRACK") => {
+                    let number = words
+                        .next()
+                        .and_then(|number| number.parse::<u8>().ok())
+                        .ok_or_else(|| invalid("invalid TRACK number"))?;
+                    let mode = words.next().map(|mode| mode.to_uppercase());
+                    let (mode, sector_size) = match mode.as_deref() {
+                        Some("AUDIO") => (TrackMode::Audio, Disc::SECTOR_SIZE),
+                        Some("MODE1/2048") => (TrackMode::Mode1, 2048),
+                        Some("MODE1/2352") => (TrackMode::Mode1, Disc::SECTOR_SIZE),
+                        Some("MODE2/2336") => (TrackMode::Mode2, 2336),
+                        Some("MODE2/2352") => (TrackMode::Mode2, Disc::SECTOR_SIZE),
+                        _ => return Err(invalid("unsupported TRACK mode")),
+                    };
+                    if files.is_empty() {
+                        return Err(invalid("TRACK before FILE"))
+                    }
+                    tracks.push(Track {
+                        number,
+                        mode,
+                        sector_size,
+                        file: files.len() - 1,
+                        file_offset: 0,
+                        pregap_start: 0,
+                        first: 0,
+                        start: 0,
+                        length: 0,
+                    });
+                    indices.push((None, None));
+                    pregaps.push(0);
+                },
+                Some("INDEX") => {
+                    let index = words.next().and_then(|index| index.parse::<u32>().ok());
+                    let time = words.next().and_then(Disc::parse_msf);
+                    let entry = indices
+                        .last_mut()
+                        .ok_or_else(|| invalid("INDEX before TRACK"))?;
+                    match (index, time) {
+                        (Some(0), Some(time)) => entry.0 = Some(time),
+                        (Some(1), Some(time)) => entry.1 = Some(time),
+                        (Some(_), Some(_)) => {},
+                        _ => return Err(invalid("invalid INDEX")),
+                    }
+                },
+                //pregaps that aren't stored in the file
+                Some("PREGAP") => {
+                    let time = words
+                        .next()
+                        .and_then(Disc::parse_msf)
+                        .ok_or_else(|| invalid("invalid PREGAP"))?;
+                    *pregaps
+                        .last_mut()
+                        .ok_or_else(|| invalid("PREGAP before TRACK"))? = time;
+                },
+                _ => {},
+            }
+        }
+        if tracks.is_empty() {
+            return Err(invalid("CUE sheet has no tracks"))
+        }
+        //lay the tracks out on the disc one after another
+        let mut next_sector = 0;
+        for i in 0..tracks.len() {
+            let index01 = indices[i].1.ok_or_else(|| invalid("TRACK without INDEX 01"))?;
+            let index00 = indices[i].0.unwrap_or(index01);
+            if index00 > index01 {
+                return Err(invalid("INDEX 00 after INDEX 01"))
+            }
+            let same_file_as_previous = i > 0 && tracks[i - 1].file == tracks[i].file;
+            let file_offset = if same_file_as_previous {
+                let previous = &tracks[i - 1];
+                let previous_index = indices[i - 1].0.or(indices[i - 1].1).unwrap();
+                if index00 < previous_index {
+                    return Err(invalid("INDEX goes backwards between tracks"))
+                }
+                previous.file_offset + (index00 - previous_index) as usize * previous.sector_size
+            } else {
+                0
+            };
+            let same_file_as_next = i + 1 < tracks.len() && tracks[i + 1].file == tracks[i].file;
+            let length = if same_file_as_next {
+                let next_index = indices[i + 1].0.or(indices[i + 1].1).unwrap_or(index00);
+                next_index.saturating_sub(index00)
+            } else {
+                let file_len = files[tracks[i].file].len();
+                (file_len.saturating_sub(file_offset) / tracks[i].sector_size) as u32
+            };
+            let track = &mut tracks[i];
+            track.file_offset = file_offset;
+            track.pregap_start = next_sector;
+            track.first = next_sector + pregaps[i];
+            track.start = track.first + (index01 - index00);
+            track.length = length;
+            next_sector = track.first + length;
+        }
+        Ok(Disc { files, tracks })
+    }
+
+    //parses an mm:ss:ff timestamp into a number of sectors
+    fn parse_msf(msf: &str) -> Option<u32> {
+        let parts = msf
+            .split(':')
+            .map(|part| part.parse::<u32>().ok())
+            .collect::<Option<Vec<u32>>>()?;
+        match parts.as_slice() {
+            [minutes, seconds, frames] => Some((minutes * 60 + seconds) * 75 + frames),
+            _ => None,
+        }
+    }
+
+    pub fn lba_to_msf(lba: u32) -> (u32, u32, u32) {
+        let sector = lba + Disc::LEAD_IN;
+        (sector / (60 * 75), (sector / 75) % 60, sector % 75)
+    }
+
+    pub fn tracks(&self) -> &Vec<Track> {
+        &self.tracks
+    }
+
+    //the first sector after the last track
+    pub fn end(&self) -> u32 {
+        self.tracks.last().map_or(0, |track| track.end())
+    }
+
+    pub fn track_at(&self, lba: u32) -> Option<&Track> {
+        self.tracks
+            .iter()
+            .find(|track| track.pregap_start <= lba && lba < track.end())
+    }
+
+    //returns a full 2352-byte sector, filling in the sync pattern and header for
+    // images that don't store them
+    pub fn read_raw(&self, lba: u32) -> Option<Vec<u8>> {
+        let track = self.track_at(lba)?;
+        let mut sector = vec![0; Disc::SECTOR_SIZE];
+        //2048-byte sectors only have user data which goes after the header
+        let dest = match track.sector_size {
+            2048 => 16,
+            size => Disc::SECTOR_SIZE - size,
+        };
+        //pregaps that aren't in the file read as silence or empty data
+        if lba >= track.first {
+            let offset = track.file_offset + (lba - track.first) as usize * track.sector_size;
+            let data = self.files[track.file].get(offset..offset + track.sector_size)?;
+            sector[dest..dest + track.sector_size].copy_from_slice(data);
+        }
+        if track.mode != TrackMode::Audio && dest != 0 {
+            let (minutes, seconds, frames) = Disc::lba_to_msf(lba);
+            let to_bcd = |value: u32| (((value / 10) << 4) | (value % 10)) as u8;
+            sector[0..12].copy_from_slice(&Disc::SYNC);
+            sector[12] = to_bcd(minutes);
+            sector[13] = to_bcd(seconds);
+            sector[14] = to_bcd(frames);
+            sector[15] = match track.mode {
+                TrackMode::Mode1 => 1,
+                _ => 2,
+            };
+        }
+        Some(sector)
+    }
+
+    //the 2048 bytes of user data in a sector
+    pub fn read_data(&self, lba: u32) -> Option<Vec<u8>> {
+        let sector = self.read_raw(lba)?;
+        let offset = Disc::data_offset(&sector);
+        Some(sector[offset..offset + 2048].to_vec())
+    }
+
+    //user data starts after the header in mode 1 and after the subheader in mode 2
+    pub fn data_offset(sector: &[u8]) -> usize {
+        match sector[15] {
+            1 => 16,
+            _ => 24,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECTOR: usize = Disc::SECTOR_SIZE;
+
+    //each file is filled with its own index so reads can be traced back to a file
+    fn parse(cue: &str, sectors: &[usize]) -> io::Result<Disc> {
+        let mut n = 0;
+        Disc::parse_cue(cue, |_| {
+            let file = vec![n as u8; sectors[n] * SECTOR].into_boxed_slice();
+            n += 1;
+            Ok(file)
+        })
+    }
+
+    #[test]
+    fn msf_timestamps() {
+        assert_eq!(Disc::parse_msf("00:00:00"), Some(0));
+        assert_eq!(Disc::parse_msf("00:02:00"), Some(150));
+        assert_eq!(Disc::parse_msf("01:02:03"), Some((60 + 2) * 75 + 3));
+        assert_eq!(Disc::parse_msf("01:02"), None);
+        assert_eq!(Disc::parse_msf("aa:02:03"), None);
+        assert_eq!(Disc::lba_to_msf(0), (0, 2, 0));
+    }
+
+    #[test]
+    fn multiple_tracks_and_files() {
+        let cue = "FILE \"game (track 1).bin\" BINARY
+  TRACK 01 MODE2/2352
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    INDEX 00 00:01:00
+    INDEX 01 00:03:00
+FILE \"game (track 3).bin\" BINARY
+  TRACK 03 AUDIO
+    PREGAP 00:02:00
+    INDEX 01 00:00:00
+";
+        let disc = parse(cue, &[1000, 500]).unwrap();
+        let tracks = disc.tracks();
+        assert_eq!(tracks.len(), 3);
+        assert_eq!(tracks[0].mode(), TrackMode::Mode2);
+        assert_eq!((tracks[0].start(), tracks[0].end()), (0, 75));
+        //track 2 starts with 2 seconds of pregap stored in the file
+        assert_eq!(tracks[1].mode(), TrackMode::Audio);
+        assert_eq!((tracks[1].start(), tracks[1].end()), (225, 1000));
+        assert_eq!(tracks[1].file_offset, 75 * SECTOR);
+        //track 3 has a pregap that isn't stored in its file
+        assert_eq!(tracks[2].pregap_start, 1000);
+        assert_eq!((tracks[2].start(), tracks[2].end()), (1150, 1650));
+        assert_eq!(disc.track_at(1100).map(|track| track.number()), Some(3));
+        assert_eq!(disc.read_raw(1100).unwrap()[0], 0);
+        assert_eq!(disc.read_raw(1150).unwrap()[0], 1);
+        assert_eq!(disc.read_raw(1649).unwrap()[0], 1);
+        assert!(disc.read_raw(1650).is_none());
+    }
+
+    #[test]
+    fn data_sectors_have_headers() {
+        let cue = "FILE \"game.bin\" BINARY\nTRACK 01 MODE1/2048\nINDEX 01 00:00:00\n";
+        let disc = Disc::parse_cue(cue, |_| Ok(vec![0xaa; 10 * 2048].into_boxed_slice())).unwrap();
+        let sector = disc.read_raw(5).unwrap();
+        assert_eq!(sector[0..12], Disc::SYNC);
+        assert_eq!(sector[12..16], [0x00, 0x02, 0x05, 0x01]);
+        assert_eq!(disc.read_data(5).unwrap(), vec![0xaa; 2048]);
+    }
+
+    #[test]
+    fn indices_out_of_order() {
+        let cue = "FILE \"game.bin\" BINARY\nTRACK 01 AUDIO\nINDEX 01 00:00:00\n\
+                   INDEX 00 00:01:00\n";
+        assert!(parse(cue, &[100]).is_err());
+        let cue = "FILE \"game.bin\" BINARY\nTRACK 01 AUDIO\nINDEX 01 00:01:00\n\
+                   TRACK 02 AUDIO\nINDEX 01 00:00:00\n";
+        assert!(parse(cue, &[100]).is_err());
+    }
+
+    #[test]
+    fn invalid_sheets() {
+        assert!(parse("", &[]).is_err());
+        assert!(parse("TRACK 01 AUDIO\nINDEX 01 00:00:00\n", &[]).is_err());
+        assert!(parse("FILE \"a.bin\" BINARY\nTRACK 01 MODE3/2352\n", &[1]).is_err());
+        assert!(parse("FILE \"a.bin\" BINARY\nTRACK 01 AUDIO\n", &[1]).is_err());
+    }
+}
@@ -0,0 +1,166 @@
+use super::Disc;
+use std::convert::TryInto;
+
+//a file or directory in an ISO9660 filesystem
+#[derive(Clone, Debug)]
+pub struct DirectoryEntry {
+    name: String,
+    lba: u32,
+    size: u32,
+    is_directory: bool,
+}
+
+impl DirectoryEntry {
+    //directory records are little-endian followed by the same values in big-endian
+    fn parse(record: &[u8]) -> Option<Self> {
+        let name_len = *record.get(32)? as usize;
+        let name = record.get(33..33 + name_len)?;
+        let name = match name {
+            //the current and parent directories
+            [0] => ".".to_string(),
+            [1] => "..".to_string(),
+            _ => {
+                let name = String::from_utf8_lossy(name);
+                //the version number after the ';' is ignored
+                name.split(';').next().unwrap_or("").to_string()
+            },
+        };
+        Some(DirectoryEntry {
+            name,
+            lba: u32::from_le_bytes(record.get(2..6)?.try_into().ok()?),
+            size: u32::from_le_bytes(record.get(10..14)?.try_into().ok()?),
+            is_directory: record.get(25)? & 2 != 0,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_directory(&self) -> bool {
+        self.is_directory
+    }
+}
+
+//the boot settings that the BIOS reads from the root directory of the disc
+#[derive(Debug)]
+pub struct SystemConfig {
+    pub boot: String,
+    //the number of thread control blocks and event control blocks in the kernel
+    pub tcb: u32,
+    pub event: u32,
+    pub stack: u32,
+}
+
+impl SystemConfig {
+    //the BIOS sets up the kernel with these before it reads SYSTEM.CNF
+    const DEFAULT_TCB: u32 = 4;
+    const DEFAULT_EVENT: u32 = 0x10;
+
+    //each line looks like `KEY = value` and numbers are in hex
+    fn parse(contents: &str) -> Option<Self> {
+        let mut config = SystemConfig {
+            boot: String::new(),
+            tcb: SystemConfig::DEFAULT_TCB,
+            event: SystemConfig::DEFAULT_EVENT,
+            stack: 0x801f_fff0,
+        };
+        for line in contents.lines() {
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next()?.trim().to_uppercase();
+            let value = match parts.next() {
+                Some(value) => value.trim(),
+                None => continue,
+            };
+            let hex = || u32::from_str_radix(value, 16).ok();
+            match key.as_str() {
+                "BOOT" => config.boot = value.to_string(),
+                "TCB" => config.tcb = hex().unwrap_or(config.tcb),
+                "EVENT" => config.event = hex().unwrap_or(config.event),
+                "STACK" => config.stack = hex().unwrap_or(config.stack),
+                _ => {},
+            }
+        }
+        if config.boot.is_empty() {
+            None
+        } else {
+            Some(config)
+        }
+    }
+
+    //whether the kernel's tables are the size the BIOS already allocated
+    pub fn default_kernel(&self) -> bool {
+        self.tcb == SystemConfig::DEFAULT_TCB && self.event == SystemConfig::DEFAULT_EVENT
+    }
+}
+
+impl Disc {
+    //the primary volume descriptor comes after 16 sectors of system area
+    const VOLUME_DESCRIPTOR: u32 = 16;
+    const ROOT_RECORD: usize = 156;
+
+    //reads the sectors of a file or directory
+    fn read_extent(&self, entry: &DirectoryEntry) -> Option<Vec<u8>> {
+        let sectors = (entry.size as usize + 2047) / 2048;
+        let mut data = Vec::with_capacity(sectors * 2048);
+        for i in 0..sectors as u32 {
+            data.extend(self.read_data(entry.lba + i)?);
+        }
+        data.truncate(entry.size as usize);
+        Some(data)
+    }
+
+    pub fn root_directory(&self) -> Option<DirectoryEntry> {
+        let descriptor = self.read_data(Disc::VOLUME_DESCRIPTOR)?;
+        if descriptor[0] != 1 || &descriptor[1..6] != b"CD001" {
+            return None
+        }
+        DirectoryEntry::parse(&descriptor[Disc::ROOT_RECORD..])
+    }
+
+    pub fn list_directory(&self, directory: &DirectoryEntry) -> Option<Vec<DirectoryEntry>> {
+        let data = self.read_extent(directory)?;
+        let mut entries = Vec::new();
+        //records never cross sector boundaries so the rest of a sector is padded with
+        // zeros
+        for sector in data.chunks(2048) {
+            let mut offset = 0;
+            while offset < sector.len() && sector[offset] != 0 {
+                let len = sector[offset] as usize;
+                entries.push(DirectoryEntry::parse(sector.get(offset..offset + len)?)?);
+                offset += len;
+            }
+        }
+        Some(entries)
+    }
+
+    //paths may start with a device like `cdrom:` and use either kind of slash
+    pub fn find_file(&self, path: &str) -> Option<DirectoryEntry> {
+        let path = path.splitn(2, ':').last().unwrap_or(path);
+        let mut entry = self.root_directory()?;
+        for name in path.split(['\\', '/']) {
+            let name = name.split(';').next().unwrap_or("");
+            if name.is_empty() {
+                continue
+            }
+            entry = self
+                .list_directory(&entry)?
+                .into_iter()
+                .find(|e| e.name().eq_ignore_ascii_case(name))?;
+        }
+        Some(entry)
+    }
+
+    pub fn read_file(&self, path: &str) -> Option<Vec<u8>> {
+        let entry = self.find_file(path)?;
+        if entry.is_directory() {
+            return None
+        }
+        self.read_extent(&entry)
+    }
+
+    pub fn system_config(&self) -> Option<SystemConfig> {
+        let contents = self.read_file("SYSTEM.CNF")?;
+        SystemConfig::parse(&String::from_utf8_lossy(&contents))
+    }
+}
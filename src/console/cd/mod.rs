@@ -0,0 +1,582 @@
+use super::dma::DMAChannel;
+use super::scheduler::{Event, Scheduler};
+use crate::register::BitTwiddle;
+pub use disc::Disc;
+use disc::TrackMode;
+use std::collections::VecDeque;
+
+mod commands;
+mod disc;
+mod iso9660;
+mod xa;
+
+use xa::XADecoder;
+
+//things the controller does some time after a command is sent
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CDEvent {
+    Command,
+    SecondResponse,
+    Sector,
+}
+
+//responses that come after the first INT3
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SecondResponse {
+    Seek,
+    Pause,
+    Stop,
+    Init,
+    GetID,
+    ReadTOC,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum DriveState {
+    Idle,
+    Seeking,
+    Reading,
+    Playing,
+}
+
+pub struct CD {
+    disc: Option<Disc>,
+    index: u32,
+    command: Option<u8>,
+    parameter_buffer: VecDeque<u8>,
+    response_buffer: VecDeque<u8>,
+    data_buffer: VecDeque<u8>,
+    interrupt_enable: u8,
+    interrupt_flag: u8,
+    //interrupts that are delivered once the current one is acknowledged along with
+    // the sector that goes with an INT1
+    queued_interrupts: VecDeque<(u8, Vec<u8>, Option<Vec<u8>>)>,
+    irq_line: bool,
+    second_response: Option<SecondResponse>,
+    state: DriveState,
+    motor_on: bool,
+    mode: u32,
+    //the position that Setloc sets and the next sector to be read
+    seek_target: Option<u32>,
+    position: u32,
+    //the sector from the last INT1 that was delivered waits here until the CPU asks
+    // for it
+    sector_buffer: Vec<u8>,
+    filter_file: u8,
+    filter_channel: u8,
+    muted: bool,
+    xa_decoder: XADecoder,
+    //44.1kHz stereo samples waiting to be mixed by the SPU
+    audio_buffer: VecDeque<[i16; 2]>,
+    //the volume from each CD channel to each SPU channel where 0x80 is 100%
+    //the order is left to left, left to right, right to right and right to left
+    volume: [u8; 4],
+    //volume changes only take effect once they're applied
+    pending_volume: [u8; 4],
+    adpcm_muted: bool,
+}
+
+impl CD {
+    const CYCLES_PER_SECOND: u64 = 33_868_800;
+    const ACK_DELAY: u64 = 25_000;
+    const INIT_ACK_DELAY: u64 = 80_000;
+    //enough for a few sectors of CD-DA or XA-ADPCM
+    const AUDIO_BUFFER_LEN: usize = 8192;
+
+    pub fn new(disc: Option<Disc>) -> Self {
+        CD {
+            disc,
+            index: 0,
+            command: None,
+            parameter_buffer: VecDeque::new(),
+            response_buffer: VecDeque::new(),
+            data_buffer: VecDeque::new(),
+            interrupt_enable: 0,
+            interrupt_flag: 0,
+            queued_interrupts: VecDeque::new(),
+            irq_line: false,
+            second_response: None,
+            state: DriveState::Idle,
+            motor_on: true,
+            mode: 0,
+            seek_target: None,
+            position: 0,
+            sector_buffer: Vec::new(),
+            filter_file: 0,
+            filter_channel: 0,
+            muted: false,
+            xa_decoder: Default::default(),
+            audio_buffer: VecDeque::new(),
+            volume: [0x80, 0, 0x80, 0],
+            pending_volume: [0x80, 0, 0x80, 0],
+            adpcm_muted: false,
+        }
+    }
+
+    //the status byte sent with most responses
+    fn stat(&self) -> u8 {
+        let mut stat = 0;
+        if self.motor_on {
+            stat |= 1 << 1;
+        }
+        match self.state {
+            DriveState::Reading => stat |= 1 << 5,
+            DriveState::Seeking => stat |= 1 << 6,
+            DriveState::Playing => stat |= 1 << 7,
+            DriveState::Idle => {},
+        }
+        stat
+    }
+
+    fn double_speed(&self) -> bool {
+        self.mode.nth_bit_bool(7)
+    }
+
+    //sectors are read at 75 per second or twice that in double speed mode
+    fn read_period(&self) -> u64 {
+        if self.double_speed() {
+            CD::CYCLES_PER_SECOND / 150
+        } else {
+            CD::CYCLES_PER_SECOND / 75
+        }
+    }
+
+    //long seeks take a fraction of a second
+    fn seek_time(&self, target: u32) -> u64 {
+        let distance = (target.max(self.position) - target.min(self.position)) as u64;
+        20_000 + distance * 10
+    }
+
+    //the controller counts sectors from the start of the lead-in
+    fn read_sector(&self, sector: u32) -> Option<Vec<u8>> {
+        let lba = sector.checked_sub(Disc::LEAD_IN)?;
+        self.disc.as_ref()?.read_raw(lba)
+    }
+
+    //the 4-byte header and the first copy of the 4-byte subheader of the last sector
+    // that was read
+    fn sector_header(&self) -> Vec<u8> {
+        match self.sector_buffer.get(12..20) {
+            Some(header) => header.to_vec(),
+            None => vec![0; 8],
+        }
+    }
+
+    pub fn read(&mut self, register: u32) -> u32 {
+        match register {
+            0 => self.status(),
+            1 => self
+                .response_buffer
+                .pop_front()
+                .map_or(0, |response| response as u32),
+            2 => self.data_buffer.pop_front().map_or(0, |data| data as u32),
+            3 => match self.index {
+                0 | 2 => (self.interrupt_enable | 0xe0) as u32,
+                _ => (self.interrupt_flag | 0xe0) as u32,
+            },
+            _ => unreachable!(""),
+        }
+    }
+
+    //the index register and the state of the FIFOs
+    fn status(&self) -> u32 {
+        let mut status = self.index;
+        if self.parameter_buffer.is_empty() {
+            status.set(3);
+        }
+        if self.parameter_buffer.len() < 16 {
+            status.set(4);
+        }
+        if !self.response_buffer.is_empty() {
+            status.set(5);
+        }
+        if !self.data_buffer.is_empty() {
+            status.set(6);
+        }
+        if self.command.is_some() {
+            status.set(7);
+        }
+        status
+    }
+
+    pub fn write(&mut self, register: u32, value: u32, scheduler: &mut Scheduler) {
+        match (register, self.index) {
+            (0, _) => self.index = value.lowest_bits(2),
+            (1, 0) => {
+                self.command = Some(value as u8);
+                let delay = match value {
+                    0x0a => CD::INIT_ACK_DELAY,
+                    _ => CD::ACK_DELAY,
+                };
+                scheduler.schedule(Event::CD(CDEvent::Command), delay);
+            },
+            (2, 0) if self.parameter_buffer.len() < 16 => {
+                self.parameter_buffer.push_back(value as u8)
+            },
+            (2, 1) => self.interrupt_enable = value.lowest_bits(5) as u8,
+            (3, 0) if !value.nth_bit_bool(7) => self.data_buffer.clear(),
+            //the CPU wants the data from the last sector that was read
+            (3, 0) if self.data_buffer.is_empty() => {
+                self.data_buffer.extend(self.sector_data())
+            },
+            (3, 1) => {
+                self.interrupt_flag &= !(value.lowest_bits(5) as u8);
+                if value.nth_bit_bool(6) {
+                    self.parameter_buffer.clear();
+                }
+                //the next interrupt is sent once the current one is acknowledged
+                if self.interrupt_flag == 0 {
+                    self.response_buffer.clear();
+                    if let Some((interrupt, response, sector)) =
+                        self.queued_interrupts.pop_front()
+                    {
+                        self.deliver(interrupt, response, sector);
+                    }
+                }
+            },
+            (2, 2) => self.pending_volume[0] = value as u8,
+            (3, 2) => self.pending_volume[1] = value as u8,
+            (1, 3) => self.pending_volume[2] = value as u8,
+            (2, 3) => self.pending_volume[3] = value as u8,
+            (3, 3) => {
+                self.adpcm_muted = value.nth_bit_bool(0);
+                if value.nth_bit_bool(5) {
+                    self.volume = self.pending_volume;
+                }
+            },
+            //the sound map registers aren't used
+            _ => {},
+        }
+    }
+
+    //mode bit 5 selects between the 0x800 bytes of user data or everything after
+    // the sync pattern
+    fn sector_data(&self) -> Vec<u8> {
+        if self.sector_buffer.len() < Disc::SECTOR_SIZE {
+            return Vec::new()
+        }
+        let range = if self.mode.nth_bit_bool(5) {
+            12..12 + 0x924
+        } else {
+            let offset = Disc::data_offset(&self.sector_buffer);
+            offset..offset + 0x800
+        };
+        self.sector_buffer[range].to_vec()
+    }
+
+    fn interrupt(&mut self, interrupt: u8, response: Vec<u8>) {
+        self.queue_interrupt(interrupt, response, None);
+    }
+
+    //the sector only replaces the one the CPU reads from once its INT1 is delivered
+    // so the data always matches the interrupt
+    fn sector_interrupt(&mut self, sector: Vec<u8>) {
+        self.queue_interrupt(1, vec![self.stat()], Some(sector));
+    }
+
+    //the controller holds at most one INT1 and one other response while the current
+    // interrupt is unacknowledged and a newer INT1 replaces the one that's waiting
+    fn queue_interrupt(&mut self, interrupt: u8, response: Vec<u8>, sector: Option<Vec<u8>>) {
+        if self.interrupt_flag == 0 {
+            self.deliver(interrupt, response, sector);
+            return
+        }
+        let waiting = self
+            .queued_interrupts
+            .iter_mut()
+            .find(|(queued, _, _)| (*queued == 1) == (interrupt == 1));
+        match waiting {
+            Some(queued) => *queued = (interrupt, response, sector),
+            None => self
+                .queued_interrupts
+                .push_back((interrupt, response, sector)),
+        }
+    }
+
+    fn deliver(&mut self, interrupt: u8, response: Vec<u8>, sector: Option<Vec<u8>>) {
+        self.interrupt_flag = interrupt;
+        self.response_buffer = response.into_iter().collect();
+        if let Some(sector) = sector {
+            self.sector_buffer = sector;
+        }
+    }
+
+    //returns true if the interrupt line to the CPU went high
+    pub fn take_interrupt(&mut self) -> bool {
+        let line = self.interrupt_flag & self.interrupt_enable & 0x1f != 0;
+        let rising = line && !self.irq_line;
+        self.irq_line = line;
+        rising
+    }
+
+    pub fn run_event(&mut self, event: CDEvent, scheduler: &mut Scheduler) {
+        match event {
+            CDEvent::Command => {
+                if let Some(command) = self.command.take() {
+                    self.exec_command(command, scheduler);
+                }
+            },
+            CDEvent::SecondResponse => {
+                if let Some(response) = self.second_response.take() {
+                    self.finish_command(response);
+                }
+            },
+            CDEvent::Sector => self.next_sector(scheduler),
+        }
+    }
+
+    fn next_sector(&mut self, scheduler: &mut Scheduler) {
+        let sector = self
+            .read_sector(self.position)
+            .unwrap_or_else(|| vec![0; Disc::SECTOR_SIZE]);
+        match self.state {
+            DriveState::Reading => {
+                self.position += 1;
+                if self.is_xa_audio(&sector) {
+                    //XA-ADPCM sectors go to the SPU instead of the CPU
+                    if self.xa_filter_matches(&sector) {
+                        let samples = self.xa_decoder.decode_sector(&sector);
+                        if !self.adpcm_muted {
+                            self.push_audio(&samples);
+                        }
+                    }
+                } else {
+                    self.sector_interrupt(sector);
+                }
+            },
+            DriveState::Playing => {
+                let lba = self.position.saturating_sub(Disc::LEAD_IN);
+                let track = self.disc.as_ref().and_then(|disc| disc.track_at(lba));
+                let track_end = track.map_or(0, |track| track.end());
+                //data tracks play as silence
+                let samples = match track.map(|track| track.mode()) {
+                    Some(TrackMode::Audio) => sector
+                        .chunks(4)
+                        .map(|sample| {
+                            [
+                                i16::from_le_bytes([sample[0], sample[1]]),
+                                i16::from_le_bytes([sample[2], sample[3]]),
+                            ]
+                        })
+                        .collect(),
+                    _ => vec![[0, 0]; Disc::SECTOR_SIZE / 4],
+                };
+                self.push_audio(&samples);
+                self.position += 1;
+                //autopause stops at the end of the track
+                if self.autopause() && lba + 1 >= track_end {
+                    self.state = DriveState::Idle;
+                    self.interrupt(4, vec![self.stat()]);
+                    return
+                }
+                //report mode sends the position and peak level every 10 sectors
+                if self.report() && lba % 10 == 0 {
+                    let peak = samples
+                        .iter()
+                        .flat_map(|sample| sample.iter())
+                        .map(|&sample| (sample as i32).unsigned_abs())
+                        .max()
+                        .unwrap_or(0)
+                        .min(0x7fff) as u16;
+                    let response = self.report_response(lba, peak);
+                    self.interrupt(1, response);
+                }
+            },
+            _ => return,
+        }
+        scheduler.schedule(Event::CD(CDEvent::Sector), self.read_period());
+    }
+
+    fn autopause(&self) -> bool {
+        self.mode.nth_bit_bool(1)
+    }
+
+    fn report(&self) -> bool {
+        self.mode.nth_bit_bool(2)
+    }
+
+    //mode 2 sectors with the audio bit set in the submode are only sent to the
+    // XA-ADPCM decoder if it's enabled
+    fn is_xa_audio(&self, sector: &[u8]) -> bool {
+        self.mode.nth_bit_bool(6) && sector[15] == 2 && sector[18] & (1 << 2) != 0
+    }
+
+    //Setfilter picks one file and channel out of the interleaved streams
+    fn xa_filter_matches(&self, sector: &[u8]) -> bool {
+        !self.mode.nth_bit_bool(3) ||
+            (sector[16] == self.filter_file && sector[17] == self.filter_channel)
+    }
+
+    //mixes the samples through the volume matrix
+    fn push_audio(&mut self, samples: &[[i16; 2]]) {
+        let [left_left, left_right, right_right, right_left] = self.volume.map(|v| v as i32);
+        for &[left, right] in samples {
+            let (left, right) = if self.muted {
+                (0, 0)
+            } else {
+                (left as i32, right as i32)
+            };
+            let mix = |from_left: i32, from_right: i32| {
+                ((left * from_left + right * from_right) >> 7).clamp(-0x8000, 0x7fff) as i16
+            };
+            self.audio_buffer
+                .push_back([mix(left_left, right_left), mix(left_right, right_right)]);
+        }
+        //drop the oldest samples if nothing is consuming them
+        while self.audio_buffer.len() > CD::AUDIO_BUFFER_LEN {
+            self.audio_buffer.pop_front();
+        }
+    }
+
+    //the next 44.1kHz sample for the SPU's CD input
+    pub fn audio_sample(&mut self) -> [i16; 2] {
+        self.audio_buffer.pop_front().unwrap_or([0, 0])
+    }
+}
+
+impl DMAChannel for CD {
+    fn send(&mut self, data: Vec<u32>) {
+        println!("CD can't receive {} words through a DMA", data.len());
+    }
+
+    //sector data goes out four bytes at a time
+    fn receive(&mut self, words: u32) -> Vec<u32> {
+        (0..words)
+            .map(|_| {
+                let mut bytes = [0; 4];
+                for byte in bytes.iter_mut() {
+                    *byte = self.data_buffer.pop_front().unwrap_or(0);
+                }
+                u32::from_le_bytes(bytes)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //handles the next CD event after moving the clock up to it
+    fn run_next_event(cd: &mut CD, scheduler: &mut Scheduler) {
+        scheduler.advance((scheduler.next_event_at() - scheduler.cycles()) as u32);
+        while let Some((_, Event::CD(event))) = scheduler.pop_due_event() {
+            cd.run_event(event, scheduler);
+        }
+    }
+
+    fn send_command(cd: &mut CD, command: u32, scheduler: &mut Scheduler) {
+        cd.write(0, 0, scheduler);
+        cd.write(1, command, scheduler);
+    }
+
+    fn interrupt_flag(cd: &mut CD, scheduler: &mut Scheduler) -> u32 {
+        cd.write(0, 1, scheduler);
+        cd.read(3) & 0x1f
+    }
+
+    fn acknowledge(cd: &mut CD, scheduler: &mut Scheduler) {
+        cd.write(0, 1, scheduler);
+        cd.write(3, 0x1f, scheduler);
+    }
+
+    //a mode 2 sector whose user data is filled with the given byte
+    fn sector(fill: u8) -> Vec<u8> {
+        let mut sector = vec![fill; Disc::SECTOR_SIZE];
+        sector[15] = 2;
+        sector
+    }
+
+    #[test]
+    fn second_response_waits_for_acknowledge() {
+        let mut cd = CD::new(None);
+        let mut scheduler: Scheduler = Default::default();
+        send_command(&mut cd, 0x0a, &mut scheduler);
+        //the busy bit stays set until the command runs
+        assert!(cd.read(0).nth_bit_bool(7));
+        run_next_event(&mut cd, &mut scheduler);
+        assert!(!cd.read(0).nth_bit_bool(7));
+        assert_eq!(interrupt_flag(&mut cd, &mut scheduler), 3);
+        //INT2 is ready but INT3 hasn't been acknowledged
+        run_next_event(&mut cd, &mut scheduler);
+        assert_eq!(interrupt_flag(&mut cd, &mut scheduler), 3);
+        acknowledge(&mut cd, &mut scheduler);
+        assert_eq!(interrupt_flag(&mut cd, &mut scheduler), 2);
+        acknowledge(&mut cd, &mut scheduler);
+        assert_eq!(interrupt_flag(&mut cd, &mut scheduler), 0);
+    }
+
+    #[test]
+    fn response_and_parameter_fifos() {
+        let mut cd = CD::new(None);
+        let mut scheduler: Scheduler = Default::default();
+        assert!(cd.read(0).nth_bit_bool(3));
+        cd.write(0, 0, &mut scheduler);
+        cd.write(2, 0x20, &mut scheduler);
+        assert!(!cd.read(0).nth_bit_bool(3));
+        //Test 0x20 returns the firmware version
+        send_command(&mut cd, 0x19, &mut scheduler);
+        run_next_event(&mut cd, &mut scheduler);
+        //parameters are used up by the command
+        assert!(cd.read(0).nth_bit_bool(3));
+        assert!(cd.read(0).nth_bit_bool(5));
+        let response: Vec<u32> = (0..4).map(|_| cd.read(1)).collect();
+        assert_eq!(response, vec![0x94, 0x09, 0x19, 0xc0]);
+        assert!(!cd.read(0).nth_bit_bool(5));
+        //bit 6 of the acknowledge clears the parameter FIFO
+        cd.write(0, 0, &mut scheduler);
+        cd.write(2, 1, &mut scheduler);
+        cd.write(0, 1, &mut scheduler);
+        cd.write(3, 0x5f, &mut scheduler);
+        assert!(cd.read(0).nth_bit_bool(3));
+        assert_eq!(interrupt_flag(&mut cd, &mut scheduler), 0);
+    }
+
+    #[test]
+    fn acknowledge_clears_the_response() {
+        let mut cd = CD::new(None);
+        let mut scheduler: Scheduler = Default::default();
+        send_command(&mut cd, 0x01, &mut scheduler);
+        run_next_event(&mut cd, &mut scheduler);
+        assert!(cd.read(0).nth_bit_bool(5));
+        acknowledge(&mut cd, &mut scheduler);
+        assert!(!cd.read(0).nth_bit_bool(5));
+    }
+
+    #[test]
+    fn only_one_int1_waits() {
+        let mut cd = CD::new(None);
+        let mut scheduler: Scheduler = Default::default();
+        cd.interrupt(3, vec![0]);
+        cd.sector_interrupt(sector(1));
+        cd.sector_interrupt(sector(2));
+        cd.interrupt(2, vec![0]);
+        cd.sector_interrupt(sector(3));
+        assert_eq!(cd.queued_interrupts.len(), 2);
+        acknowledge(&mut cd, &mut scheduler);
+        assert_eq!(interrupt_flag(&mut cd, &mut scheduler), 1);
+        acknowledge(&mut cd, &mut scheduler);
+        assert_eq!(interrupt_flag(&mut cd, &mut scheduler), 2);
+        acknowledge(&mut cd, &mut scheduler);
+        assert!(cd.queued_interrupts.is_empty());
+    }
+
+    #[test]
+    fn int1_data_matches_its_sector() {
+        let mut cd = CD::new(None);
+        let mut scheduler: Scheduler = Default::default();
+        cd.sector_interrupt(sector(1));
+        //these arrive before the first INT1 is acknowledged
+        cd.sector_interrupt(sector(2));
+        cd.sector_interrupt(sector(3));
+        cd.write(0, 0, &mut scheduler);
+        cd.write(3, 0x80, &mut scheduler);
+        assert_eq!(cd.read(2), 1);
+        acknowledge(&mut cd, &mut scheduler);
+        assert_eq!(interrupt_flag(&mut cd, &mut scheduler), 1);
+        cd.write(0, 0, &mut scheduler);
+        cd.write(3, 0, &mut scheduler);
+        cd.write(3, 0x80, &mut scheduler);
+        assert_eq!(cd.read(2), 3);
+    }
+}
@@ -0,0 +1,154 @@
+//decodes the XA-ADPCM audio in mode 2 form 2 sectors
+//each sector has 18 sound groups of 128 bytes, and each group has 16 bytes of
+// parameters followed by 28 words of interleaved sample data
+#[derive(Default)]
+pub struct XADecoder {
+    //the last two decoded samples for each channel
+    history: [[i32; 2]; 2],
+    //used to convert each sample rate to 44.1kHz
+    phase: u32,
+}
+
+impl XADecoder {
+    const POS: [i32; 4] = [0, 60, 115, 98];
+    const NEG: [i32; 4] = [0, 0, -52, -55];
+    const OUTPUT_RATE: u32 = 44_100;
+
+    pub fn reset(&mut self) {
+        *self = Default::default();
+    }
+
+    //returns 44.1kHz stereo samples for a raw 2352-byte sector
+    pub fn decode_sector(&mut self, sector: &[u8]) -> Vec<[i16; 2]> {
+        let coding = sector[19];
+        let stereo = coding & 3 == 1;
+        let rate = if (coding >> 2) & 3 == 1 { 18_900 } else { 37_800 };
+        let eight_bit = (coding >> 4) & 3 == 1;
+        let mut left = Vec::new();
+        let mut right = Vec::new();
+        for group in sector[24..24 + 18 * 128].chunks(128) {
+            let units = if eight_bit { 4 } else { 8 };
+            for unit in 0..units {
+                //stereo sectors alternate between the left and right channels
+                let channel = if stereo { unit & 1 } else { 0 };
+                let samples = self.decode_unit(group, unit, eight_bit, channel);
+                if stereo && channel == 1 {
+                    right.extend(samples);
+                } else {
+                    left.extend(samples);
+                }
+            }
+        }
+        if !stereo {
+            right = left.clone();
+        }
+        let mut output = Vec::new();
+        for (&l, &r) in left.iter().zip(right.iter()) {
+            self.phase += XADecoder::OUTPUT_RATE;
+            while self.phase >= rate {
+                self.phase -= rate;
+                output.push([l, r]);
+            }
+        }
+        output
+    }
+
+    fn decode_unit(
+        &mut self, group: &[u8], unit: usize, eight_bit: bool, channel: usize,
+    ) -> Vec<i16> {
+        let parameters = group[4 + unit];
+        let shift = match parameters & 0xf {
+            shift if shift > 12 => 9,
+            shift => shift,
+        };
+        let filter = ((parameters >> 4) & 3) as usize;
+        let [mut old, mut older] = self.history[channel];
+        let samples = (0..28)
+            .map(|word| {
+                let raw = if eight_bit {
+                    ((group[16 + word * 4 + unit] as u16) << 8) as i16
+                } else {
+                    let byte = group[16 + word * 4 + unit / 2];
+                    let nibble = (byte >> ((unit & 1) * 4)) & 0xf;
+                    ((nibble as u16) << 12) as i16
+                };
+                let sample = ((raw >> shift) as i32) +
+                    (old * XADecoder::POS[filter] + older * XADecoder::NEG[filter] + 32) / 64;
+                let sample = sample.clamp(i16::MIN as i32, i16::MAX as i32);
+                older = old;
+                old = sample;
+                sample as i16
+            })
+            .collect();
+        self.history[channel] = [old, older];
+        samples
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECTOR_LEN: usize = 2352;
+
+    fn sector(coding: u8) -> Vec<u8> {
+        let mut sector = vec![0; SECTOR_LEN];
+        sector[19] = coding;
+        sector
+    }
+
+    #[test]
+    fn silence() {
+        let mut xa = XADecoder::default();
+        //4032 samples at 37.8kHz
+        let mono = xa.decode_sector(&sector(0x00));
+        assert_eq!(mono.len(), 4704);
+        assert!(mono.iter().all(|&s| s == [0, 0]));
+        xa.reset();
+        //2016 samples per channel at 37.8kHz
+        let stereo = xa.decode_sector(&sector(0x01));
+        assert_eq!(stereo.len(), 2352);
+        xa.reset();
+        //4032 samples at 18.9kHz
+        let half_rate = xa.decode_sector(&sector(0x04));
+        assert_eq!(half_rate.len(), 9408);
+    }
+
+    #[test]
+    fn filters() {
+        let mut xa = XADecoder::default();
+        let mut sector = sector(0x00);
+        //the first unit of the first group uses filter 1 with no shift
+        sector[24 + 4] = 0x10;
+        sector[24 + 16] = 0x01;
+        let samples = xa.decode_sector(&sector);
+        assert_eq!(samples[0], [0x1000, 0x1000]);
+        //the following zero nibbles decay by 60/64
+        assert_eq!(samples[1], [3840, 3840]);
+        assert_eq!(samples[2], [3600, 3600]);
+    }
+
+    #[test]
+    fn stereo_channels() {
+        let mut xa = XADecoder::default();
+        let mut sector = sector(0x01);
+        //the low nibble is unit 0 on the left and the high nibble is unit 1 on the
+        // right
+        sector[24 + 4] = 0x04;
+        sector[24 + 5] = 0x08;
+        sector[24 + 16] = 0x71;
+        let samples = xa.decode_sector(&sector);
+        assert_eq!(samples[0], [0x1000 >> 4, 0x7000 >> 8]);
+    }
+
+    #[test]
+    fn shift_range() {
+        let mut xa = XADecoder::default();
+        let mut sector = sector(0x00);
+        //shifts above 12 act like 9
+        sector[24 + 4] = 0x0d;
+        sector[24 + 16] = 0x08;
+        let samples = xa.decode_sector(&sector);
+        assert_eq!(samples[0], [-0x8000 >> 9, -0x8000 >> 9]);
+    }
+}
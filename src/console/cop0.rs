@@ -79,10 +79,19 @@ impl Cop0 {
         None
     }
 
-    pub fn request_interrupt(&mut self, irq: u32) {
-        //FIXME: double check what else needs to be done
-        //there should be something that specifies which interrupt was requested right?
-        self.registers[Cop0::IDX_R13].set(10);
+    pub fn set_interrupt_line(&mut self, active: bool) {
+        if active {
+            self.registers[Cop0::IDX_R13].set(10);
+        } else {
+            self.registers[Cop0::IDX_R13].clear(10);
+        }
+    }
+
+    //interrupts need to be enabled in SR and unmasked by the IM bits
+    pub fn interrupt_pending(&self) -> bool {
+        let sr = self.registers[Cop0::IDX_R12];
+        let cause = self.registers[Cop0::IDX_R13];
+        sr.nth_bit_bool(0) && (sr & cause & 0xff00) != 0
     }
 
     pub fn generate_exception(&mut self, kind: Cop0Exception, current_pc: u32) -> u32 {
@@ -147,3 +156,51 @@ impl Cop0 {
             .set_mask(prev << 2);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_sr(sr: u32) -> Cop0 {
+        let mut cop0: Cop0 = Default::default();
+        cop0.nth_data_reg_mut(12).maybe_set(sr);
+        cop0
+    }
+
+    #[test]
+    fn interrupt_line_is_cause_bit_10() {
+        let mut cop0 = with_sr(0);
+        cop0.set_interrupt_line(true);
+        assert_eq!(cop0.nth_data_reg(13), 1 << 10);
+        cop0.set_interrupt_line(false);
+        assert_eq!(cop0.nth_data_reg(13), 0);
+    }
+
+    #[test]
+    fn interrupt_needs_iec_and_im2() {
+        let mut cop0 = with_sr(1 << 10);
+        cop0.set_interrupt_line(true);
+        assert!(!cop0.interrupt_pending());
+        let mut cop0 = with_sr(1);
+        cop0.set_interrupt_line(true);
+        assert!(!cop0.interrupt_pending());
+        let mut cop0 = with_sr(1 | (1 << 10));
+        cop0.set_interrupt_line(true);
+        assert!(cop0.interrupt_pending());
+        cop0.set_interrupt_line(false);
+        assert!(!cop0.interrupt_pending());
+    }
+
+    #[test]
+    fn interrupt_exception() {
+        let mut cop0 = with_sr(1 | (1 << 10));
+        cop0.set_interrupt_line(true);
+        assert_eq!(cop0.generate_exception(Cop0Exception::Interrupt, 0x8001_0000), 0x8000_0080);
+        assert_eq!(cop0.nth_data_reg(14), 0x8001_0000);
+        //the exception code is 0 but the line stays up
+        assert_eq!(cop0.nth_data_reg(13), 1 << 10);
+        //IEc was pushed to IEp
+        assert_eq!(cop0.nth_data_reg(12), (1 << 10) | (1 << 2));
+        assert!(!cop0.interrupt_pending());
+    }
+}
@@ -1,6 +1,124 @@
+use crate::register::BitTwiddle;
+
 pub trait DMAChannel {
     fn send(&mut self, data: Vec<u32>);
-    fn receive(&self) -> u32;
+    fn receive(&mut self, words: u32) -> Vec<u32>;
+}
+
+//keeps track of which transfers are waiting and the DMA interrupt register (DICR)
+#[derive(Default)]
+pub struct DMA {
+    //transfers that were started while another channel was busy
+    pending: Vec<Transfer>,
+    //the transfer in progress, its data moves once it's had time to finish
+    active: Option<Transfer>,
+    interrupt: u32,
+}
+
+impl DMA {
+    //the most distinct headers that fit in main RAM so a longer list must be circular
+    const MAX_LINKED_LIST_NODES: usize = 0x8_0000;
+
+    //a channel that is started again replaces its previous request
+    pub fn request(&mut self, transfer: Transfer) {
+        self.pending
+            .retain(|t| t.channel_num() != transfer.channel_num());
+        self.pending.push(transfer);
+    }
+
+    //picks the next transfer according to the priorities in DPCR
+    //lower values have higher priority and ties go to the higher channel
+    //channels whose device isn't ready to transfer data yet are skipped
+    pub fn start_next(
+        &mut self, control: u32, ready: impl Fn(u8) -> bool,
+    ) -> Option<&Transfer> {
+        if self.active.is_some() {
+            return None
+        }
+        let priority = |channel: u8| control.range(4 * channel as u32, 4 * channel as u32 + 2);
+        let enabled = |channel: u8| control.nth_bit_bool(4 * channel as u32 + 3);
+        let (idx, _) = self
+            .pending
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| enabled(t.channel_num()) && ready(t.channel_num()))
+            .min_by_key(|(_, t)| (priority(t.channel_num()), 7 - t.channel_num()))?;
+        self.active = Some(self.pending.remove(idx));
+        self.active.as_ref()
+    }
+
+    //the transfer in progress which is removed once its data has moved
+    pub fn take_active(&mut self) -> Option<Transfer> {
+        self.active.take()
+    }
+
+    //returns true if the DMA IRQ should be requested
+    pub fn finish(&mut self, channel: u8) -> bool {
+        self.active = None;
+        if self.interrupt.nth_bit_bool(16 + channel as u32) {
+            self.interrupt.set(24 + channel as u32);
+        }
+        self.update_master_flag()
+    }
+
+    pub fn read_interrupt(&self) -> u32 {
+        self.interrupt
+    }
+
+    //writing 1 to a channel's flag acknowledges it
+    pub fn write_interrupt(&mut self, value: u32) -> bool {
+        let flags = self.interrupt & !value & 0x7f00_0000;
+        self.interrupt = (self.interrupt & 0x8000_0000) | (value & 0x00ff_803f) | flags;
+        self.update_master_flag()
+    }
+
+    //OTC builds an empty ordering table backwards from the start address where each
+    // entry links to the previous one and the last one is the end marker
+    pub fn ordering_table(start_address: u32, num: u32) -> Vec<u32> {
+        (1..=num)
+            .map(|i| match num - i {
+                0 => 0x00ff_ffff,
+                _ => start_address.wrapping_sub(4 * i) & 0x001f_fffc,
+            })
+            .collect()
+    }
+
+    //the address of each header in a linked list
+    pub fn linked_list(start_address: u32, mut read_word: impl FnMut(u32) -> u32) -> Vec<u32> {
+        let addr_mask = 0x001f_fffc;
+        let mut header_address = start_address & addr_mask;
+        let mut headers = Vec::new();
+        loop {
+            let header = read_word(header_address);
+            headers.push(header_address);
+            //the end marker is usually 0x00ff_ffff but only bit 23 is checked
+            if header.nth_bit_bool(23) {
+                break
+            }
+            //real hardware would hang on a corrupt or circular list but the emulator
+            // shouldn't
+            if headers.len() == DMA::MAX_LINKED_LIST_NODES {
+                println!("DMA linked list at {:#x} has no end marker", start_address);
+                break
+            }
+            header_address = header & addr_mask;
+        }
+        headers
+    }
+
+    //bit 31 summarizes the other bits and the IRQ is requested when it goes high
+    fn update_master_flag(&mut self) -> bool {
+        let enabled = self.interrupt.range(16, 22) & self.interrupt.range(24, 30);
+        let master_flag = self.interrupt.nth_bit_bool(15) ||
+            (self.interrupt.nth_bit_bool(23) && enabled != 0);
+        let rising = master_flag && !self.interrupt.nth_bit_bool(31);
+        if master_flag {
+            self.interrupt.set(31);
+        } else {
+            self.interrupt.clear(31);
+        }
+        rising
+    }
 }
 
 #[derive(Debug)]
@@ -92,3 +210,111 @@ impl Transfer {
         &self.chunks
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transfer(channel_num: u8) -> Transfer {
+        Transfer::new(
+            channel_num,
+            0,
+            Chunks::NumWords(1),
+            Direction::FromRAM,
+            Step::Forward,
+            0,
+        )
+    }
+
+    //every channel is enabled with the given priority
+    fn control(priorities: [u32; 7]) -> u32 {
+        priorities
+            .iter()
+            .enumerate()
+            .map(|(channel, priority)| (priority | 8) << (4 * channel))
+            .sum()
+    }
+
+    #[test]
+    fn channels_start_in_priority_order() {
+        let mut dma: DMA = Default::default();
+        let control = control([7, 7, 1, 7, 0, 7, 1]);
+        for &channel in &[2, 6, 4] {
+            dma.request(transfer(channel));
+        }
+        let mut order = Vec::new();
+        while let Some(channel) = dma.start_next(control, |_| true).map(|t| t.channel_num()) {
+            //nothing else starts while a channel is busy
+            assert!(dma.start_next(control, |_| true).is_none());
+            dma.take_active();
+            dma.finish(channel);
+            order.push(channel);
+        }
+        assert_eq!(order, vec![4, 6, 2]);
+    }
+
+    #[test]
+    fn disabled_and_waiting_channels_are_skipped() {
+        let mut dma: DMA = Default::default();
+        //channel 0 is disabled
+        let control = control([0; 7]) & !8;
+        for &channel in &[0, 1, 2] {
+            dma.request(transfer(channel));
+        }
+        let started = dma.start_next(control, |channel| channel != 2);
+        assert_eq!(started.map(|t| t.channel_num()), Some(1));
+    }
+
+    #[test]
+    fn finished_channels_raise_the_irq() {
+        let mut dma: DMA = Default::default();
+        //enable channel 2's interrupt and the master enable
+        assert!(!dma.write_interrupt((1 << 18) | (1 << 23)));
+        assert!(dma.finish(2));
+        assert_eq!(dma.read_interrupt() >> 24, 0x84);
+        //the IRQ is only requested when the master flag goes high
+        assert!(!dma.finish(2));
+        //writing 1 to the flag acknowledges it
+        assert!(!dma.write_interrupt((1 << 18) | (1 << 23) | (1 << 26)));
+        assert_eq!(dma.read_interrupt() >> 24, 0);
+        //channels without their interrupt enabled don't set a flag
+        assert!(!dma.finish(3));
+        assert_eq!(dma.read_interrupt() >> 24, 0);
+    }
+
+    #[test]
+    fn master_flag_needs_the_master_enable() {
+        let mut dma: DMA = Default::default();
+        dma.write_interrupt(1 << 16);
+        assert!(!dma.finish(0));
+        assert_eq!(dma.read_interrupt() >> 24, 0x01);
+        //enabling it afterwards raises the IRQ for the pending flag
+        assert!(dma.write_interrupt((1 << 16) | (1 << 23)));
+        //bit 15 forces the master flag
+        let mut dma: DMA = Default::default();
+        assert!(dma.write_interrupt(1 << 15));
+        assert!(dma.read_interrupt().nth_bit_bool(31));
+    }
+
+    #[test]
+    fn ordering_table_links_backwards() {
+        assert_eq!(
+            DMA::ordering_table(0x100, 4),
+            vec![0xfc, 0xf8, 0xf4, 0x00ff_ffff]
+        );
+    }
+
+    #[test]
+    fn linked_list_follows_headers() {
+        let ram = [0x0100_0008, 0, 0x0000_0010, 0, 0x00ff_ffff];
+        let headers = DMA::linked_list(0, |address| ram[address as usize / 4]);
+        assert_eq!(headers, vec![0, 8, 0x10]);
+    }
+
+    #[test]
+    fn circular_linked_list_stops() {
+        //each header points back to itself
+        let headers = DMA::linked_list(0x40, |address| address);
+        assert_eq!(headers.len(), DMA::MAX_LINKED_LIST_NODES);
+    }
+}
@@ -1,3 +1,4 @@
+use super::rasterizer::{Texture, Vertex};
 use crate::register::BitTwiddle;
 
 #[derive(Debug)]
@@ -28,70 +29,141 @@ impl Command {
         self.0.push(parameters);
     }
 
-    pub fn get_monochrome(&self) -> Vec<i16> {
-        self.as_ref()
-            .iter()
-            .take(1)
-            .enumerate()
-            .map(|(i, v)| v >> (8 * i))
-            .map(|c| c.lowest_bits(8) as i16)
-            .cycle()
-            .take(4)
-            .collect()
+    pub fn is_shaded(&self) -> bool {
+        (self.id() as u32).nth_bit_bool(4)
+    }
+
+    pub fn is_quad(&self) -> bool {
+        (self.id() as u32).nth_bit_bool(3)
+    }
+
+    pub fn is_textured(&self) -> bool {
+        (self.id() as u32).nth_bit_bool(2)
     }
 
-    pub fn get_colors(&self) -> Vec<i16> {
-        self.as_ref()
-            .iter()
-            .step_by(2)
-            .map(|col| {
-                vec![col]
-                    .into_iter()
-                    .cycle()
-                    .take(3)
-                    .enumerate()
-                    .map(|(i, c)| c >> (8 * i))
-                    .map(|c| c.lowest_bits(8) as i16)
-                    .collect::<Vec<i16>>()
+    pub fn is_semi_transparent(&self) -> bool {
+        (self.id() as u32).nth_bit_bool(1)
+    }
+
+    pub fn is_raw_texture(&self) -> bool {
+        (self.id() as u32).nth_bit_bool(0)
+    }
+
+    pub fn color(&self, idx: usize) -> [i32; 3] {
+        let word = self.idx(idx);
+        [
+            word.byte() as i32,
+            word.range(8, 15) as i32,
+            word.range(16, 23) as i32,
+        ]
+    }
+
+    //vertex coordinates are signed 11-bit values
+    pub fn position(&self, idx: usize) -> (i32, i32) {
+        let word = self.idx(idx);
+        let x = ((word << 21) as i32) >> 21;
+        let y = ((word.upper_bits(16) << 21) as i32) >> 21;
+        (x, y)
+    }
+
+    pub fn texcoord(&self, idx: usize) -> (i32, i32) {
+        let word = self.idx(idx);
+        (word.byte() as i32, word.range(8, 15) as i32)
+    }
+
+    fn polygon_stride(&self) -> usize {
+        1 + self.is_shaded() as usize + self.is_textured() as usize
+    }
+
+    //polygon parameters are grouped by vertex as [color], position, [texcoord]
+    //the first color is always part of the command word
+    pub fn polygon_vertices(&self) -> Vec<Vertex> {
+        let num_vertices = if self.is_quad() { 4 } else { 3 };
+        let stride = self.polygon_stride();
+        (0..num_vertices)
+            .map(|n| {
+                let (x, y) = self.position(1 + n * stride);
+                let color = if self.is_shaded() {
+                    self.color(n * stride)
+                } else {
+                    self.color(0)
+                };
+                let vertex = Vertex::new(x, y, color);
+                if self.is_textured() {
+                    let (u, v) = self.texcoord(2 + n * stride);
+                    vertex.with_texcoord(u, v)
+                } else {
+                    vertex
+                }
             })
-            .flatten()
             .collect()
     }
 
-    pub fn get_xpos_consecutive(&self) -> Vec<i16> {
-        self.as_ref()
-            .iter()
-            .skip(1)
-            .map(|yx| yx.half() as i16)
-            .collect()
+    //the upper halfword of the second texcoord in a polygon is the texpage
+    pub fn polygon_texpage(&self) -> u32 {
+        self.idx(2 + self.polygon_stride()).upper_bits(16)
     }
 
-    pub fn get_ypos_consecutive(&self) -> Vec<i16> {
-        self.as_ref()
-            .iter()
-            .skip(1)
-            .map(|yx| yx.upper_bits(16) as i16)
-            .collect()
+    //the upper halfword of the first texcoord in polygons and rectangles is the
+    // CLUT
+    pub fn texture(&self, texpage: u32) -> Texture {
+        let clut = self.idx(2).upper_bits(16);
+        Texture::new(texpage, clut, self.is_raw_texture())
     }
 
-    pub fn get_xpos_every_other(&self) -> Vec<i16> {
-        self.as_ref()
-            .iter()
-            .skip(1)
-            .step_by(2)
-            .map(|yx| yx.half() as i16)
-            .collect()
+    fn num_polygon_words(&self) -> usize {
+        let num_vertices = if self.is_quad() { 4 } else { 3 };
+        let num_colors = if self.is_shaded() { num_vertices } else { 1 };
+        num_vertices * (1 + self.is_textured() as usize) + num_colors
+    }
+
+    pub fn is_polyline(&self) -> bool {
+        (self.id() as u32).nth_bit_bool(3)
     }
 
-    pub fn get_ypos_every_other(&self) -> Vec<i16> {
-        self.as_ref()
-            .iter()
-            .skip(1)
-            .step_by(2)
-            .map(|yx| yx.upper_bits(16) as i16)
+    //line parameters are grouped by vertex as [color], position
+    pub fn line_vertices(&self) -> Vec<Vertex> {
+        let stride = 1 + self.is_shaded() as usize;
+        //skip the terminator at the end of polylines
+        let num_words = self.num_words() - self.is_polyline() as usize;
+        (1..num_words)
+            .step_by(stride)
+            .map(|i| {
+                let (x, y) = self.position(i);
+                let color = if self.is_shaded() {
+                    self.color(i - 1)
+                } else {
+                    self.color(0)
+                };
+                Vertex::new(x, y, color)
+            })
             .collect()
     }
 
+    //polylines end with a word in the form 0x5xxx5xxx
+    fn is_polyline_terminator(word: u32) -> bool {
+        word & 0xf000_f000 == 0x5000_5000
+    }
+
+    //rectangles are either variable-sized or 1x1, 8x8 or 16x16
+    pub fn rectangle_size(&self) -> (i32, i32) {
+        match (self.id() as u32).range(3, 4) {
+            0 => {
+                let size = self.idx(2 + self.is_textured() as usize);
+                (size.lowest_bits(10) as i32, size.range(16, 24) as i32)
+            },
+            1 => (1, 1),
+            2 => (8, 8),
+            3 => (16, 16),
+            _ => unreachable!(""),
+        }
+    }
+
+    fn num_rectangle_words(&self) -> usize {
+        let variable_size = (self.id() as u32).range(3, 4) == 0;
+        2 + self.is_textured() as usize + variable_size as usize
+    }
+
     pub fn get_xpos_copy(&self, idx: usize) -> u32 {
         self.idx(idx).half() & 0x3ff
     }
@@ -101,46 +173,49 @@ impl Command {
     }
 
     pub fn get_xsize_copy(&self, idx: usize) -> u32 {
-        ((self.idx(idx).half() - 1) & 0x3ff) + 1
+        (self.idx(idx).half().wrapping_sub(1) & 0x3ff) + 1
     }
 
     pub fn get_ysize_copy(&self, idx: usize) -> u32 {
-        (((self.idx(idx) >> 16) - 1) & 0x3ff) + 1
+        ((self.idx(idx) >> 16).wrapping_sub(1) & 0x1ff) + 1
     }
 
     pub fn completed(&self) -> bool {
         match self.id() {
             0xe1 | 0xe2 | 0xe3 | 0xe4 | 0xe5 | 0xe6 | 0x01 | 0x1f => self.num_words() == 1,
-            0x68 | 0x6a | 0x70 | 0x72 | 0x78 | 0x7a => self.num_words() == 2,
-            0x6c | 0x6d | 0x6e | 0x6f | 0x74 | 0x75 | 0x76 | 0x77 | 0x7c | 0x7d | 0x7e | 0x7f |
-            0x60 | 0x62 | 0x40 | 0x42 | 0x02 => self.num_words() == 3,
-            0x20 | 0x22 | 0x64 | 0x65 | 0x66 | 0x67 | 0x80..=0x9f | 0x50 | 0x52 => {
-                self.num_words() == 4
-            },
-            0x28 | 0x2a => self.num_words() == 5,
-            0x30 | 0x32 => self.num_words() == 6,
-            0x24 | 0x25 | 0x26 | 0x27 => self.num_words() == 7,
-            0x38 | 0x3a => self.num_words() == 8,
-            0x2C | 0x2D | 0x2E | 0x2F | 0x34 | 0x36 => self.num_words() == 9,
-            0x3c | 0x3e => self.num_words() == 12,
-            0x48 | 0x4a | 0x58 | 0x5a => {
-                (self.num_words() >= 4) && self.0.iter().rev().take(1).all(|&p| p == 0x55555555)
+            0x02 => self.num_words() == 3,
+            0x80..=0x9f => self.num_words() == 4,
+            0x20..=0x3f => self.num_words() == self.num_polygon_words(),
+            0x60..=0x7f => self.num_words() == self.num_rectangle_words(),
+            0x40..=0x5f => {
+                let stride = 1 + self.is_shaded() as usize;
+                //a single line has two vertices
+                let num_words = 2 + stride;
+                if self.is_polyline() {
+                    //the terminator takes the place of the next vertex's first word
+                    let last = self.num_words() - 1;
+                    last >= num_words &&
+                        (last - 2) % stride == 0 &&
+                        Command::is_polyline_terminator(self.0[last])
+                } else {
+                    self.num_words() == num_words
+                }
             },
             0xa0..=0xbf => {
                 if self.num_words() < 3 {
                     false
                 } else {
                     //xsize and ysize are measured in halfwords
-                    let ysize = self.0[2].upper_bits(16);
-                    let xsize = self.0[2].half();
-                    //paramter length is in bytes
-                    let num_words = *((xsize * ysize) + 1).clear(0) >> 1;
+                    let xsize = self.get_xsize_copy(2);
+                    let ysize = self.get_ysize_copy(2);
+                    //each word contains two halfwords
+                    let num_words = (xsize * ysize + 1) / 2;
                     self.num_words() == 3 + num_words as usize
                 }
             },
             0xc0..=0xdf => self.num_words() == 3,
-            0x00 | 04..=0x1e | 0xe0 | 0xe7..=0xef => true,
-            _ => todo!("implement this GP0 command {:x}", self.id()),
+            //everything else is a single word and unknown commands are treated as NOPs
+            0x00 | 0x03..=0x1e | 0xe0 | 0xe7..=0xff => true,
         }
     }
 }
@@ -0,0 +1,59 @@
+use super::GPU;
+use crate::register::BitTwiddle;
+
+impl GPU {
+    //the horizontal display range is measured in GPU cycles
+    fn display_width(&self) -> u32 {
+        let cycles = self.display_range_x2.saturating_sub(self.display_range_x1);
+        //the number of pixels is rounded to a multiple of 4
+        match ((cycles / self.gpustat.dot_clock_divider()) + 2) & !3 {
+            0 => self.gpustat.horizontal_resolution(),
+            width => width,
+        }
+    }
+
+    //the vertical display range is measured in scanlines
+    fn display_height(&self) -> u32 {
+        let lines = match self.display_range_y2.saturating_sub(self.display_range_y1) {
+            0 => 240,
+            lines => lines.min(256),
+        };
+        //interlaced 480-line mode shows both fields at once
+        if self.gpustat.is_480_lines() {
+            lines * 2
+        } else {
+            lines
+        }
+    }
+
+    //returns the part of VRAM shown on screen as 24-bit RGB
+    pub fn display_frame(&self) -> (Vec<u8>, u32, u32) {
+        let width = self.display_width();
+        let height = self.display_height();
+        if !self.gpustat.display_enabled() {
+            return (vec![0; (width * height * 3) as usize], width, height)
+        }
+        let mut pixels = Vec::with_capacity((width * height * 3) as usize);
+        for y in 0..height {
+            let line = self.display_y + y;
+            for x in 0..width {
+                if self.gpustat.is_24bit_color() {
+                    //each pixel takes up three bytes
+                    let offset = self.display_x * 2 + x * 3;
+                    for byte in offset..offset + 3 {
+                        let halfword = self.vram_pixel(byte / 2, line);
+                        pixels.push((halfword >> (8 * (byte % 2))) as u8);
+                    }
+                } else {
+                    let halfword = self.vram_pixel(self.display_x + x, line) as u32;
+                    for i in 0..3 {
+                        //expand each 5-bit component to 8 bits
+                        let c = halfword.range(5 * i, 5 * i + 4);
+                        pixels.push(((c << 3) | (c >> 2)) as u8);
+                    }
+                }
+            }
+        }
+        (pixels, width, height)
+    }
+}
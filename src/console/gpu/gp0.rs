@@ -1,121 +1,176 @@
-use super::super::memory::KB;
-use super::super::screen::Drawable;
+use super::rasterizer::{Blending, Vertex};
 use super::{Command, GPU};
-use crate::common::{ReadArray, WriteArray};
 use crate::register::BitTwiddle;
 
 impl GPU {
-    fn object_within_limits(xpos: &Vec<i16>, ypos: &Vec<i16>) -> bool {
-        let xmax = 1023;
-        let ymax = 511;
-        let xdiff = xpos.iter().max().unwrap() - xpos.iter().min().unwrap();
-        let ydiff = ypos.iter().max().unwrap() - ypos.iter().min().unwrap();
-        xdiff < xmax && ydiff < ymax
+    //the drawing offset is a signed 11-bit value
+    fn apply_drawing_offset(&self, vertex: Vertex) -> Vertex {
+        let xoffset = ((self.drawing_offset_x << 21) as i32) >> 21;
+        let yoffset = ((self.drawing_offset_y << 21) as i32) >> 21;
+        Vertex {
+            x: vertex.x + xoffset,
+            y: vertex.y + yoffset,
+            ..vertex
+        }
     }
 
-    fn zip_positions(&self, xpos: Vec<i16>, ypos: Vec<i16>) -> Vec<i16> {
-        let xoffset = self.drawing_offset_x as i16;
-        let yoffset = self.drawing_offset_y as i16;
-        xpos.into_iter()
-            .map(|xp| xp - xoffset)
-            .zip(ypos.into_iter().map(|yp| yp - yoffset))
-            .map(|(a, b)| vec![a, b])
-            .flatten()
-            .collect()
+    //the blending mode comes from the current texpage, which textured polygons
+    // update before drawing
+    fn blending(&self, command: &Command) -> Option<Blending> {
+        if command.is_semi_transparent() {
+            Some(Blending::new(self.gpustat.texture_page()))
+        } else {
+            None
+        }
     }
 
-    pub fn exec_next_gp0_command(&mut self) -> Option<Drawable> {
+    fn exec_next_gp0_command(&mut self) {
         let cmd = self.command_buffer.pop_front();
         match cmd {
             Some(command) => {
                 match command.id() {
                     0x00 => {},
                     0x01 => {},
+                    0x02 => {
+                        //fills ignore the drawing area and mask settings and are never dithered
+                        let color = command.color(0);
+                        let value = (color[0] >> 3) | (color[1] >> 3) << 5 | (color[2] >> 3) << 10;
+                        //the x position and width are rounded to multiples of 16 pixels
+                        let xpos = command.idx(1).half() & 0x3f0;
+                        let ypos = command.get_ypos_copy(1);
+                        let width = ((command.idx(2).half() & 0x3ff) + 0xf) & !0xf;
+                        let height = command.idx(2).upper_bits(16) & 0x1ff;
+                        for y in ypos..ypos + height {
+                            for x in xpos..xpos + width {
+                                self.set_vram_pixel(x, y, value as u16);
+                            }
+                        }
+                    },
                     0x04..=0x1e | 0xe0 | 0xe7..=0xef => {},
-                    0x28 => {
+                    0x20..=0x3f => {
                         if self.logging {
-                            println!("rendered an opaque monochrome four-point polygon");
+                            println!("rendered a polygon {:#x?}", command);
                         }
-                        let xpos = command.get_xpos_consecutive();
-                        let ypos = command.get_ypos_consecutive();
-                        if GPU::object_within_limits(&xpos, &ypos) {
-                            let positions = self.zip_positions(xpos, ypos);
-                            let monochrome = command.get_monochrome();
-                            return Some(Drawable::new(positions, monochrome))
+                        let vertices = command
+                            .polygon_vertices()
+                            .into_iter()
+                            .map(|v| self.apply_drawing_offset(v))
+                            .collect::<Vec<Vertex>>();
+                        let texture = if command.is_textured() {
+                            let texpage = command.polygon_texpage();
+                            self.gpustat.set_texture_page(texpage);
+                            Some(command.texture(texpage))
+                        } else {
+                            None
+                        };
+                        let blending = self.blending(&command);
+                        //shaded and texture-blended polygons are dithered
+                        let dither = command.is_shaded() ||
+                            (command.is_textured() && !command.is_raw_texture());
+                        match vertices.len() {
+                            3 => self.draw_triangle(
+                                [vertices[0], vertices[1], vertices[2]],
+                                texture,
+                                blending,
+                                dither,
+                            ),
+                            _ => self.draw_quad(
+                                [vertices[0], vertices[1], vertices[2], vertices[3]],
+                                texture,
+                                blending,
+                                dither,
+                            ),
                         }
                     },
-                    0x2c => {
-                        //TODO: add texturing
-                        let xpos = command.get_xpos_every_other();
-                        let ypos = command.get_ypos_every_other();
-                        if GPU::object_within_limits(&xpos, &ypos) {
-                            let positions = self.zip_positions(xpos, ypos);
-                            let cols = command.get_monochrome();
-                            return Some(Drawable::new(positions, cols))
+                    0x40..=0x5f => {
+                        if self.logging {
+                            println!("rendered a line {:#x?}", command);
                         }
-                    },
-                    0x30 => {
-                        let xpos = command.get_xpos_every_other();
-                        let ypos = command.get_ypos_every_other();
-                        if GPU::object_within_limits(&xpos, &ypos) {
-                            let positions = self.zip_positions(xpos, ypos);
-                            let cols = command.get_colors();
-                            return Some(Drawable::new(positions, cols))
+                        let vertices = command
+                            .line_vertices()
+                            .into_iter()
+                            .map(|v| self.apply_drawing_offset(v))
+                            .collect::<Vec<Vertex>>();
+                        let blending = self.blending(&command);
+                        for segment in vertices.windows(2) {
+                            self.draw_line(segment[0], segment[1], blending, command.is_shaded());
                         }
                     },
-                    0x38 => {
+                    0x60..=0x7f => {
                         if self.logging {
-                            println!("rendered an shaded four-point polygon");
+                            println!("rendered a rectangle {:#x?}", command);
                         }
-                        let xpos = command.get_xpos_every_other();
-                        let ypos = command.get_ypos_every_other();
-                        if GPU::object_within_limits(&xpos, &ypos) {
-                            let positions = self.zip_positions(xpos, ypos);
-                            let cols = command.get_colors();
-                            return Some(Drawable::new(positions, cols))
+                        let (x, y) = command.position(1);
+                        let origin = self.apply_drawing_offset(Vertex::new(x, y, command.color(0)));
+                        let (width, height) = command.rectangle_size();
+                        let blending = self.blending(&command);
+                        if command.is_textured() {
+                            let (u, v) = command.texcoord(2);
+                            let texture = command.texture(self.gpustat.texture_page());
+                            let origin = origin.with_texcoord(u, v);
+                            self.draw_rectangle(origin, width, height, Some(texture), blending);
+                        } else {
+                            self.draw_rectangle(origin, width, height, None, blending);
+                        }
+                    },
+                    0x80..=0x9f => {
+                        let src_x = command.get_xpos_copy(1);
+                        let src_y = command.get_ypos_copy(1);
+                        let dest_x = command.get_xpos_copy(2);
+                        let dest_y = command.get_ypos_copy(2);
+                        let width = command.get_xsize_copy(3);
+                        let height = command.get_ysize_copy(3);
+                        for y in 0..height {
+                            for x in 0..width {
+                                let value = self.vram_pixel(src_x + x, src_y + y);
+                                self.write_masked_pixel(dest_x + x, dest_y + y, value);
+                            }
                         }
                     },
-                    0xa0 => {
+                    //0xa1-0xbf and 0xc1-0xdf mirror the CPU to VRAM and VRAM to CPU copies
+                    0xa0..=0xbf => {
                         let xpos = command.get_xpos_copy(1);
                         let ypos = command.get_ypos_copy(1);
                         let width = command.get_xsize_copy(2);
                         let height = command.get_ysize_copy(2);
-                        command
+                        let halfwords = command
                             .as_ref()
-                            .into_iter()
+                            .iter()
                             .skip(3)
-                            .enumerate()
-                            .for_each(|(i, &word)| {
-                                let i = i as u32;
-                                let xoffset = i % width;
-                                let yoffset = i / width;
-                                let x = (xpos + xoffset) % 2048;
-                                let y = (ypos + yoffset) % 512;
-                                let idx = x + (y * 2 * KB as u32);
-                                self.vram.as_mut().write_word(idx, word);
-                            });
+                            .flat_map(|&word| vec![word.half(), word.upper_bits(16)])
+                            .take((width * height) as usize)
+                            .collect::<Vec<u32>>();
+                        for (i, &halfword) in halfwords.iter().enumerate() {
+                            let i = i as u32;
+                            let x = xpos + (i % width);
+                            let y = ypos + (i / width);
+                            self.write_masked_pixel(x, y, halfword as u16);
+                        }
                     },
-                    0xc0 => {
-                        let xpos = command.get_xpos_copy(1) as usize;
-                        let ypos = command.get_ypos_copy(1) as usize;
-                        let width = command.get_xsize_copy(2) as usize;
-                        let height = command.get_ysize_copy(2) as usize;
-                        let start_idx = xpos + (ypos * 2 * KB);
-                        let vram_data = self
-                            .vram
-                            .chunks(4)
-                            .skip(start_idx / 8)
-                            .take(((width * height) + 1) / 8)
-                            .map(|chunk| chunk.read_word(0))
+                    0xc0..=0xdf => {
+                        let xpos = command.get_xpos_copy(1);
+                        let ypos = command.get_ypos_copy(1);
+                        let width = command.get_xsize_copy(2);
+                        let height = command.get_ysize_copy(2);
+                        let halfwords = (0..width * height)
+                            .map(|i| self.vram_pixel(xpos + (i % width), ypos + (i / width)) as u32)
                             .collect::<Vec<u32>>();
-                        vram_data
-                            .iter()
-                            .for_each(|&word| self.gpuread.push_back(word))
+                        for pair in halfwords.chunks(2) {
+                            let upper = pair.get(1).map_or(0, |&halfword| halfword << 16);
+                            self.gpuread.push_back(pair[0] | upper);
+                        }
                     },
                     0xe1 => {
-                        let mask = 0x0000_83ff;
-                        let command = command.idx(0) & mask;
-                        self.gpustat.as_mut().clear_mask(mask).set_mask(command);
+                        let command = command.idx(0);
+                        let mask = 0x0000_87ff;
+                        //the texture disable bit goes in bit 15 of gpustat
+                        let texture_disable = if self.texture_disable_allowed {
+                            command.nth_bit(11) << 15
+                        } else {
+                            0
+                        };
+                        let new_values = command.lowest_bits(11) | texture_disable;
+                        self.gpustat.as_mut().clear_mask(mask).set_mask(new_values);
                     },
                     0xe2 => {
                         let command = command.idx(0);
@@ -144,26 +199,14 @@ impl GPU {
                         let mask = command.lowest_bits(2) << 11;
                         self.gpustat.as_mut().clear(11).clear(12).set_mask(mask);
                     },
-                    0x60 => {
-                        let xoffset = command.get_xpos_copy(1) as i16;
-                        let yoffset = command.get_ypos_copy(1) as i16;
-                        let width = command.get_xpos_copy(2) as i16;
-                        let height = command.get_ypos_copy(2) as i16;
-                        let xpos = vec![xoffset, xoffset, xoffset + width, xoffset + width];
-                        let ypos = vec![yoffset, yoffset + height, yoffset, yoffset + height];
-                        if GPU::object_within_limits(&xpos, &ypos) {
-                            let positions = self.zip_positions(xpos, ypos);
-                            let monochrome = command.get_monochrome();
-                            return Some(Drawable::new(positions, monochrome))
-                        }
-                    },
                     _ => {
-                        todo!("implement this GP0 command {:#x}", command.id());
+                        if self.logging {
+                            println!("ignored unknown GP0 command {:#x}", command.id());
+                        }
                     },
                 }
-                None
             },
-            None => None,
+            None => {},
         }
     }
 
@@ -191,6 +234,9 @@ impl GPU {
                 }
                 self.command_buffer.push_back(cmd);
                 self.waiting_for_parameters = false;
+                //draw as soon as the last parameter arrives since nothing else drains the
+                // buffer
+                self.exec_next_gp0_command();
             },
             false => {
                 self.partial_command = Some(cmd);
@@ -205,3 +251,73 @@ impl GPU {
             .fold(0, |acc, command| acc + command.num_words())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn send(gpu: &mut GPU, words: &[u32]) {
+        for &word in words {
+            gpu.write_to_gp0(word);
+        }
+    }
+
+    fn filled(gpu: &GPU, y: u32) -> Vec<u32> {
+        (0..64).filter(|&x| gpu.vram_pixel(x, y) != 0).collect()
+    }
+
+    #[test]
+    fn fill_rounds_to_16_pixels() {
+        let mut gpu = GPU::new(false);
+        //x = 20 and width = 17 become x = 16 and width = 32
+        send(&mut gpu, &[0x0200_00ff, (1 << 16) | 20, (2 << 16) | 17]);
+        assert_eq!(filled(&gpu, 0), vec![]);
+        assert_eq!(filled(&gpu, 1), (16..48).collect::<Vec<u32>>());
+        assert_eq!(filled(&gpu, 2), (16..48).collect::<Vec<u32>>());
+        assert_eq!(filled(&gpu, 3), vec![]);
+        assert_eq!(gpu.vram_pixel(16, 1), 0x1f);
+    }
+
+    #[test]
+    fn fill_ignores_drawing_area_and_mask() {
+        let mut gpu = GPU::new(false);
+        gpu.set_vram_pixel(0, 0, 0x8000);
+        //an empty drawing area and mask checking on
+        send(&mut gpu, &[0xe300_0000 | 100, 0xe400_0000, 0xe600_0003]);
+        send(&mut gpu, &[0x0200_ff00, 0, (1 << 16) | 16]);
+        assert_eq!(gpu.vram_pixel(0, 0), 0x03e0);
+        assert_eq!(gpu.vram_pixel(15, 0), 0x03e0);
+    }
+
+    #[test]
+    fn copy_within_vram() {
+        let mut gpu = GPU::new(false);
+        for x in 0..4 {
+            gpu.set_vram_pixel(x, 0, 1 + x as u16);
+        }
+        send(&mut gpu, &[0x8000_0000, 0, (10 << 16) | 100, (1 << 16) | 4]);
+        let copied = (100..104).map(|x| gpu.vram_pixel(x, 10)).collect::<Vec<u16>>();
+        assert_eq!(copied, vec![1, 2, 3, 4]);
+        assert_eq!(gpu.vram_pixel(104, 10), 0);
+    }
+
+    #[test]
+    fn copy_respects_the_mask_bit() {
+        let mut gpu = GPU::new(false);
+        gpu.set_vram_pixel(0, 0, 0x0001);
+        gpu.set_vram_pixel(1, 0, 0x0002);
+        gpu.set_vram_pixel(11, 0, 0x8000);
+        send(&mut gpu, &[0xe600_0003, 0x8000_0000, 0, 10, (1 << 16) | 2]);
+        assert_eq!(gpu.vram_pixel(10, 0), 0x8001);
+        assert_eq!(gpu.vram_pixel(11, 0), 0x8000);
+    }
+
+    #[test]
+    fn copy_sizes_wrap() {
+        let mut gpu = GPU::new(false);
+        gpu.set_vram_pixel(1023, 0, 7);
+        //a width of 0 copies 1024 pixels
+        send(&mut gpu, &[0x8000_0000, 0, 1 << 16, 1 << 16]);
+        assert_eq!(gpu.vram_pixel(1023, 1), 7);
+    }
+}
@@ -12,7 +12,25 @@ impl GPU {
                 self.command_buffer.clear();
                 self.waiting_for_parameters = false;
                 self.partial_command = None;
+                self.gpuread.clear();
                 *self.gpustat.as_mut() = 0x1480_2000;
+                self.drawing_min_x = 0;
+                self.drawing_min_y = 0;
+                self.drawing_max_x = 0;
+                self.drawing_max_y = 0;
+                self.drawing_offset_x = 0;
+                self.drawing_offset_y = 0;
+                self.texture_mask_x = 0;
+                self.texture_mask_y = 0;
+                self.texture_offset_x = 0;
+                self.texture_offset_y = 0;
+                self.display_x = 0;
+                self.display_y = 0;
+                self.display_range_x1 = 0x200;
+                self.display_range_x2 = 0x200 + 256 * 10;
+                self.display_range_y1 = 0x10;
+                self.display_range_y2 = 0x10 + 240;
+                self.texture_disable_allowed = false;
             },
             0x01 => {
                 self.command_buffer.clear();
@@ -26,7 +44,7 @@ impl GPU {
                 self.gpustat
                     .as_mut()
                     .clear(23)
-                    .set_mask(command.lowest_bits(1) << 23);
+                    .set_mask(value.lowest_bits(1) << 23);
             },
             0x04 => {
                 let mask = 0x6000_0000;
@@ -34,25 +52,53 @@ impl GPU {
                 self.gpustat.as_mut().clear_mask(mask).set_mask(new_values);
             },
             0x05 => {
-                self.display_x = *command.lowest_bits(10).clone().clear(0);
-                self.display_y = command.upper_bits(22).lowest_bits(9);
+                self.display_x = *value.lowest_bits(10).clone().clear(0);
+                self.display_y = value.range(10, 18);
             },
             0x06 => {
-                self.display_range_x1 = command.lowest_bits(12);
-                self.display_range_x2 = command.upper_bits(20).lowest_bits(12);
+                self.display_range_x1 = value.lowest_bits(12);
+                self.display_range_x2 = value.range(12, 23);
             },
             0x07 => {
-                self.display_range_y1 = command.lowest_bits(10);
-                self.display_range_y2 = command.upper_bits(22).lowest_bits(10);
+                self.display_range_y1 = value.lowest_bits(10);
+                self.display_range_y2 = value.range(10, 19);
             },
             0x08 => {
-                let mask = 0x003f_4000;
+                let mask = 0x007f_4000;
+                //the second horizontal resolution bit goes in bit 16 and the reverse flag
+                // goes in bit 14
                 let new_values =
-                    ((value & 0x3f) << 17) | (value & 0x40) << 16 | (value & 0x80) << 14;
+                    ((value & 0x3f) << 17) | (value & 0x40) << 10 | (value & 0x80) << 7;
                 self.gpustat.as_mut().clear_mask(mask).set_mask(new_values);
             },
+            0x09 => {
+                self.texture_disable_allowed = value.nth_bit_bool(0);
+            },
+            0x0a..=0x0f => {},
+            0x10..=0x1f => {
+                //only the lowest 3 bits select which info to return
+                let info = match value.lowest_bits(3) {
+                    2 => {
+                        self.texture_mask_x |
+                            self.texture_mask_y << 5 |
+                            self.texture_offset_x << 10 |
+                            self.texture_offset_y << 15
+                    },
+                    3 => self.drawing_min_x | self.drawing_min_y << 10,
+                    4 => self.drawing_max_x | self.drawing_max_y << 10,
+                    5 => self.drawing_offset_x | self.drawing_offset_y << 11,
+                    //this is the GPU version for the original 160-pin GPU
+                    7 => 2,
+                    //the remaining values leave GPUREAD unchanged
+                    _ => return,
+                };
+                self.gpuread.clear();
+                self.gpuread.push_back(info);
+            },
             _ => {
-                todo!("implement this GP1 command {:#x}", command);
+                if self.logging {
+                    println!("ignored GP1 command {:#x}", command);
+                }
             },
         }
     }
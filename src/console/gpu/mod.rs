@@ -4,9 +4,13 @@ use crate::register::BitTwiddle;
 use std::collections::VecDeque;
 
 mod command;
+mod display;
 mod gp0;
 mod gp1;
+mod rasterizer;
+mod timing;
 use command::Command;
+pub use timing::VBlank;
 
 pub struct GPU {
     logging: bool,
@@ -32,6 +36,12 @@ pub struct GPU {
     display_range_x2: u32,
     display_range_y1: u32,
     display_range_y2: u32,
+    texture_disable_allowed: bool,
+    //video timing
+    scanline: u32,
+    in_vblank: bool,
+    line_clock_remainder: u32,
+    dot_clock_remainder: u32,
 }
 
 impl DMAChannel for GPU {
@@ -39,9 +49,9 @@ impl DMAChannel for GPU {
         data.iter().for_each(|&word| self.write_to_gp0(word))
     }
 
-    fn receive(&self) -> u32 {
-        //self.gpuread
-        0
+    //VRAM to CPU transfers are read through GPUREAD
+    fn receive(&mut self, words: u32) -> Vec<u32> {
+        (0..words).map(|_| self.gpuread()).collect()
     }
 }
 
@@ -56,10 +66,72 @@ impl GPUStatus {
         &mut self.0
     }
 
-    //this may be useful for when the GPU (i.e. OpengGL part) needs to access the
-    // info in gpustat
-    fn texture_page_x(&self) -> u32 {
-        self.0.lowest_bits(4)
+    //the texpage used by rectangles, in the same format as GP0(E1h)
+    fn texture_page(&self) -> u32 {
+        self.0.lowest_bits(9)
+    }
+
+    //polygons update the texpage bits with their own texpage
+    fn set_texture_page(&mut self, texpage: u32) {
+        self.0.clear_mask(0x1ff).set_mask(texpage.lowest_bits(9));
+    }
+
+    fn dithering(&self) -> bool {
+        self.0.nth_bit_bool(9)
+    }
+
+    fn set_mask_bit(&self) -> bool {
+        self.0.nth_bit_bool(11)
+    }
+
+    fn check_mask_bit(&self) -> bool {
+        self.0.nth_bit_bool(12)
+    }
+
+    fn horizontal_resolution(&self) -> u32 {
+        if self.0.nth_bit_bool(16) {
+            368
+        } else {
+            match self.0.range(17, 18) {
+                0 => 256,
+                1 => 320,
+                2 => 512,
+                3 => 640,
+                _ => unreachable!(""),
+            }
+        }
+    }
+
+    //the number of GPU cycles per pixel for each horizontal resolution
+    fn dot_clock_divider(&self) -> u32 {
+        match self.horizontal_resolution() {
+            256 => 10,
+            320 => 8,
+            368 => 7,
+            512 => 5,
+            _ => 4,
+        }
+    }
+
+    //480 lines are only used in interlaced mode
+    fn is_480_lines(&self) -> bool {
+        self.0.nth_bit_bool(19) && self.0.nth_bit_bool(22)
+    }
+
+    fn is_pal(&self) -> bool {
+        self.0.nth_bit_bool(20)
+    }
+
+    fn is_interlaced(&self) -> bool {
+        self.0.nth_bit_bool(22)
+    }
+
+    fn display_enabled(&self) -> bool {
+        !self.0.nth_bit_bool(23)
+    }
+
+    fn is_24bit_color(&self) -> bool {
+        self.0.nth_bit_bool(21)
     }
 }
 
@@ -92,21 +164,48 @@ impl GPU {
             display_range_x2: 0,
             display_range_y1: 0,
             display_range_y2: 0,
+            texture_disable_allowed: false,
+            scanline: 0,
+            in_vblank: false,
+            line_clock_remainder: 0,
+            dot_clock_remainder: 0,
         }
     }
 
     pub fn gpustat(&self) -> u32 {
-        //this is a dirty hack
-        *self
-            .gpustat
-            .0
-            .clone()
-            .clear(19)
-            .clear(14)
-            .clear(31)
-            .set(26)
-            .set(27)
-            .set(28)
+        let mut gpustat = self.gpustat.0;
+        let ready_for_commands = self.command_buffer.is_empty();
+        let ready_to_send_vram = !self.gpuread.is_empty();
+        gpustat.clear_mask(0x1e00_0000);
+        if ready_for_commands {
+            gpustat.set(26).set(28);
+        }
+        if ready_to_send_vram {
+            gpustat.set(27);
+        }
+        //the DMA request bit depends on the DMA direction
+        let dma_request = match gpustat.range(29, 30) {
+            0 => false,
+            1 => true,
+            2 => ready_for_commands,
+            3 => ready_to_send_vram,
+            _ => unreachable!(""),
+        };
+        if dma_request {
+            gpustat.set(25);
+        }
+        //the interlace field bit is always set when interlacing is off
+        if !self.gpustat.is_interlaced() {
+            gpustat.set(13);
+        }
+        gpustat
+    }
+
+    //switches between drawing even and odd lines once per frame
+    pub fn end_frame(&mut self) {
+        if self.gpustat.is_interlaced() {
+            *self.gpustat.as_mut() ^= 0x8000_2000;
+        }
     }
 
     pub fn gpuread(&mut self) -> u32 {
@@ -0,0 +1,655 @@
+use super::GPU;
+use crate::common::{ReadArray, WriteArray};
+use crate::register::BitTwiddle;
+
+//offsets added to each color component when dithering, indexed by the lowest
+//two bits of y and x
+const DITHER: [[i32; 4]; 4] = [[-4, 0, -3, 1], [2, -2, 3, -1], [-3, 1, -4, 0], [3, -1, 2, -2]];
+
+#[derive(Clone, Copy, Debug)]
+pub struct Vertex {
+    pub x: i32,
+    pub y: i32,
+    pub color: [i32; 3],
+    pub u: i32,
+    pub v: i32,
+}
+
+impl Vertex {
+    pub fn new(x: i32, y: i32, color: [i32; 3]) -> Self {
+        Vertex {
+            x,
+            y,
+            color,
+            u: 0,
+            v: 0,
+        }
+    }
+
+    pub fn with_texcoord(self, u: i32, v: i32) -> Self {
+        Vertex { u, v, ..self }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum TextureDepth {
+    Bit4,
+    Bit8,
+    Bit15,
+}
+
+//where a primitive's texture and color lookup table are located in VRAM
+#[derive(Clone, Copy, Debug)]
+pub struct Texture {
+    page_x: u32,
+    page_y: u32,
+    depth: TextureDepth,
+    clut_x: u32,
+    clut_y: u32,
+    raw: bool,
+}
+
+impl Texture {
+    //texpage is in the same format as the lowest 9 bits of GP0(E1h)
+    pub fn new(texpage: u32, clut: u32, raw: bool) -> Self {
+        let depth = match texpage.range(7, 8) {
+            0 => TextureDepth::Bit4,
+            1 => TextureDepth::Bit8,
+            _ => TextureDepth::Bit15,
+        };
+        Texture {
+            page_x: texpage.lowest_bits(4) * 64,
+            page_y: texpage.nth_bit(4) * 256,
+            depth,
+            clut_x: clut.lowest_bits(6) * 16,
+            clut_y: clut.range(6, 14),
+            raw,
+        }
+    }
+}
+
+//how semi-transparent primitives are combined with what's already in VRAM
+#[derive(Clone, Copy, Debug)]
+pub enum Blending {
+    Average,
+    Add,
+    Subtract,
+    AddQuarter,
+}
+
+impl Blending {
+    //the mode is in bits 5 and 6 of the texpage
+    pub fn new(texpage: u32) -> Self {
+        match texpage.range(5, 6) {
+            0 => Blending::Average,
+            1 => Blending::Add,
+            2 => Blending::Subtract,
+            3 => Blending::AddQuarter,
+            _ => unreachable!(""),
+        }
+    }
+
+    //blends each 5-bit component of the background and foreground colors
+    fn blend(&self, background: u16, foreground: u16) -> u16 {
+        let mut result = foreground & 0x8000;
+        for i in 0..3 {
+            let b = (background >> (5 * i)) as i32 & 0x1f;
+            let f = (foreground >> (5 * i)) as i32 & 0x1f;
+            let c = match self {
+                Blending::Average => (b + f) / 2,
+                Blending::Add => b + f,
+                Blending::Subtract => b - f,
+                Blending::AddQuarter => b + f / 4,
+            };
+            result |= (c.clamp(0, 0x1f) as u16) << (5 * i);
+        }
+        result
+    }
+}
+
+//twice the signed area of the triangle abc
+fn edge(a: &Vertex, b: &Vertex, x: i32, y: i32) -> i64 {
+    ((b.x - a.x) as i64 * (y - a.y) as i64) - ((b.y - a.y) as i64 * (x - a.x) as i64)
+}
+
+//pixels lying exactly on the right or bottom edges of a polygon are not drawn
+fn is_top_left(a: &Vertex, b: &Vertex) -> bool {
+    let dx = b.x - a.x;
+    let dy = b.y - a.y;
+    (dy == 0 && dx > 0) || dy < 0
+}
+
+//rounds n / d to the nearest integer
+fn div_round(n: i64, d: i64) -> i64 {
+    (2 * n + d).div_euclid(2 * d)
+}
+
+impl GPU {
+    //VRAM is 512 lines of 1024 halfwords
+    pub(super) fn vram_pixel(&self, x: u32, y: u32) -> u16 {
+        let idx = ((x & 0x3ff) + (y & 0x1ff) * 1024) * 2;
+        self.vram.as_ref().read_half(idx) as u16
+    }
+
+    pub(super) fn set_vram_pixel(&mut self, x: u32, y: u32, value: u16) {
+        let idx = ((x & 0x3ff) + (y & 0x1ff) * 1024) * 2;
+        self.vram.as_mut().write_half(idx, value as u32);
+    }
+
+    //writes a pixel to VRAM while respecting the mask bit settings from GP0(E6h)
+    pub(super) fn write_masked_pixel(&mut self, x: u32, y: u32, value: u16) {
+        if self.gpustat.check_mask_bit() && (self.vram_pixel(x, y) as u32).nth_bit_bool(15) {
+            return
+        }
+        let value = if self.gpustat.set_mask_bit() {
+            value | 0x8000
+        } else {
+            value
+        };
+        self.set_vram_pixel(x, y, value);
+    }
+
+    fn in_drawing_area(&self, x: i32, y: i32) -> bool {
+        x >= self.drawing_min_x as i32 &&
+            x <= self.drawing_max_x as i32 &&
+            y >= self.drawing_min_y as i32 &&
+            y <= self.drawing_max_y as i32
+    }
+
+    fn plot(&mut self, x: i32, y: i32, value: u16) {
+        if self.in_drawing_area(x, y) {
+            self.write_masked_pixel(x as u32, y as u32, value);
+        }
+    }
+
+    //texture coordinates are 8-bit and wrap around inside the window set by GP0(E2h)
+    fn texel(&self, texture: &Texture, u: i32, v: i32) -> u16 {
+        let window = |coord: i32, mask: u32, offset: u32| {
+            ((coord as u32 & 0xff) & !(mask * 8)) | ((offset & mask) * 8)
+        };
+        let u = window(u, self.texture_mask_x, self.texture_offset_x);
+        let v = window(v, self.texture_mask_y, self.texture_offset_y);
+        let y = texture.page_y + v;
+        match texture.depth {
+            TextureDepth::Bit4 => {
+                let halfword = self.vram_pixel(texture.page_x + u / 4, y);
+                let index = (halfword >> ((u % 4) * 4)) & 0xf;
+                self.vram_pixel(texture.clut_x + index as u32, texture.clut_y)
+            },
+            TextureDepth::Bit8 => {
+                let halfword = self.vram_pixel(texture.page_x + u / 2, y);
+                let index = (halfword >> ((u % 2) * 8)) & 0xff;
+                self.vram_pixel(texture.clut_x + index as u32, texture.clut_y)
+            },
+            TextureDepth::Bit15 => self.vram_pixel(texture.page_x + u, y),
+        }
+    }
+
+    //blends the color with the texture (if any) and writes the result to VRAM
+    fn shade(
+        &mut self, x: i32, y: i32, color: [i32; 3], texture: Option<(&Texture, i32, i32)>,
+        blending: Option<Blending>, dither: bool,
+    ) {
+        let value = match texture {
+            Some((texture, u, v)) => {
+                let texel = self.texel(texture, u, v);
+                //texels that are all zeros are fully transparent
+                if texel == 0 {
+                    return
+                }
+                if texture.raw {
+                    texel
+                } else {
+                    //a color component of 0x80 leaves the texel unchanged
+                    let mut modulated = [0; 3];
+                    for (i, component) in modulated.iter_mut().enumerate() {
+                        let t = (texel as u32).range(5 * i as u32, 5 * i as u32 + 4) as i32;
+                        *component = (t << 3) * color[i] / 0x80;
+                    }
+                    self.to_rgb15(modulated, x, y, dither) | (texel & 0x8000)
+                }
+            },
+            None => self.to_rgb15(color, x, y, dither),
+        };
+        //textured primitives are only semi-transparent where the texel's highest bit
+        // is set
+        let value = match blending {
+            Some(blending) if texture.is_none() || (value as u32).nth_bit_bool(15) => {
+                blending.blend(self.vram_pixel(x as u32, y as u32), value)
+            },
+            _ => value,
+        };
+        self.plot(x, y, value);
+    }
+
+    //converts a 24-bit color to the 15-bit format used in VRAM
+    fn to_rgb15(&self, color: [i32; 3], x: i32, y: i32, dither: bool) -> u16 {
+        let offset = if dither && self.gpustat.dithering() {
+            DITHER[(y & 3) as usize][(x & 3) as usize]
+        } else {
+            0
+        };
+        let component = |c: i32| ((c + offset).clamp(0, 0xff) >> 3) as u16;
+        component(color[0]) | component(color[1]) << 5 | component(color[2]) << 10
+    }
+
+    pub(super) fn draw_triangle(
+        &mut self, vertices: [Vertex; 3], texture: Option<Texture>, blending: Option<Blending>,
+        dither: bool,
+    ) {
+        let [a, b, c] = vertices;
+        let area = edge(&a, &b, c.x, c.y);
+        if area == 0 {
+            return
+        }
+        //make sure the vertices wind in the same direction as the edge functions
+        let (a, b, area) = if area < 0 { (b, a, -area) } else { (a, b, area) };
+        let xs = [a.x, b.x, c.x];
+        let ys = [a.y, b.y, c.y];
+        let min_x = *xs.iter().min().unwrap();
+        let max_x = *xs.iter().max().unwrap();
+        let min_y = *ys.iter().min().unwrap();
+        let max_y = *ys.iter().max().unwrap();
+        //polygons that are too large are skipped entirely
+        if max_x - min_x >= 1024 || max_y - min_y >= 512 {
+            return
+        }
+        let min_x = min_x.max(self.drawing_min_x as i32);
+        let max_x = max_x.min(self.drawing_max_x as i32);
+        let min_y = min_y.max(self.drawing_min_y as i32);
+        let max_y = max_y.min(self.drawing_max_y as i32);
+        let bias = |from: &Vertex, to: &Vertex| if is_top_left(from, to) { 0 } else { -1 };
+        let bias = [bias(&b, &c), bias(&c, &a), bias(&a, &b)];
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                let w = [edge(&b, &c, x, y), edge(&c, &a, x, y), edge(&a, &b, x, y)];
+                if (0..3).any(|i| w[i] + bias[i] < 0) {
+                    continue
+                }
+                let interpolate = |p: i32, q: i32, r: i32| {
+                    ((w[0] * p as i64 + w[1] * q as i64 + w[2] * r as i64) / area) as i32
+                };
+                let mut color = [0; 3];
+                for (i, component) in color.iter_mut().enumerate() {
+                    *component = interpolate(a.color[i], b.color[i], c.color[i]);
+                }
+                let u = interpolate(a.u, b.u, c.u);
+                let v = interpolate(a.v, b.v, c.v);
+                let texture = texture.as_ref().map(|t| (t, u, v));
+                self.shade(x, y, color, texture, blending, dither);
+            }
+        }
+    }
+
+    pub(super) fn draw_quad(
+        &mut self, vertices: [Vertex; 4], texture: Option<Texture>, blending: Option<Blending>,
+        dither: bool,
+    ) {
+        let [a, b, c, d] = vertices;
+        self.draw_triangle([a, b, c], texture, blending, dither);
+        self.draw_triangle([b, c, d], texture, blending, dither);
+    }
+
+    //rectangles are never dithered and their texture coordinates increase by one
+    // per pixel
+    pub(super) fn draw_rectangle(
+        &mut self, origin: Vertex, width: i32, height: i32, texture: Option<Texture>,
+        blending: Option<Blending>,
+    ) {
+        let min_x = origin.x.max(self.drawing_min_x as i32);
+        let max_x = (origin.x + width - 1).min(self.drawing_max_x as i32);
+        let min_y = origin.y.max(self.drawing_min_y as i32);
+        let max_y = (origin.y + height - 1).min(self.drawing_max_y as i32);
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                let u = origin.u + (x - origin.x);
+                let v = origin.v + (y - origin.y);
+                let texture = texture.as_ref().map(|t| (t, u, v));
+                self.shade(x, y, origin.color, texture, blending, false);
+            }
+        }
+    }
+
+    pub(super) fn draw_line(
+        &mut self, from: Vertex, to: Vertex, blending: Option<Blending>, dither: bool,
+    ) {
+        let dx = (to.x - from.x) as i64;
+        let dy = (to.y - from.y) as i64;
+        //lines that are too long are skipped entirely
+        if dx.abs() >= 1024 || dy.abs() >= 512 {
+            return
+        }
+        let steps = dx.abs().max(dy.abs()).max(1);
+        for i in 0..=steps {
+            let x = from.x + div_round(dx * i, steps) as i32;
+            let y = from.y + div_round(dy * i, steps) as i32;
+            let mut color = [0; 3];
+            for (c, component) in color.iter_mut().enumerate() {
+                let diff = (to.color[c] - from.color[c]) as i64;
+                *component = from.color[c] + div_round(diff * i, steps) as i32;
+            }
+            self.shade(x, y, color, None, blending, dither);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //a GPU that can draw anywhere in VRAM
+    fn new_gpu() -> GPU {
+        let mut gpu = GPU::new(false);
+        send(&mut gpu, &[0xe300_0000, 0xe400_0000 | (511 << 10) | 1023]);
+        gpu
+    }
+
+    fn send(gpu: &mut GPU, words: &[u32]) {
+        for &word in words {
+            gpu.write_to_gp0(word);
+        }
+    }
+
+    fn position(x: i32, y: i32) -> u32 {
+        (x as u32 & 0xffff) | (y as u32) << 16
+    }
+
+    //returns the coordinates of every non-zero pixel in the top-left corner of VRAM
+    fn drawn(gpu: &GPU) -> Vec<(u32, u32)> {
+        let mut pixels = Vec::new();
+        for y in 0..32 {
+            for x in 0..32 {
+                if gpu.vram_pixel(x, y) != 0 {
+                    pixels.push((x, y));
+                }
+            }
+        }
+        pixels
+    }
+
+    fn square(x: u32, y: u32, size: u32) -> Vec<(u32, u32)> {
+        (y..y + size)
+            .flat_map(|y| (x..x + size).map(move |x| (x, y)))
+            .collect()
+    }
+
+    #[test]
+    fn quads_skip_their_right_and_bottom_edges() {
+        let mut gpu = new_gpu();
+        let corners = [position(0, 0), position(4, 0), position(0, 4), position(4, 4)];
+        send(&mut gpu, &[0x28ff_ffff]);
+        send(&mut gpu, &corners);
+        assert_eq!(drawn(&gpu), square(0, 0, 4));
+        assert_eq!(gpu.vram_pixel(0, 0), 0x7fff);
+    }
+
+    #[test]
+    fn winding_does_not_change_coverage() {
+        let mut gpu = new_gpu();
+        send(&mut gpu, &[0x20ff_ffff, position(0, 0), position(0, 4), position(4, 0)]);
+        let clockwise = drawn(&gpu);
+        let mut gpu = new_gpu();
+        send(&mut gpu, &[0x20ff_ffff, position(0, 0), position(4, 0), position(0, 4)]);
+        assert_eq!(drawn(&gpu), clockwise);
+        //the hypotenuse is a right edge
+        assert_eq!(clockwise.len(), 4 + 3 + 2 + 1);
+    }
+
+    #[test]
+    fn drawing_area_and_offset() {
+        let mut gpu = new_gpu();
+        //only draw inside (2, 2)-(5, 5) and move everything by (1, 1)
+        send(&mut gpu, &[0xe300_0000 | (2 << 10) | 2, 0xe400_0000 | (5 << 10) | 5]);
+        send(&mut gpu, &[0xe500_0000 | (1 << 11) | 1]);
+        send(&mut gpu, &[0x6000_001f, position(-1, -1), (16 << 16) | 16]);
+        assert_eq!(drawn(&gpu), square(2, 2, 4));
+        //the offset is a signed 11-bit value
+        let mut gpu = new_gpu();
+        send(&mut gpu, &[0xe500_0000 | (0x7ff << 11) | 0x7ff]);
+        send(&mut gpu, &[0x6800_001f, position(3, 3)]);
+        assert_eq!(drawn(&gpu), vec![(2, 2)]);
+    }
+
+    #[test]
+    fn only_shaded_polygons_are_dithered() {
+        let gray = 0x0080_8080;
+        let triangle = [position(0, 0), gray, position(8, 0), gray, position(0, 8)];
+        let mut gpu = new_gpu();
+        //enable dithering
+        send(&mut gpu, &[0xe100_0200, 0x3000_0000 | gray]);
+        send(&mut gpu, &triangle);
+        assert_eq!(gpu.vram_pixel(0, 0) & 0x1f, (0x80 - 4) >> 3);
+        assert_eq!(gpu.vram_pixel(1, 0) & 0x1f, 0x80 >> 3);
+        assert_eq!(gpu.vram_pixel(3, 0) & 0x1f, (0x80 + 1) >> 3);
+        let mut gpu = new_gpu();
+        send(&mut gpu, &[0xe100_0200, 0x2000_0000 | gray]);
+        send(&mut gpu, &[position(0, 0), position(8, 0), position(0, 8)]);
+        assert_eq!(gpu.vram_pixel(0, 0) & 0x1f, 0x80 >> 3);
+        //dithering is off by default
+        let mut gpu = new_gpu();
+        send(&mut gpu, &[0x3000_0000 | gray]);
+        send(&mut gpu, &triangle);
+        assert_eq!(gpu.vram_pixel(0, 0) & 0x1f, 0x80 >> 3);
+    }
+
+    #[test]
+    fn mask_bit_is_set_and_checked() {
+        let mut gpu = new_gpu();
+        gpu.set_vram_pixel(1, 0, 0x8001);
+        //set the mask bit on every pixel drawn
+        send(&mut gpu, &[0xe600_0001, 0x6800_00ff, position(0, 0)]);
+        assert_eq!(gpu.vram_pixel(0, 0), 0x801f);
+        //and don't draw over pixels with the mask bit set
+        send(&mut gpu, &[0xe600_0002, 0x6a00_ff00, position(0, 0), (1 << 16) | 2]);
+        assert_eq!(gpu.vram_pixel(0, 0), 0x801f);
+        assert_eq!(gpu.vram_pixel(1, 0), 0x8001);
+        send(&mut gpu, &[0xe600_0000, 0x6000_ff00, position(0, 0), (1 << 16) | 2]);
+        assert_eq!(gpu.vram_pixel(0, 0), 0x03e0);
+    }
+
+    //draws a raw textured rectangle from texture page 1 with the CLUT at (32, 1)
+    fn textured_rectangle(gpu: &mut GPU, depth: u32, u: u32, width: u32) {
+        let clut = 2 | (1 << 6);
+        send(gpu, &[0xe100_0001 | (depth << 7), 0x6500_0000, position(0, 0)]);
+        send(gpu, &[(clut << 16) | u, (1 << 16) | width]);
+    }
+
+    fn row(gpu: &GPU, width: u32) -> Vec<u16> {
+        (0..width).map(|x| gpu.vram_pixel(x, 0)).collect()
+    }
+
+    #[test]
+    fn clut4_texture() {
+        let mut gpu = new_gpu();
+        for i in 0..16 {
+            gpu.set_vram_pixel(32 + i, 1, 0x100 + i as u16);
+        }
+        gpu.set_vram_pixel(64, 0, 0x3f21);
+        gpu.set_vram_pixel(65, 0, 0x0004);
+        textured_rectangle(&mut gpu, 0, 0, 5);
+        assert_eq!(row(&gpu, 5), vec![0x101, 0x102, 0x10f, 0x103, 0x104]);
+    }
+
+    #[test]
+    fn clut8_texture() {
+        let mut gpu = new_gpu();
+        for i in 0..256 {
+            gpu.set_vram_pixel(32 + i, 1, 0x1000 + i as u16);
+        }
+        gpu.set_vram_pixel(64, 0, 0xf001);
+        gpu.set_vram_pixel(65, 0, 0x0080);
+        textured_rectangle(&mut gpu, 1, 0, 3);
+        assert_eq!(row(&gpu, 3), vec![0x1001, 0x10f0, 0x1080]);
+    }
+
+    #[test]
+    fn direct_texture_and_transparency() {
+        let mut gpu = new_gpu();
+        gpu.set_vram_pixel(0, 0, 0x1234);
+        gpu.set_vram_pixel(1, 0, 0x1234);
+        gpu.set_vram_pixel(64, 0, 0x7c00);
+        //texels that are all zeros aren't drawn
+        gpu.set_vram_pixel(65, 0, 0);
+        textured_rectangle(&mut gpu, 2, 0, 2);
+        assert_eq!(row(&gpu, 2), vec![0x7c00, 0x1234]);
+    }
+
+    #[test]
+    fn texture_window() {
+        let mut gpu = new_gpu();
+        for i in 0..16 {
+            gpu.set_vram_pixel(64 + i, 0, 1 + i as u16);
+        }
+        //repeat the 8 texels starting at u = 8
+        send(&mut gpu, &[0xe200_0000 | (1 << 10) | 1]);
+        textured_rectangle(&mut gpu, 2, 0, 16);
+        let window = (9..17).collect::<Vec<u16>>();
+        assert_eq!(row(&gpu, 16), [window.clone(), window].concat());
+    }
+
+    #[test]
+    fn modulated_texture() {
+        let mut gpu = new_gpu();
+        gpu.set_vram_pixel(64, 0, 0x8000 | (4 << 10) | (8 << 5) | 16);
+        //0x80 leaves the texel unchanged and 0x40 halves it
+        send(&mut gpu, &[0xe100_0101, 0x6c40_8080, position(0, 0), 0]);
+        assert_eq!(gpu.vram_pixel(0, 0), 0x8000 | (2 << 10) | (8 << 5) | 16);
+    }
+
+    #[test]
+    fn textured_polygons_use_their_own_texpage() {
+        let mut gpu = new_gpu();
+        gpu.set_vram_pixel(128, 0, 0x7fff);
+        //texpage 2 with 15-bit texels, every vertex samples (0, 0)
+        let texpage = 2 | (2 << 7);
+        send(&mut gpu, &[0x2500_0000, position(0, 0), 0, position(4, 0)]);
+        send(&mut gpu, &[texpage << 16, position(0, 4), 0]);
+        let triangle = vec![
+            (0, 0),
+            (1, 0),
+            (2, 0),
+            (3, 0),
+            (0, 1),
+            (1, 1),
+            (2, 1),
+            (0, 2),
+            (1, 2),
+            (0, 3),
+        ];
+        assert_eq!(drawn(&gpu), triangle);
+        assert!(drawn(&gpu).iter().all(|&(x, y)| gpu.vram_pixel(x, y) == 0x7fff));
+        assert_eq!(gpu.gpustat() & 0x1ff, texpage);
+    }
+
+    #[test]
+    fn fixed_size_sprites() {
+        for &(id, size) in &[(0x6c, 1), (0x74, 8), (0x7c, 16)] {
+            let mut gpu = new_gpu();
+            for (x, y) in square(64, 0, 16) {
+                gpu.set_vram_pixel(x, y, 0x7fff);
+            }
+            send(&mut gpu, &[0xe100_0101, (id << 24) | 0x80_8080, position(0, 0), 0]);
+            assert_eq!(drawn(&gpu), square(0, 0, size));
+        }
+    }
+
+    //draws a semi-transparent red pixel with 8 in its red component over 16
+    fn blend_red(mode: u32, background: u16) -> u16 {
+        let mut gpu = new_gpu();
+        gpu.set_vram_pixel(0, 0, background);
+        send(&mut gpu, &[0xe100_0000 | (mode << 5), 0x6a00_0040, position(0, 0)]);
+        gpu.vram_pixel(0, 0)
+    }
+
+    #[test]
+    fn blend_modes() {
+        assert_eq!(blend_red(0, 16), 12);
+        assert_eq!(blend_red(1, 16), 24);
+        assert_eq!(blend_red(2, 16), 8);
+        assert_eq!(blend_red(3, 16), 18);
+        //each component saturates
+        assert_eq!(blend_red(1, 0x7c1f), 0x7c1f);
+        assert_eq!(blend_red(2, 0x7c04), 0x7c00);
+    }
+
+    #[test]
+    fn shared_edges_are_blended_once() {
+        let mut gpu = new_gpu();
+        for (x, y) in square(0, 0, 8) {
+            gpu.set_vram_pixel(x, y, 1);
+        }
+        let corners = [position(0, 0), position(8, 0), position(0, 8), position(8, 8)];
+        send(&mut gpu, &[0xe100_0020, 0x2a00_0008]);
+        send(&mut gpu, &corners);
+        assert!(square(0, 0, 8).iter().all(|&(x, y)| gpu.vram_pixel(x, y) == 2));
+    }
+
+    #[test]
+    fn textures_are_semi_transparent_where_bit_15_is_set() {
+        let mut gpu = new_gpu();
+        gpu.set_vram_pixel(0, 0, 16);
+        gpu.set_vram_pixel(1, 0, 16);
+        gpu.set_vram_pixel(64, 0, 0x8008);
+        gpu.set_vram_pixel(65, 0, 0x0008);
+        //average mode with 15-bit texels
+        send(&mut gpu, &[0xe100_0101, 0x6700_0000, position(0, 0), 0, (1 << 16) | 2]);
+        assert_eq!(row(&gpu, 2), vec![0x800c, 0x0008]);
+    }
+
+    #[test]
+    fn lines_are_blended() {
+        let mut gpu = new_gpu();
+        gpu.set_vram_pixel(0, 0, 16);
+        send(&mut gpu, &[0xe100_0020, 0x4200_0040, position(0, 0), position(1, 0)]);
+        assert_eq!(row(&gpu, 2), vec![24, 8]);
+    }
+
+    #[test]
+    fn lines_include_both_endpoints() {
+        let mut gpu = new_gpu();
+        send(&mut gpu, &[0x4000_001f, position(3, 1), position(0, 1)]);
+        assert_eq!(drawn(&gpu), vec![(0, 1), (1, 1), (2, 1), (3, 1)]);
+        let mut gpu = new_gpu();
+        send(&mut gpu, &[0x4000_001f, position(0, 0), position(1, 3)]);
+        assert_eq!(drawn(&gpu), vec![(0, 0), (0, 1), (1, 2), (1, 3)]);
+        //lines with no length are a single pixel
+        let mut gpu = new_gpu();
+        send(&mut gpu, &[0x4000_001f, position(2, 2), position(2, 2)]);
+        assert_eq!(drawn(&gpu), vec![(2, 2)]);
+    }
+
+    #[test]
+    fn shaded_lines_reach_both_colors() {
+        let mut gpu = new_gpu();
+        send(&mut gpu, &[0x5000_0000, position(0, 0), 0x0000_00f8, position(2, 0)]);
+        assert_eq!(row(&gpu, 3), vec![0, 15, 31]);
+    }
+
+    #[test]
+    fn polylines_end_at_the_terminator() {
+        let mut gpu = new_gpu();
+        send(&mut gpu, &[0x4800_001f, position(0, 0), position(2, 0), position(2, 2)]);
+        assert_eq!(drawn(&gpu), vec![]);
+        send(&mut gpu, &[0x5555_5555]);
+        assert_eq!(drawn(&gpu), vec![(0, 0), (1, 0), (2, 0), (2, 1), (2, 2)]);
+        //the next word starts a new command
+        send(&mut gpu, &[0x6800_00ff, position(0, 3)]);
+        assert_eq!(gpu.vram_pixel(0, 3), 0x1f);
+    }
+
+    #[test]
+    fn shaded_polylines() {
+        let mut gpu = new_gpu();
+        send(&mut gpu, &[0x5800_00f8, position(0, 0), 0x0000_00f8, position(1, 0)]);
+        send(&mut gpu, &[0x0000_0000, position(2, 0), 0x5000_5000]);
+        assert_eq!(row(&gpu, 3), vec![31, 31, 0]);
+    }
+
+    #[test]
+    fn long_lines_are_skipped() {
+        let mut gpu = new_gpu();
+        send(&mut gpu, &[0x4000_001f, position(0, 0), position(1024, 0)]);
+        send(&mut gpu, &[0x4000_001f, position(0, 0), position(0, 512)]);
+        assert_eq!(drawn(&gpu), vec![]);
+    }
+}
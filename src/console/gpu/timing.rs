@@ -0,0 +1,84 @@
+use super::GPU;
+
+//the start or end of the vertical blanking period
+#[derive(Debug, PartialEq)]
+pub enum VBlank {
+    Start,
+    End,
+}
+
+impl GPU {
+    //the GPU clock is about 11/7 times as fast as the CPU clock
+    const GPU_CLOCKS: u32 = 11;
+    const CPU_CYCLES: u32 = 7;
+
+    fn lines_per_frame(&self) -> u32 {
+        if self.gpustat.is_pal() {
+            314
+        } else {
+            263
+        }
+    }
+
+    fn clocks_per_line(&self) -> u32 {
+        if self.gpustat.is_pal() {
+            3406
+        } else {
+            3413
+        }
+    }
+
+    //the vertical display range decides where vblank starts and ends
+    fn vblank_range(&self) -> (u32, u32) {
+        let lines = self.lines_per_frame();
+        if self.display_range_y1 < self.display_range_y2 {
+            (self.display_range_y1.min(lines), self.display_range_y2.min(lines))
+        } else {
+            (0x10, 0x10 + 240)
+        }
+    }
+
+    //the number of CPU cycles until the current scanline ends
+    //the fraction of a cycle that doesn't fit is carried over to the next line
+    pub fn cycles_per_line(&mut self) -> u64 {
+        let total = self.clocks_per_line() * GPU::CPU_CYCLES + self.line_clock_remainder;
+        self.line_clock_remainder = total % GPU::GPU_CLOCKS;
+        (total / GPU::GPU_CLOCKS) as u64
+    }
+
+    //hblank starts at the end of the horizontal display range
+    pub fn cycles_until_hblank(&self) -> u64 {
+        let clocks = self.display_range_x2.min(self.clocks_per_line());
+        (clocks * GPU::CPU_CYCLES / GPU::GPU_CLOCKS) as u64
+    }
+
+    //the number of dots in the current scanline for the selected horizontal resolution
+    pub fn dots_per_line(&mut self) -> u32 {
+        let divider = self.gpustat.dot_clock_divider();
+        let total = self.clocks_per_line() + self.dot_clock_remainder;
+        self.dot_clock_remainder = total % divider;
+        total / divider
+    }
+
+    //moves on to the next scanline and reports whether vblank started or ended
+    pub fn next_scanline(&mut self) -> Option<VBlank> {
+        self.scanline = (self.scanline + 1) % self.lines_per_frame();
+        //without interlacing, the field bit alternates between lines instead
+        if !self.gpustat.is_interlaced() {
+            *self.gpustat.as_mut() ^= 1 << 31;
+        }
+        let (start, end) = self.vblank_range();
+        let in_vblank = self.scanline < start || self.scanline >= end;
+        match (self.in_vblank, in_vblank) {
+            (false, true) => {
+                self.in_vblank = true;
+                Some(VBlank::Start)
+            },
+            (true, false) => {
+                self.in_vblank = false;
+                Some(VBlank::End)
+            },
+            _ => None,
+        }
+    }
+}
@@ -1,3 +1,62 @@
+use super::{MaybeSet, Name};
+use crate::register::BitTwiddle;
+
+//the GTE has separate banks of data and control registers
+enum Bank {
+    Data,
+    Control,
+}
+
+//this wraps a mutable reference to a GTE register so that writes have the
+//same side effects as MTC2 and CTC2 on real hardware
+pub struct GTEReg<'a> {
+    gte: &'a mut GTE,
+    idx: usize,
+    bank: Bank,
+}
+
+impl<'a> GTEReg<'a> {
+    fn new(gte: &'a mut GTE, idx: usize, bank: Bank) -> Self {
+        GTEReg { gte, idx, bank }
+    }
+}
+
+impl<'a> MaybeSet for Option<GTEReg<'a>> {
+    fn maybe_set(self, value: u32) -> Option<Name> {
+        self.map(|reg| match reg.bank {
+            Bank::Data => reg.gte.write_data_reg(reg.idx, value),
+            Bank::Control => reg.gte.write_ctrl_reg(reg.idx, value),
+        });
+        None
+    }
+}
+
+//the matrices that MVMVA can select
+#[derive(Clone, Copy)]
+enum Matrix {
+    Rotation,
+    Light,
+    Color,
+    //selecting the fourth matrix returns garbage built from other registers
+    Reserved,
+}
+
+//the vectors that MVMVA can select
+#[derive(Clone, Copy)]
+enum Vector {
+    V(usize),
+    IR,
+}
+
+//the translation vectors that MVMVA can select
+#[derive(Clone, Copy, PartialEq)]
+enum Translation {
+    TR,
+    BK,
+    FC,
+    None,
+}
+
 #[derive(Default)]
 pub struct GTE {
     data_registers: [u32; 32],
@@ -5,28 +64,127 @@ pub struct GTE {
 }
 
 impl GTE {
+    const BK: usize = 13;
+    //bits in FLAG which also set the error bit (i.e. bit 31)
+    const ERROR_MASK: u32 = 0x7f87_e000;
+    const FC: usize = 21;
+    const FLAG: usize = 31;
+    const H: usize = 26;
+    const DQA: usize = 27;
+    const DQB: usize = 28;
+    const IR0: usize = 8;
+    const LCM: usize = 16;
+    const LLM: usize = 8;
+    const LZCR: usize = 31;
+    const LZCS: usize = 30;
+    const MAC0: usize = 24;
+    const OFX: usize = 24;
+    const OFY: usize = 25;
+    const OTZ: usize = 7;
+    const RGB0: usize = 20;
+    const RGBC: usize = 6;
+    const RT: usize = 0;
+    const SXY0: usize = 12;
+    const SZ0: usize = 16;
+    const TR: usize = 5;
+    const VXY0: usize = 0;
+    const VZ0: usize = 1;
+    const ZSF3: usize = 29;
+    const ZSF4: usize = 30;
+
     pub fn nth_data_reg(&self, idx: u32) -> u32 {
         assert!(idx < 32);
         let idx = idx as usize;
-        self.data_registers[idx]
+        let value = self.data_registers[idx];
+        match idx {
+            //VZ0-2 and IR0-3 are sign-extended
+            1 | 3 | 5 | 8..=11 => value.half_sign_extended(),
+            //OTZ and SZ0-3 are zero-extended
+            7 | 16..=19 => value.half(),
+            //SXYP mirrors the last entry in the SXY FIFO
+            15 => self.data_registers[GTE::SXY0 + 2],
+            //IRGB and ORGB both read back IR1-3 packed into a 15-bit color
+            28 | 29 => self.orgb(),
+            _ => value,
+        }
     }
 
-    pub fn nth_data_reg_mut(&mut self, idx: u32) -> Option<&mut u32> {
+    pub fn nth_data_reg_mut(&mut self, idx: u32) -> Option<GTEReg<'_>> {
         assert!(idx < 32);
-        let idx = idx as usize;
-        Some(&mut self.data_registers[idx])
+        Some(GTEReg::new(self, idx as usize, Bank::Data))
     }
 
     pub fn nth_ctrl_reg(&self, idx: u32) -> u32 {
         assert!(idx < 32);
         let idx = idx as usize;
-        self.ctrl_registers[idx]
+        let value = self.ctrl_registers[idx];
+        match idx {
+            //RT33, L33, LB3, H, DQA, ZSF3 and ZSF4 are 16-bit registers read as sign-extended
+            //values. H is unsigned but is still sign-extended due to a hardware bug
+            4 | 12 | 20 | 26 | 27 | 29 | 30 => value.half_sign_extended(),
+            _ => value,
+        }
     }
 
-    pub fn nth_ctrl_reg_mut(&mut self, idx: u32) -> Option<&mut u32> {
+    pub fn nth_ctrl_reg_mut(&mut self, idx: u32) -> Option<GTEReg<'_>> {
         assert!(idx < 32);
-        let idx = idx as usize;
-        Some(&mut self.ctrl_registers[idx])
+        Some(GTEReg::new(self, idx as usize, Bank::Control))
+    }
+
+    fn write_data_reg(&mut self, idx: usize, value: u32) {
+        match idx {
+            15 => {
+                //writing to SXYP pushes a new entry onto the SXY FIFO
+                self.data_registers[GTE::SXY0] = self.data_registers[GTE::SXY0 + 1];
+                self.data_registers[GTE::SXY0 + 1] = self.data_registers[GTE::SXY0 + 2];
+                self.data_registers[GTE::SXY0 + 2] = value;
+            },
+            28 => {
+                //IRGB expands a 15-bit color into IR1-3
+                for n in 0..3 {
+                    let component = value.range(5 * n as u32, 5 * n as u32 + 4);
+                    self.data_registers[GTE::IR0 + n + 1] = component * 0x80;
+                }
+            },
+            30 => {
+                //LZCR counts the leading bits of LZCS which match its sign bit
+                self.data_registers[GTE::LZCS] = value;
+                self.data_registers[GTE::LZCR] = if value.nth_bit_bool(31) {
+                    (!value).leading_zeros()
+                } else {
+                    value.leading_zeros()
+                };
+            },
+            //ORGB and LZCR are read-only
+            29 | 31 => {},
+            _ => {
+                self.data_registers[idx] = value;
+            },
+        }
+    }
+
+    fn write_ctrl_reg(&mut self, idx: usize, value: u32) {
+        match idx {
+            31 => {
+                //only bits 12-30 of FLAG are writable and bit 31 is always recomputed
+                let mut flag = value & 0x7fff_f000;
+                if flag & GTE::ERROR_MASK != 0 {
+                    flag.set(31);
+                }
+                self.ctrl_registers[GTE::FLAG] = flag;
+            },
+            _ => {
+                self.ctrl_registers[idx] = value;
+            },
+        }
+    }
+
+    //IR1-3 saturated and packed into a 15-bit color
+    fn orgb(&self) -> u32 {
+        (1..=3)
+            .map(|n| (self.ir(n) / 0x80).max(0).min(0x1f) as u32)
+            .enumerate()
+            .fold(0, |acc, (i, c)| acc | c << (5 * i))
     }
 
     pub fn bcnf(&self, imm16: u32) -> Option<u32> {
@@ -34,6 +192,641 @@ impl GTE {
     }
 
     pub fn execute_command(&mut self, imm25: u32) -> Option<u32> {
+        let shift = imm25.nth_bit(19) * 12;
+        let lm = imm25.nth_bit_bool(10);
+        self.ctrl_registers[GTE::FLAG] = 0;
+        match imm25.lowest_bits(6) {
+            0x01 => {
+                //RTPS
+                self.rtps(0, shift, lm, true);
+            },
+            0x06 => {
+                //NCLIP
+                self.nclip();
+            },
+            0x0c => {
+                //OP
+                self.op(shift, lm);
+            },
+            0x10 => {
+                //DPCS
+                let color = self.data_registers[GTE::RGBC];
+                self.dpcs(color, shift, lm);
+            },
+            0x11 => {
+                //INTPL
+                self.intpl(shift, lm);
+            },
+            0x12 => {
+                //MVMVA
+                let matrix = match imm25.range(17, 18) {
+                    0 => Matrix::Rotation,
+                    1 => Matrix::Light,
+                    2 => Matrix::Color,
+                    _ => Matrix::Reserved,
+                };
+                let vector = match imm25.range(15, 16) {
+                    3 => Vector::IR,
+                    n => Vector::V(n as usize),
+                };
+                let translation = match imm25.range(13, 14) {
+                    0 => Translation::TR,
+                    1 => Translation::BK,
+                    2 => Translation::FC,
+                    _ => Translation::None,
+                };
+                self.mvmva(matrix, vector, translation, shift, lm);
+            },
+            0x13 => {
+                //NCDS
+                self.ncds(0, shift, lm);
+            },
+            0x14 => {
+                //CDP
+                self.cdp(shift, lm);
+            },
+            0x16 => {
+                //NCDT
+                for n in 0..3 {
+                    self.ncds(n, shift, lm);
+                }
+            },
+            0x1b => {
+                //NCCS
+                self.nccs(0, shift, lm);
+            },
+            0x1c => {
+                //CC
+                self.cc(shift, lm);
+            },
+            0x1e => {
+                //NCS
+                self.ncs(0, shift, lm);
+            },
+            0x20 => {
+                //NCT
+                for n in 0..3 {
+                    self.ncs(n, shift, lm);
+                }
+            },
+            0x28 => {
+                //SQR
+                self.sqr(shift, lm);
+            },
+            0x29 => {
+                //DCPL
+                self.dcpl(shift, lm);
+            },
+            0x2a => {
+                //DPCT
+                //this always uses the first entry since the color FIFO advances after each step
+                for _ in 0..3 {
+                    let color = self.data_registers[GTE::RGB0];
+                    self.dpcs(color, shift, lm);
+                }
+            },
+            0x2d => {
+                //AVSZ3
+                self.avsz(GTE::ZSF3, 1);
+            },
+            0x2e => {
+                //AVSZ4
+                self.avsz(GTE::ZSF4, 0);
+            },
+            0x30 => {
+                //RTPT
+                for n in 0..3 {
+                    self.rtps(n, shift, lm, n == 2);
+                }
+            },
+            0x3d => {
+                //GPF
+                self.gpf(shift, lm);
+            },
+            0x3e => {
+                //GPL
+                self.gpl(shift, lm);
+            },
+            0x3f => {
+                //NCCT
+                for n in 0..3 {
+                    self.nccs(n, shift, lm);
+                }
+            },
+            //unused command numbers don't do anything
+            _ => {},
+        }
+        if self.ctrl_registers[GTE::FLAG] & GTE::ERROR_MASK != 0 {
+            self.ctrl_registers[GTE::FLAG].set(31);
+        }
         None
     }
+
+    //perspective transformation of vector n
+    fn rtps(&mut self, n: usize, shift: u32, lm: bool, last: bool) {
+        let rt = self.matrix(Matrix::Rotation);
+        let v = self.vector(Vector::V(n));
+        let tr = self.translation(Translation::TR);
+        let mut z = 0;
+        for row in 0..3 {
+            let mut acc = self.check_mac(row + 1, tr[row] << 12);
+            for col in 0..3 {
+                acc = self.check_mac(row + 1, acc + rt[row][col] * v[col]);
+            }
+            let mac = self.set_mac(row + 1, acc, shift);
+            if row < 2 {
+                self.set_ir(row + 1, mac, lm);
+            } else {
+                //the IR3 saturation flag is set as if sf were 1 and lm were 0
+                z = acc >> 12;
+                if z < -0x8000 || z > 0x7fff {
+                    self.set_flag(22);
+                }
+                let min = if lm { 0 } else { -0x8000 };
+                self.data_registers[GTE::IR0 + 3] = mac.max(min).min(0x7fff) as u32;
+            }
+        }
+        let sz3 = self.saturate(z, 0, 0xffff, 18);
+        for i in 0..3 {
+            self.data_registers[GTE::SZ0 + i] = self.data_registers[GTE::SZ0 + i + 1];
+        }
+        self.data_registers[GTE::SZ0 + 3] = sz3 as u32;
+        let div = self.divide(sz3);
+        let ofx = self.ctrl_registers[GTE::OFX] as i32 as i64;
+        let ofy = self.ctrl_registers[GTE::OFY] as i32 as i64;
+        let sx = self.set_mac0(div * self.ir(1) + ofx) >> 16;
+        let sx = self.saturate(sx, -0x400, 0x3ff, 14);
+        let sy = self.set_mac0(div * self.ir(2) + ofy) >> 16;
+        let sy = self.saturate(sy, -0x400, 0x3ff, 13);
+        for i in 0..2 {
+            self.data_registers[GTE::SXY0 + i] = self.data_registers[GTE::SXY0 + i + 1];
+        }
+        self.data_registers[GTE::SXY0 + 2] = (sx as u32).half() | (sy as u32) << 16;
+        if last {
+            let dqa = self.ctrl_registers[GTE::DQA].half() as i16 as i64;
+            let dqb = self.ctrl_registers[GTE::DQB] as i32 as i64;
+            let mac0 = self.set_mac0(div * dqa + dqb);
+            self.set_ir0(mac0 >> 12);
+        }
+    }
+
+    //normal clipping, i.e. the winding of the triangle in the SXY FIFO
+    fn nclip(&mut self) {
+        let (x0, y0) = self.sxy(0);
+        let (x1, y1) = self.sxy(1);
+        let (x2, y2) = self.sxy(2);
+        let value = x0 * y1 + x1 * y2 + x2 * y0 - x0 * y2 - x1 * y0 - x2 * y1;
+        self.set_mac0(value);
+    }
+
+    //outer product of the rotation matrix diagonal and IR
+    fn op(&mut self, shift: u32, lm: bool) {
+        let rt = self.matrix(Matrix::Rotation);
+        let d = [rt[0][0], rt[1][1], rt[2][2]];
+        let ir = self.vector(Vector::IR);
+        let mac = [
+            ir[2] * d[1] - ir[1] * d[2],
+            ir[0] * d[2] - ir[2] * d[0],
+            ir[1] * d[0] - ir[0] * d[1],
+        ];
+        self.set_mac_ir(mac, shift, lm);
+    }
+
+    //depth cueing of a single color
+    fn dpcs(&mut self, color: u32, shift: u32, lm: bool) {
+        let mac = [
+            (color.byte() as i64) << 16,
+            (color.range(8, 15) as i64) << 16,
+            (color.range(16, 23) as i64) << 16,
+        ];
+        self.interpolate(mac, shift, lm);
+        self.push_color();
+    }
+
+    //interpolation of IR and the far color
+    fn intpl(&mut self, shift: u32, lm: bool) {
+        let ir = self.vector(Vector::IR);
+        let mac = [ir[0] << 12, ir[1] << 12, ir[2] << 12];
+        self.interpolate(mac, shift, lm);
+        self.push_color();
+    }
+
+    fn mvmva(
+        &mut self, matrix: Matrix, vector: Vector, translation: Translation, shift: u32, lm: bool,
+    ) {
+        let m = self.matrix(matrix);
+        let v = self.vector(vector);
+        let t = self.translation(translation);
+        if translation == Translation::FC {
+            //the far color translation is buggy and only the flags from the first column are kept
+            let mut mac = [0; 3];
+            for row in 0..3 {
+                let partial = self.check_mac(row + 1, (t[row] << 12) + m[row][0] * v[0]);
+                self.set_ir(row + 1, partial >> shift, false);
+                let acc = self.check_mac(row + 1, m[row][1] * v[1]);
+                mac[row] = self.check_mac(row + 1, acc + m[row][2] * v[2]);
+            }
+            self.set_mac_ir(mac, shift, lm);
+        } else {
+            self.multiply_matrix_by_vector(m, v, t, shift, lm);
+        }
+    }
+
+    //normal color depth cue of vector n
+    fn ncds(&mut self, n: usize, shift: u32, lm: bool) {
+        self.light(n, shift, lm);
+        self.depth_cue(shift, lm);
+        self.push_color();
+    }
+
+    //color depth cue
+    fn cdp(&mut self, shift: u32, lm: bool) {
+        self.light_color(shift, lm);
+        self.depth_cue(shift, lm);
+        self.push_color();
+    }
+
+    //normal color color of vector n
+    fn nccs(&mut self, n: usize, shift: u32, lm: bool) {
+        self.light(n, shift, lm);
+        self.color_product(shift, lm);
+        self.push_color();
+    }
+
+    //color color
+    fn cc(&mut self, shift: u32, lm: bool) {
+        self.light_color(shift, lm);
+        self.color_product(shift, lm);
+        self.push_color();
+    }
+
+    //normal color of vector n
+    fn ncs(&mut self, n: usize, shift: u32, lm: bool) {
+        self.light(n, shift, lm);
+        self.push_color();
+    }
+
+    //square of IR
+    fn sqr(&mut self, shift: u32, lm: bool) {
+        let ir = self.vector(Vector::IR);
+        let mac = [ir[0] * ir[0], ir[1] * ir[1], ir[2] * ir[2]];
+        self.set_mac_ir(mac, shift, lm);
+    }
+
+    //depth cue color light
+    fn dcpl(&mut self, shift: u32, lm: bool) {
+        self.depth_cue(shift, lm);
+        self.push_color();
+    }
+
+    //average of the last three or four entries in the SZ FIFO
+    fn avsz(&mut self, factor: usize, first: usize) {
+        let zsf = self.ctrl_registers[factor].half() as i16 as i64;
+        let sum = (first..4)
+            .map(|i| self.data_registers[GTE::SZ0 + i].half() as i64)
+            .sum::<i64>();
+        let mac0 = self.set_mac0(zsf * sum);
+        let otz = self.saturate(mac0 >> 12, 0, 0xffff, 18);
+        self.data_registers[GTE::OTZ] = otz as u32;
+    }
+
+    //general purpose interpolation
+    fn gpf(&mut self, shift: u32, lm: bool) {
+        let ir = self.vector(Vector::IR);
+        let ir0 = self.ir(0);
+        let mac = [ir[0] * ir0, ir[1] * ir0, ir[2] * ir0];
+        self.set_mac_ir(mac, shift, lm);
+        self.push_color();
+    }
+
+    //general purpose interpolation with base
+    fn gpl(&mut self, shift: u32, lm: bool) {
+        let ir = self.vector(Vector::IR);
+        let ir0 = self.ir(0);
+        let mut mac = [0; 3];
+        for n in 0..3 {
+            let base = self.mac(n + 1) << shift;
+            mac[n] = self.check_mac(n + 1, base + ir[n] * ir0);
+        }
+        self.set_mac_ir(mac, shift, lm);
+        self.push_color();
+    }
+
+    //IR = BK + LCM * (LLM * V)
+    fn light(&mut self, n: usize, shift: u32, lm: bool) {
+        let llm = self.matrix(Matrix::Light);
+        let v = self.vector(Vector::V(n));
+        self.multiply_matrix_by_vector(llm, v, [0; 3], shift, lm);
+        self.light_color(shift, lm);
+    }
+
+    //IR = BK + LCM * IR
+    fn light_color(&mut self, shift: u32, lm: bool) {
+        let lcm = self.matrix(Matrix::Color);
+        let ir = self.vector(Vector::IR);
+        let bk = self.translation(Translation::BK);
+        self.multiply_matrix_by_vector(lcm, ir, bk, shift, lm);
+    }
+
+    //MAC = (RGB * IR) << 4
+    fn color_product(&mut self, shift: u32, lm: bool) {
+        let mac = self.rgb_times_ir();
+        self.set_mac_ir(mac, shift, lm);
+    }
+
+    //MAC = (RGB * IR) << 4 interpolated towards the far color
+    fn depth_cue(&mut self, shift: u32, lm: bool) {
+        let mac = self.rgb_times_ir();
+        self.interpolate(mac, shift, lm);
+    }
+
+    fn rgb_times_ir(&self) -> [i64; 3] {
+        let color = self.data_registers[GTE::RGBC];
+        let ir = self.vector(Vector::IR);
+        [
+            (color.byte() as i64 * ir[0]) << 4,
+            (color.range(8, 15) as i64 * ir[1]) << 4,
+            (color.range(16, 23) as i64 * ir[2]) << 4,
+        ]
+    }
+
+    //MAC = MAC + (FC - MAC) * IR0
+    fn interpolate(&mut self, mac: [i64; 3], shift: u32, lm: bool) {
+        let fc = self.translation(Translation::FC);
+        for n in 0..3 {
+            let diff = self.check_mac(n + 1, (fc[n] << 12) - mac[n]) >> shift;
+            self.set_ir(n + 1, diff, false);
+        }
+        let ir0 = self.ir(0);
+        let mut result = [0; 3];
+        for n in 0..3 {
+            result[n] = self.check_mac(n + 1, self.ir(n + 1) * ir0 + mac[n]);
+        }
+        self.set_mac_ir(result, shift, lm);
+    }
+
+    fn multiply_matrix_by_vector(
+        &mut self, m: [[i64; 3]; 3], v: [i64; 3], t: [i64; 3], shift: u32, lm: bool,
+    ) {
+        let mut mac = [0; 3];
+        for row in 0..3 {
+            let mut acc = self.check_mac(row + 1, t[row] << 12);
+            for col in 0..3 {
+                acc = self.check_mac(row + 1, acc + m[row][col] * v[col]);
+            }
+            mac[row] = acc;
+        }
+        self.set_mac_ir(mac, shift, lm);
+    }
+
+    //unsigned Newton-Raphson division of H by SZ3
+    fn divide(&mut self, sz3: i64) -> i64 {
+        let h = self.ctrl_registers[GTE::H].half() as i64;
+        if h < sz3 * 2 {
+            let z = (sz3 as u16).leading_zeros();
+            let n = h << z;
+            let d = sz3 << z;
+            let u = GTE::unr_table(((d - 0x7fc0) >> 7) as usize) + 0x101;
+            let d = (0x200_0080 - d * u) >> 8;
+            let d = (0x000_0080 + d * u) >> 8;
+            ((n * d + 0x8000) >> 16).min(0x1_ffff)
+        } else {
+            self.set_flag(17);
+            0x1_ffff
+        }
+    }
+
+    //the 257 entry table used as the initial guess for division
+    fn unr_table(idx: usize) -> i64 {
+        assert!(idx <= 0x100);
+        ((0x4_0000 / (idx as i64 + 0x100) + 1) / 2 - 0x101).max(0)
+    }
+
+    fn push_color(&mut self) {
+        let mut color = self.data_registers[GTE::RGBC] & 0xff00_0000;
+        for n in 0..3 {
+            let component = self.mac(n + 1) >> 4;
+            let component = self.saturate(component, 0, 0xff, 21 - n as u32);
+            color |= (component as u32) << (8 * n);
+        }
+        self.data_registers[GTE::RGB0] = self.data_registers[GTE::RGB0 + 1];
+        self.data_registers[GTE::RGB0 + 1] = self.data_registers[GTE::RGB0 + 2];
+        self.data_registers[GTE::RGB0 + 2] = color;
+    }
+
+    fn matrix(&self, matrix: Matrix) -> [[i64; 3]; 3] {
+        let base = match matrix {
+            Matrix::Rotation => GTE::RT,
+            Matrix::Light => GTE::LLM,
+            Matrix::Color => GTE::LCM,
+            Matrix::Reserved => {
+                let r = self.data_registers[GTE::RGBC].byte() as i64;
+                let rt = self.matrix(Matrix::Rotation);
+                return [
+                    [-(r << 4), r << 4, self.ir(0)],
+                    [rt[0][2]; 3],
+                    [rt[1][1]; 3],
+                ]
+            },
+        };
+        let mut m = [[0; 3]; 3];
+        for row in 0..3 {
+            for col in 0..3 {
+                let k = row * 3 + col;
+                let word = self.ctrl_registers[base + k / 2];
+                let element = if k % 2 == 0 { word.half() } else { word >> 16 };
+                m[row][col] = element as i16 as i64;
+            }
+        }
+        m
+    }
+
+    fn vector(&self, vector: Vector) -> [i64; 3] {
+        match vector {
+            Vector::V(n) => {
+                let xy = self.data_registers[GTE::VXY0 + 2 * n];
+                let z = self.data_registers[GTE::VZ0 + 2 * n];
+                [
+                    xy.half() as i16 as i64,
+                    (xy >> 16) as i16 as i64,
+                    z.half() as i16 as i64,
+                ]
+            },
+            Vector::IR => [self.ir(1), self.ir(2), self.ir(3)],
+        }
+    }
+
+    fn translation(&self, translation: Translation) -> [i64; 3] {
+        let base = match translation {
+            Translation::TR => GTE::TR,
+            Translation::BK => GTE::BK,
+            Translation::FC => GTE::FC,
+            Translation::None => return [0; 3],
+        };
+        [
+            self.ctrl_registers[base] as i32 as i64,
+            self.ctrl_registers[base + 1] as i32 as i64,
+            self.ctrl_registers[base + 2] as i32 as i64,
+        ]
+    }
+
+    fn ir(&self, n: usize) -> i64 {
+        self.data_registers[GTE::IR0 + n].half() as i16 as i64
+    }
+
+    fn mac(&self, n: usize) -> i64 {
+        self.data_registers[GTE::MAC0 + n] as i32 as i64
+    }
+
+    fn sxy(&self, n: usize) -> (i64, i64) {
+        let sxy = self.data_registers[GTE::SXY0 + n];
+        (sxy.half() as i16 as i64, (sxy >> 16) as i16 as i64)
+    }
+
+    fn set_flag(&mut self, n: u32) {
+        self.ctrl_registers[GTE::FLAG].set(n);
+    }
+
+    fn saturate(&mut self, value: i64, min: i64, max: i64, flag: u32) -> i64 {
+        if value < min {
+            self.set_flag(flag);
+            min
+        } else if value > max {
+            self.set_flag(flag);
+            max
+        } else {
+            value
+        }
+    }
+
+    //MAC1-3 are 44-bit accumulators so this flags overflows and truncates the value
+    fn check_mac(&mut self, n: usize, value: i64) -> i64 {
+        if value >= (1 << 43) {
+            self.set_flag(31 - n as u32);
+        } else if value < -(1 << 43) {
+            self.set_flag(28 - n as u32);
+        }
+        (value << 20) >> 20
+    }
+
+    fn set_mac(&mut self, n: usize, value: i64, shift: u32) -> i64 {
+        let value = self.check_mac(n, value) >> shift;
+        self.data_registers[GTE::MAC0 + n] = value as u32;
+        value
+    }
+
+    fn set_mac0(&mut self, value: i64) -> i64 {
+        if value > 0x7fff_ffff {
+            self.set_flag(16);
+        } else if value < -0x8000_0000 {
+            self.set_flag(15);
+        }
+        self.data_registers[GTE::MAC0] = value as u32;
+        value
+    }
+
+    fn set_mac_ir(&mut self, mac: [i64; 3], shift: u32, lm: bool) {
+        for n in 0..3 {
+            let value = self.set_mac(n + 1, mac[n], shift);
+            self.set_ir(n + 1, value, lm);
+        }
+    }
+
+    fn set_ir(&mut self, n: usize, value: i64, lm: bool) {
+        let min = if lm { 0 } else { -0x8000 };
+        let value = self.saturate(value, min, 0x7fff, 25 - n as u32);
+        self.data_registers[GTE::IR0 + n] = value as u32;
+    }
+
+    fn set_ir0(&mut self, value: i64) {
+        let value = self.saturate(value, 0, 0x1000, 12);
+        self.data_registers[GTE::IR0] = value as u32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unr_table_endpoints() {
+        assert_eq!(GTE::unr_table(0), 0xff);
+        assert_eq!(GTE::unr_table(0x100), 0);
+    }
+
+    #[test]
+    fn nclip_winding() {
+        let mut gte: GTE = Default::default();
+        gte.nth_data_reg_mut(12).maybe_set(0);
+        gte.nth_data_reg_mut(13).maybe_set(10);
+        gte.nth_data_reg_mut(14).maybe_set(10 << 16);
+        gte.execute_command(0x0140_0006);
+        assert_eq!(gte.nth_data_reg(24), 100);
+        assert_eq!(gte.nth_ctrl_reg(31), 0);
+    }
+
+    #[test]
+    fn rtps_identity() {
+        let mut gte: GTE = Default::default();
+        //identity rotation matrix
+        gte.nth_ctrl_reg_mut(0).maybe_set(0x1000);
+        gte.nth_ctrl_reg_mut(2).maybe_set(0x1000);
+        gte.nth_ctrl_reg_mut(4).maybe_set(0x1000);
+        //projection plane distance
+        gte.nth_ctrl_reg_mut(26).maybe_set(0x100);
+        //V0 = (0x10, 0x20, 0x200)
+        gte.nth_data_reg_mut(0).maybe_set(0x0020_0010);
+        gte.nth_data_reg_mut(1).maybe_set(0x200);
+        gte.execute_command(0x0148_0001);
+        assert_eq!(gte.nth_data_reg(19), 0x200);
+        assert_eq!(gte.nth_data_reg(14), 0x0010_0008);
+        assert_eq!(gte.nth_ctrl_reg(31), 0);
+    }
+
+    #[test]
+    fn divide_overflow_sets_flag() {
+        let mut gte: GTE = Default::default();
+        gte.nth_ctrl_reg_mut(26).maybe_set(0x1000);
+        gte.execute_command(0x0148_0001);
+        assert!(gte.nth_ctrl_reg(31).nth_bit_bool(17));
+        assert!(gte.nth_ctrl_reg(31).nth_bit_bool(31));
+    }
+
+    #[test]
+    fn avsz3_average() {
+        let mut gte: GTE = Default::default();
+        gte.nth_ctrl_reg_mut(29).maybe_set(0x555);
+        for i in 17..20 {
+            gte.nth_data_reg_mut(i).maybe_set(0x300);
+        }
+        gte.execute_command(0x0158_002d);
+        assert_eq!(gte.nth_data_reg(7), (0x555 * 0x900) >> 12);
+    }
+
+    #[test]
+    fn leading_bit_count() {
+        let mut gte: GTE = Default::default();
+        gte.nth_data_reg_mut(30).maybe_set(0x0000_ffff);
+        assert_eq!(gte.nth_data_reg(31), 16);
+        gte.nth_data_reg_mut(30).maybe_set(0xff00_0000);
+        assert_eq!(gte.nth_data_reg(31), 8);
+    }
+
+    #[test]
+    fn irgb_round_trip() {
+        let mut gte: GTE = Default::default();
+        gte.nth_data_reg_mut(28).maybe_set(0x7c1f);
+        assert_eq!(gte.nth_data_reg(9), 0xf80);
+        assert_eq!(gte.nth_data_reg(10), 0);
+        assert_eq!(gte.nth_data_reg(29), 0x7c1f);
+    }
+
+    #[test]
+    fn sign_extended_reads() {
+        let mut gte: GTE = Default::default();
+        gte.nth_data_reg_mut(1).maybe_set(0x0000_8000);
+        gte.nth_ctrl_reg_mut(26).maybe_set(0x0000_ffff);
+        assert_eq!(gte.nth_data_reg(1), 0xffff_8000);
+        assert_eq!(gte.nth_ctrl_reg(26), 0xffff_ffff);
+    }
 }
@@ -1,105 +1,136 @@
-use super::dma::{Chunks, DMAChannel, Direction, Step, Transfer};
+use super::dma::{Chunks, DMAChannel, Direction, Step, Transfer, DMA};
+use super::interrupts::IRQ_DMA;
 use super::memory::Memory;
+use super::scheduler::Event;
 use crate::console::Console;
-use crate::register::BitTwiddle;
 
 impl Console {
-    pub(super) fn handle_dma(&mut self, transfer: Transfer) {
-        let addr_mask = 0x00ff_fffc;
+    //starts the highest priority transfer if no channel is busy
+    pub(super) fn start_next_dma(&mut self) {
+        let control = self.memory.dma_control();
+        //MDEC output waits until a macroblock has been decoded
+        let mdec_ready = self.mdec.output_ready();
+        let ready = |channel| channel != 1 || mdec_ready;
+        let (channel, start_address, words) = match self.dma.start_next(control, ready) {
+            Some(transfer) => {
+                let words = match transfer.chunks() {
+                    Chunks::NumWords(num) => Some(*num),
+                    Chunks::Blocks(blocks) => {
+                        Some(blocks.num_blocks() as u32 * blocks.block_size() as u32)
+                    },
+                    Chunks::LinkedList => None,
+                };
+                (transfer.channel_num(), transfer.start_address(), words)
+            },
+            None => return,
+        };
+        let words = words.unwrap_or_else(|| {
+            //each node takes a cycle for its header and one for each word in the packet
+            DMA::linked_list(start_address, |address| self.read_word(address))
+                .iter()
+                .map(|&address| (self.read_word(address) >> 24) + 1)
+                .sum()
+        });
+        //the channel is busy for about a cycle per word and the data moves at the end
+        self.scheduler
+            .schedule(Event::DMA(channel), words as u64 + 1);
+    }
+
+    //moves the data for the transfer and clears the busy bit of its channel once it's
+    // had time to finish
+    pub(super) fn finish_dma(&mut self, channel: u8) {
+        if let Some(transfer) = self.dma.take_active() {
+            self.handle_dma(transfer);
+        }
+        self.memory.reset_dma_channel(channel);
+        if self.dma.finish(channel) {
+            self.request_interrupt(IRQ_DMA);
+        }
+        self.start_next_dma();
+    }
+
+    //moves the data for a transfer
+    fn handle_dma(&mut self, transfer: Transfer) {
+        let madr = Memory::DMA_ADDRESS_0 + (transfer.channel_num() as u32 * 0x10);
+        match transfer.chunks() {
+            Chunks::NumWords(num) => {
+                self.dma_words(&transfer, *num);
+            },
+            Chunks::Blocks(blocks) => {
+                let num = blocks.num_blocks() as u32 * blocks.block_size() as u32;
+                let addr = self.dma_words(&transfer, num);
+                //the address and the number of blocks left are updated as blocks go out
+                self.memory.write_word(madr, addr);
+                self.memory
+                    .write_word(madr + 4, blocks.block_size() as u32);
+            },
+            Chunks::LinkedList => {
+                self.dma_linked_list(&transfer);
+                self.memory.write_word(madr, 0x00ff_ffff);
+            },
+        }
+    }
+
+    //copies a contiguous range of words and returns the address after the last one
+    fn dma_words(&mut self, transfer: &Transfer, num: u32) -> u32 {
+        let addr_mask = 0x001f_fffc;
         let step = |address: u32| match transfer.step() {
             Step::Forward => address.wrapping_add(4) & addr_mask,
             Step::Backward => address.wrapping_sub(4) & addr_mask,
         };
-        let undo_step = |address: u32| match transfer.step() {
-            Step::Forward => address.wrapping_sub(4) & addr_mask,
-            Step::Backward => address.wrapping_add(4) & addr_mask,
-        };
         let mut addr = transfer.start_address() & addr_mask;
         match transfer.direction() {
-            Direction::ToRAM => match transfer.channel_num() {
-                6 => match transfer.chunks() {
-                    Chunks::NumWords(num) => {
-                        for i in 1..=*num {
-                            let remaining = *num - i;
-                            let data = match remaining {
-                                0 => 0x00ff_ffff,
-                                _ => addr.wrapping_sub(4) & addr_mask,
-                            };
-                            self.memory.write_word(addr, data);
-                            addr = step(addr);
-                        }
-                    },
-                    _ => todo!("implement DMA {:#x?}", transfer),
-                },
-                _ => todo!("implement DMA {:#x?}", transfer),
+            Direction::ToRAM => {
+                let data = match transfer.channel_num() {
+                    6 => DMA::ordering_table(addr, num),
+                    channel => self
+                        .get_dma_channel(channel)
+                        .map_or_else(|| vec![0; num as usize], |c| c.receive(num)),
+                };
+                for word in data {
+                    self.memory.write_word(addr, word);
+                    self.overwritten.insert(addr);
+                    addr = step(addr);
+                }
             },
             Direction::FromRAM => {
-                let channel_available = self.get_dma_channel(transfer.channel_num()).is_some();
-                if channel_available {
-                    let mut buffer = Vec::new();
-                    match transfer.chunks() {
-                        Chunks::NumWords(num) => {
-                            for _ in 1..=*num {
-                                let data = self.read_word(addr);
-                                buffer.push(data);
-                                addr = step(addr);
-                            }
-                        },
-                        Chunks::Blocks(blocks) => {
-                            let packet_size = blocks.num_blocks() * blocks.block_size();
-                            for _ in 1..=packet_size {
-                                let data = self.read_word(addr);
-                                buffer.push(data);
-                                addr = step(addr);
-                            }
-                            addr = undo_step(addr);
-                            self.memory.write_word(
-                                Memory::DMA_ADDRESS_0 + (transfer.channel_num() as u32 * 0x10),
-                                addr,
-                            );
-                        },
-                        Chunks::LinkedList => {
-                            let mut header_address = addr;
-                            loop {
-                                let header = self.read_word(header_address);
-                                let packet_size = header >> 24;
-                                for _ in 0..=packet_size {
-                                    addr = step(addr);
-                                    let data = self.read_word(addr);
-                                    buffer.push(data);
-                                }
-                                let next_packet = header.lowest_bits(24);
-                                if next_packet == 0x00ff_ffff {
-                                    break
-                                } else {
-                                    header_address = next_packet & addr_mask;
-                                    addr = header_address;
-                                }
-                            }
-                            self.memory.write_word(
-                                Memory::DMA_ADDRESS_0 + (transfer.channel_num() as u32 * 0x10),
-                                0x00ff_ffff,
-                            );
-                        },
-                    }
-                    //println!("sending {:#x?} through a DMA", buffer);
-                    self.get_dma_channel(transfer.channel_num())
-                        .map(|channel| channel.send(buffer));
+                let mut buffer = Vec::with_capacity(num as usize);
+                for _ in 0..num {
+                    buffer.push(self.read_word(addr));
+                    addr = step(addr);
                 }
+                self.get_dma_channel(transfer.channel_num())
+                    .map(|channel| channel.send(buffer));
             },
         }
-        self.memory.reset_dma_channel(transfer.channel_num());
+        addr
+    }
+
+    //only the GPU reads linked lists, each node is a header with the number of
+    // words in the packet and the address of the next node
+    fn dma_linked_list(&mut self, transfer: &Transfer) {
+        let addr_mask = 0x001f_fffc;
+        let mut buffer = Vec::new();
+        let headers = DMA::linked_list(transfer.start_address(), |address| self.read_word(address));
+        for header_address in headers {
+            let packet_size = self.read_word(header_address) >> 24;
+            for i in 1..=packet_size {
+                let data = self.read_word((header_address + 4 * i) & addr_mask);
+                buffer.push(data);
+            }
+        }
+        self.get_dma_channel(transfer.channel_num())
+            .map(|channel| channel.send(buffer));
     }
 
+    //channel 5 goes to the expansion port which has nothing plugged in
     fn get_dma_channel(&mut self, channel_num: u8) -> Option<&mut dyn DMAChannel> {
         match channel_num {
+            0 | 1 => Some(&mut self.mdec),
             2 => Some(&mut self.gpu),
             3 => Some(&mut self.cd),
-            6 => Some(&mut self.memory),
-            _ => todo!(
-                "implement get_dma_channel() for channel num {}",
-                channel_num
-            ),
+            4 => Some(&mut self.spu),
+            _ => None,
         }
     }
 }
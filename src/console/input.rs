@@ -0,0 +1,212 @@
+use super::sio::{Button, Stick, SIO};
+use sdl2::controller::{Axis, Button as ControllerButton, GameController};
+use sdl2::event::Event;
+use sdl2::haptic::Haptic;
+use sdl2::keyboard::Keycode;
+use sdl2::{GameControllerSubsystem, HapticSubsystem};
+use std::collections::HashMap;
+
+//the keyboard always controls the pad in the first slot
+fn key_button(keycode: Keycode) -> Option<Button> {
+    match keycode {
+        Keycode::S => Some(Button::Cross),
+        Keycode::D => Some(Button::Circle),
+        Keycode::A => Some(Button::Square),
+        Keycode::W => Some(Button::Triangle),
+        Keycode::K => Some(Button::Down),
+        Keycode::L => Some(Button::Right),
+        Keycode::J => Some(Button::Left),
+        Keycode::I => Some(Button::Up),
+        Keycode::Return => Some(Button::Start),
+        Keycode::Backspace => Some(Button::Select),
+        Keycode::Q => Some(Button::L1),
+        Keycode::E => Some(Button::R1),
+        Keycode::Num1 => Some(Button::L2),
+        Keycode::Num3 => Some(Button::R2),
+        _ => None,
+    }
+}
+
+fn controller_button(button: ControllerButton) -> Option<Button> {
+    match button {
+        ControllerButton::A => Some(Button::Cross),
+        ControllerButton::B => Some(Button::Circle),
+        ControllerButton::X => Some(Button::Square),
+        ControllerButton::Y => Some(Button::Triangle),
+        ControllerButton::Back => Some(Button::Select),
+        ControllerButton::Start => Some(Button::Start),
+        ControllerButton::LeftStick => Some(Button::L3),
+        ControllerButton::RightStick => Some(Button::R3),
+        ControllerButton::LeftShoulder => Some(Button::L1),
+        ControllerButton::RightShoulder => Some(Button::R1),
+        ControllerButton::DPadUp => Some(Button::Up),
+        ControllerButton::DPadDown => Some(Button::Down),
+        ControllerButton::DPadLeft => Some(Button::Left),
+        ControllerButton::DPadRight => Some(Button::Right),
+        _ => None,
+    }
+}
+
+//SDL's axes go from -32768 to 32767 and the pad's go from 0 to 255
+fn stick_value(value: i16) -> u8 {
+    ((value as i32 + 0x8000) >> 8) as u8
+}
+
+struct Controller {
+    //the controller is closed when this is dropped
+    _controller: GameController,
+    haptic: Option<Haptic>,
+    slot: usize,
+    rumbling: bool,
+}
+
+//turns keyboard and game controller events into button presses on the pads
+pub struct Input {
+    subsystem: Option<GameControllerSubsystem>,
+    haptic_subsystem: Option<HapticSubsystem>,
+    //open controllers keyed by instance ID
+    controllers: HashMap<u32, Controller>,
+}
+
+impl Input {
+    //rumble is refreshed every frame so it stops on its own if the emulator stalls
+    const RUMBLE_DURATION: u32 = 100;
+
+    pub fn new(sdl: &sdl2::Sdl) -> Self {
+        let subsystem = match sdl.game_controller() {
+            Ok(subsystem) => Some(subsystem),
+            Err(e) => {
+                println!("Unable to use game controllers ({}), only the keyboard works", e);
+                None
+            },
+        };
+        Input {
+            subsystem,
+            haptic_subsystem: sdl.haptic().ok(),
+            controllers: HashMap::new(),
+        }
+    }
+
+    //SDL sends an event for each controller that's already plugged in at startup
+    fn add_controller(&mut self, index: u32, sio: &mut SIO) {
+        let subsystem = match &self.subsystem {
+            Some(subsystem) => subsystem,
+            None => return,
+        };
+        //the first two controllers go in slots 1 and 2
+        let slot = (0..2).find(|&slot| self.controllers.values().all(|c| c.slot != slot));
+        let slot = match slot {
+            Some(slot) => slot,
+            None => return,
+        };
+        match subsystem.open(index) {
+            Ok(controller) => {
+                println!("Using {} as controller {}", controller.name(), slot + 1);
+                sio.pad_mut(slot).connect();
+                //controllers without rumble just don't get a haptic device
+                let haptic = self
+                    .haptic_subsystem
+                    .as_ref()
+                    .and_then(|haptic| haptic.open_from_joystick_id(index).ok());
+                //events carry the instance ID as a u32 but SDL hands it out as an i32
+                let id = controller.instance_id() as u32;
+                let controller = Controller {
+                    _controller: controller,
+                    haptic,
+                    slot,
+                    rumbling: false,
+                };
+                self.controllers.insert(id, controller);
+            },
+            Err(e) => println!("Unable to open controller {}: {}", index, e),
+        }
+    }
+
+    //the keyboard keeps the first pad connected but the second one is unplugged
+    fn remove_controller(&mut self, id: u32, sio: &mut SIO) {
+        if let Some(controller) = self.controllers.remove(&id) {
+            println!("Controller {} was removed", controller.slot + 1);
+            let pad = sio.pad_mut(controller.slot);
+            pad.release();
+            if controller.slot == 1 {
+                pad.disconnect();
+            }
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &Event, sio: &mut SIO) {
+        match *event {
+            Event::KeyDown {
+                keycode: Some(keycode),
+                ..
+            } => {
+                if let Some(button) = key_button(keycode) {
+                    sio.pad_mut(0).set_button(button, true);
+                }
+            },
+            Event::KeyUp {
+                keycode: Some(keycode),
+                ..
+            } => {
+                if let Some(button) = key_button(keycode) {
+                    sio.pad_mut(0).set_button(button, false);
+                }
+            },
+            Event::ControllerDeviceAdded { which, .. } => self.add_controller(which, sio),
+            Event::ControllerDeviceRemoved { which, .. } => self.remove_controller(which, sio),
+            Event::ControllerButtonDown { which, button, .. } |
+            Event::ControllerButtonUp { which, button, .. } => {
+                let pressed = matches!(event, Event::ControllerButtonDown { .. });
+                let slot = match self.controllers.get(&which) {
+                    Some(controller) => controller.slot,
+                    None => return,
+                };
+                let pad = sio.pad_mut(slot);
+                match controller_button(button) {
+                    Some(button) => pad.set_button(button, pressed),
+                    //the guide button stands in for the analog button
+                    None if button == ControllerButton::Guide && pressed => pad.toggle_analog(),
+                    None => {},
+                }
+            },
+            Event::ControllerAxisMotion {
+                which, axis, value, ..
+            } => {
+                let slot = match self.controllers.get(&which) {
+                    Some(controller) => controller.slot,
+                    None => return,
+                };
+                let pad = sio.pad_mut(slot);
+                match axis {
+                    Axis::LeftX => pad.set_stick(Stick::LeftX, stick_value(value)),
+                    Axis::LeftY => pad.set_stick(Stick::LeftY, stick_value(value)),
+                    Axis::RightX => pad.set_stick(Stick::RightX, stick_value(value)),
+                    Axis::RightY => pad.set_stick(Stick::RightY, stick_value(value)),
+                    //the pad has no analog triggers so they act like L2 and R2
+                    Axis::TriggerLeft => pad.set_button(Button::L2, value > i16::MAX / 2),
+                    Axis::TriggerRight => pad.set_button(Button::R2, value > i16::MAX / 2),
+                }
+            },
+            _ => {},
+        }
+    }
+
+    //passes the pads' motors on to the controllers in the same slots
+    pub fn update_rumble(&mut self, sio: &SIO) {
+        for controller in self.controllers.values_mut() {
+            let haptic = match &mut controller.haptic {
+                Some(haptic) => haptic,
+                None => continue,
+            };
+            let (small, large) = sio.pad(controller.slot).motors();
+            //the small motor is either on or off so it's treated as a weak rumble
+            let strength = (large as f32 / 255.0).max(if small { 0.5 } else { 0.0 });
+            if strength > 0.0 {
+                haptic.rumble_play(strength, Input::RUMBLE_DURATION);
+            } else if controller.rumbling {
+                haptic.rumble_stop();
+            }
+            controller.rumbling = strength > 0.0;
+        }
+    }
+}
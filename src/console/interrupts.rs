@@ -0,0 +1,87 @@
+use crate::register::BitTwiddle;
+
+pub const IRQ_VBLANK: u32 = 0;
+pub const IRQ_CD: u32 = 2;
+pub const IRQ_DMA: u32 = 3;
+//timers 1 and 2 use the next two IRQs
+pub const IRQ_TIMER0: u32 = 4;
+pub const IRQ_CONTROLLER: u32 = 7;
+pub const IRQ_SPU: u32 = 9;
+
+//the interrupt controller combines all IRQs into a single line going to COP0
+#[derive(Default)]
+pub struct Interrupts {
+    stat: u32,
+    mask: u32,
+}
+
+impl Interrupts {
+    pub fn request(&mut self, irq: u32) {
+        self.stat.set(irq);
+    }
+
+    //I_STAT is at offset 0 and I_MASK is at offset 4
+    pub fn read(&self, address: u32) -> u32 {
+        if address.nth_bit_bool(2) {
+            self.mask
+        } else {
+            self.stat
+        }
+    }
+
+    pub fn write(&mut self, address: u32, value: u32) {
+        if address.nth_bit_bool(2) {
+            self.mask = value.lowest_bits(11);
+        } else {
+            //IRQs are acknowledged by writing 0 to their bit in I_STAT
+            self.stat &= value;
+        }
+    }
+
+    pub fn active(&self) -> bool {
+        self.stat & self.mask != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const I_STAT: u32 = 0x1f80_1070;
+    const I_MASK: u32 = 0x1f80_1074;
+
+    #[test]
+    fn acknowledge_by_writing_zero() {
+        let mut interrupts: Interrupts = Default::default();
+        interrupts.request(IRQ_VBLANK);
+        interrupts.request(IRQ_DMA);
+        //writing 1 leaves a bit alone
+        interrupts.write(I_STAT, !(1 << IRQ_DMA));
+        assert_eq!(interrupts.read(I_STAT), 1 << IRQ_VBLANK);
+        interrupts.write(I_STAT, 0);
+        assert_eq!(interrupts.read(I_STAT), 0);
+    }
+
+    #[test]
+    fn mask_gates_the_line() {
+        let mut interrupts: Interrupts = Default::default();
+        interrupts.request(IRQ_CD);
+        assert!(!interrupts.active());
+        interrupts.write(I_MASK, 1 << IRQ_SPU);
+        assert!(!interrupts.active());
+        interrupts.write(I_MASK, 1 << IRQ_CD);
+        assert!(interrupts.active());
+        //the mask doesn't change I_STAT
+        interrupts.write(I_MASK, 0);
+        assert!(!interrupts.active());
+        assert_eq!(interrupts.read(I_STAT), 1 << IRQ_CD);
+        assert_eq!(interrupts.read(I_MASK), 0);
+    }
+
+    #[test]
+    fn mask_only_has_11_bits() {
+        let mut interrupts: Interrupts = Default::default();
+        interrupts.write(I_MASK, 0xffff_ffff);
+        assert_eq!(interrupts.read(I_MASK), 0x7ff);
+    }
+}
@@ -0,0 +1,548 @@
+use super::dma::DMAChannel;
+use crate::register::BitTwiddle;
+use std::collections::VecDeque;
+
+//the order that run-length encoded coefficients fill each 8x8 block
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20,
+    13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59,
+    52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+//the end of a block's run-length data
+const END_OF_BLOCK: u16 = 0xfe00;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Depth {
+    Bits4,
+    Bits8,
+    Bits24,
+    Bits15,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Command {
+    DecodeMacroblock,
+    SetQuantTable,
+    SetScaleTable,
+    None,
+}
+
+fn sign_extend_10(value: u16) -> i32 {
+    ((value << 6) as i16 >> 6) as i32
+}
+
+//the motion decoder turns compressed macroblocks into pixels for FMVs
+pub struct MDEC {
+    command_word: u32,
+    command: Command,
+    //the number of parameter words the current command is still waiting for
+    remaining: u32,
+    parameters: Vec<u32>,
+    output: VecDeque<u32>,
+    luma_quant: [u8; 64],
+    chroma_quant: [u8; 64],
+    scale: [i16; 64],
+    dma_in_enabled: bool,
+    dma_out_enabled: bool,
+    //the block that was decoded last where 0-3 are Y1-Y4, 4 is Cr and 5 is Cb
+    current_block: u32,
+}
+
+impl MDEC {
+    pub fn new() -> Self {
+        MDEC {
+            command_word: 0,
+            command: Command::None,
+            remaining: 0,
+            parameters: Vec::new(),
+            output: VecDeque::new(),
+            luma_quant: [0; 64],
+            chroma_quant: [0; 64],
+            scale: [0; 64],
+            dma_in_enabled: false,
+            dma_out_enabled: false,
+            current_block: 4,
+        }
+    }
+
+    fn depth(&self) -> Depth {
+        match self.command_word.range(27, 28) {
+            0 => Depth::Bits4,
+            1 => Depth::Bits8,
+            2 => Depth::Bits24,
+            _ => Depth::Bits15,
+        }
+    }
+
+    fn signed(&self) -> bool {
+        self.command_word.nth_bit_bool(26)
+    }
+
+    fn set_bit_15(&self) -> bool {
+        self.command_word.nth_bit_bool(25)
+    }
+
+    //DMA1 waits until there's decoded data to read
+    pub fn output_ready(&self) -> bool {
+        !self.output.is_empty()
+    }
+
+    //register 0 is for commands and data and register 1 is for control and status
+    pub fn read(&mut self, register: u32) -> u32 {
+        match register {
+            0 => self.output.pop_front().unwrap_or(0),
+            _ => self.status(),
+        }
+    }
+
+    pub fn write(&mut self, register: u32, value: u32) {
+        match register {
+            0 => self.write_command(value),
+            _ => {
+                if value.nth_bit_bool(31) {
+                    self.reset();
+                }
+                self.dma_in_enabled = value.nth_bit_bool(30);
+                self.dma_out_enabled = value.nth_bit_bool(29);
+            },
+        }
+    }
+
+    fn reset(&mut self) {
+        self.command_word = 0;
+        self.command = Command::None;
+        self.remaining = 0;
+        self.parameters.clear();
+        self.output.clear();
+        self.current_block = 4;
+    }
+
+    fn status(&self) -> u32 {
+        let mut status = self.command_word.range(25, 28) << 23;
+        if self.output.is_empty() {
+            status.set(31);
+        }
+        if self.remaining > 0 || !self.output.is_empty() {
+            status.set(29);
+        }
+        if self.dma_in_enabled && self.remaining > 0 {
+            status.set(28);
+        }
+        if self.dma_out_enabled && !self.output.is_empty() {
+            status.set(27);
+        }
+        status |= self.current_block << 16;
+        //the number of parameter words left minus 1
+        status | self.remaining.wrapping_sub(1).lowest_bits(16)
+    }
+
+    fn write_command(&mut self, value: u32) {
+        if self.remaining > 0 {
+            self.parameters.push(value);
+            self.remaining -= 1;
+            if self.remaining == 0 {
+                self.exec_command();
+            }
+            return
+        }
+        self.command_word = value;
+        self.parameters.clear();
+        let (command, remaining) = match value.upper_bits(3) {
+            1 => (Command::DecodeMacroblock, value.lowest_bits(16)),
+            //the chroma table is only sent if bit 0 is set
+            2 => (Command::SetQuantTable, 16 + 16 * value.nth_bit(0)),
+            3 => (Command::SetScaleTable, 32),
+            _ => (Command::None, 0),
+        };
+        self.command = command;
+        self.remaining = remaining;
+        if remaining == 0 {
+            self.exec_command();
+        }
+    }
+
+    fn exec_command(&mut self) {
+        let bytes = self
+            .parameters
+            .iter()
+            .flat_map(|word| word.to_le_bytes().to_vec())
+            .collect::<Vec<u8>>();
+        match self.command {
+            Command::DecodeMacroblock => self.decode_macroblocks(),
+            Command::SetQuantTable => {
+                self.luma_quant.copy_from_slice(&bytes[0..64]);
+                if bytes.len() == 128 {
+                    self.chroma_quant.copy_from_slice(&bytes[64..128]);
+                }
+            },
+            Command::SetScaleTable => {
+                for (i, entry) in self.scale.iter_mut().enumerate() {
+                    *entry = i16::from_le_bytes([bytes[2 * i], bytes[2 * i + 1]]);
+                }
+            },
+            Command::None => {},
+        }
+        self.parameters.clear();
+    }
+
+    fn decode_macroblocks(&mut self) {
+        let halfwords = self
+            .parameters
+            .iter()
+            .flat_map(|&word| vec![word as u16, (word >> 16) as u16])
+            .collect::<Vec<u16>>();
+        let mut input = halfwords.iter().copied().peekable();
+        match self.depth() {
+            //monochrome blocks are all Y so the status always reports block 4
+            Depth::Bits4 | Depth::Bits8 => {
+                while input.peek().is_some() {
+                    let luma_quant = self.luma_quant;
+                    match self.decode_block(&mut input, &luma_quant) {
+                        Some(block) => self.output_mono(&block),
+                        None => break,
+                    }
+                }
+            },
+            Depth::Bits15 | Depth::Bits24 => {
+                while input.peek().is_some() {
+                    let (luma_quant, chroma_quant) = (self.luma_quant, self.chroma_quant);
+                    //Cr and Cb come before the four Y blocks
+                    let mut blocks = Vec::with_capacity(6);
+                    for i in 0..6 {
+                        let quant = if i < 2 { &chroma_quant } else { &luma_quant };
+                        match self.decode_block(&mut input, quant) {
+                            Some(block) => blocks.push(block),
+                            None => break,
+                        }
+                        self.current_block = match i {
+                            0 => 4,
+                            1 => 5,
+                            n => n - 2,
+                        };
+                    }
+                    if blocks.len() < 6 {
+                        break
+                    }
+                    self.output_color(&blocks);
+                }
+            },
+        }
+        self.current_block = 4;
+    }
+
+    //reads the run-length data for one block and returns it after the IDCT
+    fn decode_block(
+        &self, input: &mut impl Iterator<Item = u16>, quant: &[u8; 64],
+    ) -> Option<[i32; 64]> {
+        let mut coefficients = [0; 64];
+        //blocks may be padded with end of block markers
+        let mut n = input.find(|&n| n != END_OF_BLOCK)?;
+        let q_scale = (n >> 10) as i32;
+        let mut k = 0;
+        let mut value = sign_extend_10(n & 0x3ff) * quant[0] as i32;
+        loop {
+            //a scale of 0 means the coefficients are stored without quantization
+            if q_scale == 0 {
+                value = sign_extend_10(n & 0x3ff) * 2;
+            }
+            let value_clamped = value.clamp(-0x400, 0x3ff);
+            if q_scale == 0 {
+                coefficients[k] = value_clamped;
+            } else {
+                coefficients[ZIGZAG[k]] = value_clamped;
+            }
+            n = input.next()?;
+            k += (n >> 10) as usize + 1;
+            if k > 63 {
+                break
+            }
+            value = (sign_extend_10(n & 0x3ff) * quant[k] as i32 * q_scale + 4) / 8;
+        }
+        Some(self.idct(&coefficients))
+    }
+
+    //the scale table holds the cosine coefficients for a 2-pass IDCT
+    fn idct(&self, block: &[i32; 64]) -> [i32; 64] {
+        let mut temp = [0i64; 64];
+        for x in 0..8 {
+            for y in 0..8 {
+                temp[x + y * 8] = (0..8)
+                    .map(|u| block[u * 8 + x] as i64 * self.scale[u * 8 + y] as i64)
+                    .sum();
+            }
+        }
+        let mut output = [0; 64];
+        for x in 0..8 {
+            for y in 0..8 {
+                let sum: i64 = (0..8)
+                    .map(|u| temp[u + y * 8] * self.scale[u * 8 + x] as i64)
+                    .sum();
+                //the result is rounded and truncated to 9 signed bits
+                let value = ((sum >> 32) + ((sum >> 31) & 1)) as i32;
+                output[x + y * 8] = ((value << 23) >> 23).clamp(-128, 127);
+            }
+        }
+        output
+    }
+
+    //monochrome output is a single 8x8 block of luma
+    fn output_mono(&mut self, block: &[i32; 64]) {
+        let offset: u8 = if self.signed() { 0 } else { 0x80 };
+        let bytes = block
+            .iter()
+            .map(|&y| y as u8 ^ offset)
+            .collect::<Vec<u8>>();
+        let bytes = match self.depth() {
+            Depth::Bits4 => bytes
+                .chunks(2)
+                .map(|pair| (pair[0] >> 4) | (pair[1] & 0xf0))
+                .collect(),
+            _ => bytes,
+        };
+        self.push_bytes(&bytes);
+    }
+
+    //color output is a 16x16 macroblock in 15-bit or 24-bit RGB
+    fn output_color(&mut self, blocks: &[[i32; 64]]) {
+        let (cr, cb) = (&blocks[0], &blocks[1]);
+        let mut pixels = [[0u8; 3]; 256];
+        for (i, y_block) in blocks[2..].iter().enumerate() {
+            let (xx, yy) = ((i % 2) * 8, (i / 2) * 8);
+            for y in 0..8 {
+                for x in 0..8 {
+                    let chroma = (x + xx) / 2 + ((y + yy) / 2) * 8;
+                    let (r, b) = (cr[chroma] as f32, cb[chroma] as f32);
+                    let g = -0.3437 * b - 0.7143 * r;
+                    let (r, b) = (1.402 * r, 1.772 * b);
+                    let luma = y_block[x + y * 8] as f32;
+                    let mut pixel = [r, g, b].map(|c| ((luma + c) as i32).clamp(-128, 127) as u8);
+                    if !self.signed() {
+                        pixel = pixel.map(|c| c ^ 0x80);
+                    }
+                    pixels[(x + xx) + (y + yy) * 16] = pixel;
+                }
+            }
+        }
+        match self.depth() {
+            Depth::Bits24 => {
+                let bytes = pixels.iter().flatten().copied().collect::<Vec<u8>>();
+                self.push_bytes(&bytes);
+            },
+            _ => {
+                let bit_15 = if self.set_bit_15() { 0x8000 } else { 0 };
+                let bytes = pixels
+                    .iter()
+                    .flat_map(|&[r, g, b]| {
+                        let color =
+                            (r as u16 >> 3) | ((g as u16 >> 3) << 5) | ((b as u16 >> 3) << 10);
+                        (color | bit_15).to_le_bytes().to_vec()
+                    })
+                    .collect::<Vec<u8>>();
+                self.push_bytes(&bytes);
+            },
+        }
+    }
+
+    fn push_bytes(&mut self, bytes: &[u8]) {
+        for word in bytes.chunks(4) {
+            let mut padded = [0; 4];
+            padded[..word.len()].copy_from_slice(word);
+            self.output.push_back(u32::from_le_bytes(padded));
+        }
+    }
+}
+
+impl DMAChannel for MDEC {
+    //DMA0 sends commands and their parameters
+    fn send(&mut self, data: Vec<u32>) {
+        for word in data {
+            self.write_command(word);
+        }
+    }
+
+    //DMA1 reads the decoded pixels
+    fn receive(&mut self, words: u32) -> Vec<u32> {
+        (0..words)
+            .map(|_| self.output.pop_front().unwrap_or(0))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DECODE: u32 = 0x2000_0000;
+    const SIGNED: u32 = 1 << 26;
+    const BIT_15: u32 = 1 << 25;
+    //signed 8-bit output makes coefficients easy to find
+    const SIGNED_8BIT: u32 = DECODE | SIGNED | (1 << 27);
+
+    fn send(mdec: &mut MDEC, words: &[u32]) {
+        for &word in words {
+            mdec.write(0, word);
+        }
+    }
+
+    //every quantization factor is 1
+    fn set_quant_tables(mdec: &mut MDEC) {
+        send(mdec, &[0x4000_0001]);
+        send(mdec, &[0x0101_0101; 32]);
+    }
+
+    //the first row of the real IDCT table, which is all a DC-only block needs to
+    // become a flat block of DC / 8
+    fn set_dc_scale_table(mdec: &mut MDEC) {
+        let mut table = [0; 32];
+        table[..4].copy_from_slice(&[0x5a82_5a82; 4]);
+        send(mdec, &[0x6000_0000]);
+        send(mdec, &table);
+    }
+
+    //a diagonal table of 0x4000 turns the IDCT into coefficient / 16
+    fn set_identity_scale_table(mdec: &mut MDEC) {
+        let mut halfwords = [0u32; 64];
+        for i in 0..8 {
+            halfwords[i * 9] = 0x4000;
+        }
+        let table = halfwords
+            .chunks(2)
+            .map(|pair| pair[0] | (pair[1] << 16))
+            .collect::<Vec<u32>>();
+        send(mdec, &[0x6000_0000]);
+        send(mdec, &table);
+    }
+
+    fn decode(mdec: &mut MDEC, command: u32, halfwords: &[u16]) -> Vec<u32> {
+        let words = halfwords
+            .chunks(2)
+            .map(|pair| pair[0] as u32 | (*pair.get(1).unwrap_or(&END_OF_BLOCK) as u32) << 16)
+            .collect::<Vec<u32>>();
+        send(mdec, &[command | words.len() as u32]);
+        send(mdec, &words);
+        mdec.output.drain(..).collect()
+    }
+
+    fn to_bytes(words: &[u32]) -> Vec<u8> {
+        words.iter().flat_map(|word| word.to_le_bytes().to_vec()).collect()
+    }
+
+    fn identity_mdec() -> MDEC {
+        let mut mdec = MDEC::new();
+        set_quant_tables(&mut mdec);
+        set_identity_scale_table(&mut mdec);
+        mdec
+    }
+
+    #[test]
+    fn run_lengths_follow_the_zigzag_order() {
+        let mut mdec = identity_mdec();
+        //a scale of 8 cancels out the division by 8 for AC coefficients
+        let first = decode(&mut mdec, SIGNED_8BIT, &[0x2000, 0x0100, END_OF_BLOCK]);
+        assert_eq!(to_bytes(&first)[1], 16);
+        //skipping one coefficient lands on the second row
+        let second = decode(&mut mdec, SIGNED_8BIT, &[0x2000, 0x0500, END_OF_BLOCK]);
+        let bytes = to_bytes(&second);
+        assert_eq!(bytes[8], 16);
+        assert_eq!(bytes.iter().filter(|&&byte| byte != 0).count(), 1);
+    }
+
+    #[test]
+    fn scale_0_skips_quantization_and_zigzag() {
+        let mut mdec = identity_mdec();
+        let words = decode(&mut mdec, SIGNED_8BIT, &[0x0000, 0x0480, END_OF_BLOCK]);
+        let bytes = to_bytes(&words);
+        //the coefficient is doubled and stays at index 2
+        assert_eq!(bytes[2], 16);
+        assert_eq!(bytes.iter().filter(|&&byte| byte != 0).count(), 1);
+    }
+
+    #[test]
+    fn leading_end_of_block_markers_are_padding() {
+        let mut mdec = identity_mdec();
+        let block = [END_OF_BLOCK, END_OF_BLOCK, 0x2000, 0x0100, END_OF_BLOCK];
+        let words = decode(&mut mdec, SIGNED_8BIT, &block);
+        assert_eq!(words.len(), 16);
+        assert_eq!(to_bytes(&words)[1], 16);
+    }
+
+    #[test]
+    fn flat_mono_blocks() {
+        let mut mdec = MDEC::new();
+        set_quant_tables(&mut mdec);
+        set_dc_scale_table(&mut mdec);
+        //a DC of 0x80 decodes to 16, which is 0x90 as unsigned
+        let block = [0x0400 | 0x080, END_OF_BLOCK];
+        assert_eq!(decode(&mut mdec, DECODE | (1 << 27), &block), vec![0x9090_9090; 16]);
+        assert_eq!(decode(&mut mdec, DECODE, &block), vec![0x9999_9999; 8]);
+        let signed = decode(&mut mdec, SIGNED_8BIT, &block);
+        assert_eq!(signed, vec![0x1010_1010; 16]);
+    }
+
+    //Cr, Cb and then four flat luma blocks
+    fn macroblock(cr: u16) -> Vec<u16> {
+        let mut halfwords = vec![0x0400 | cr, END_OF_BLOCK, 0x0400, END_OF_BLOCK];
+        for _ in 0..4 {
+            halfwords.extend_from_slice(&[0x0400 | 0x100, END_OF_BLOCK]);
+        }
+        halfwords
+    }
+
+    #[test]
+    fn gray_macroblock() {
+        let mut mdec = MDEC::new();
+        set_quant_tables(&mut mdec);
+        set_dc_scale_table(&mut mdec);
+        //a luma of 32 is 0xa0 as unsigned
+        let rgb24 = decode(&mut mdec, DECODE | (2 << 27), &macroblock(0));
+        assert_eq!(rgb24, vec![0xa0a0_a0a0; 192]);
+        let rgb15 = decode(&mut mdec, DECODE | (3 << 27) | BIT_15, &macroblock(0));
+        assert_eq!(rgb15, vec![0xd294_d294; 128]);
+        let rgb15 = decode(&mut mdec, DECODE | (3 << 27), &macroblock(0));
+        assert_eq!(rgb15, vec![0x5294_5294; 128]);
+    }
+
+    #[test]
+    fn red_macroblock() {
+        let mut mdec = MDEC::new();
+        set_quant_tables(&mut mdec);
+        set_dc_scale_table(&mut mdec);
+        //Cr = 16 adds 1.402 * 16 to red and takes 0.7143 * 16 from green
+        let words = decode(&mut mdec, DECODE | SIGNED | (2 << 27), &macroblock(0x80));
+        let bytes = to_bytes(&words);
+        assert_eq!(bytes.len(), 768);
+        assert!(bytes.chunks(3).all(|pixel| pixel == [54, 20, 32]));
+    }
+
+    #[test]
+    fn status() {
+        let mut mdec = MDEC::new();
+        //empty output, block 4 and no parameters left
+        assert_eq!(mdec.read(1), 0x8004_ffff);
+        mdec.write(1, 0x6000_0000);
+        mdec.write(0, DECODE | (3 << 27) | 2);
+        let busy = (1 << 31) | (1 << 29) | (1 << 28) | (3 << 25);
+        assert_eq!(mdec.read(1), busy | 0x0004_0001);
+        mdec.write(0, 0);
+        assert_eq!(mdec.read(1), busy | 0x0004_0000);
+        //resetting drops the rest of the command
+        mdec.write(1, 0x8000_0000);
+        assert_eq!(mdec.read(1), 0x8004_ffff);
+    }
+
+    #[test]
+    fn status_while_reading_output() {
+        let mut mdec = MDEC::new();
+        set_quant_tables(&mut mdec);
+        set_dc_scale_table(&mut mdec);
+        mdec.write(1, 0x6000_0000);
+        let block = [0x0400 | 0x080, END_OF_BLOCK];
+        send(&mut mdec, &[DECODE | (1 << 27) | 1, block[0] as u32 | (block[1] as u32) << 16]);
+        //the output is waiting to be read by DMA1
+        assert_eq!(mdec.read(1), (1 << 29) | (1 << 27) | (1 << 25) | 0x0004_ffff);
+        assert!(mdec.output_ready());
+        assert_eq!(mdec.receive(16), vec![0x9090_9090; 16]);
+        assert_eq!(mdec.read(1), (1 << 31) | (1 << 25) | 0x0004_ffff);
+    }
+}
@@ -3,111 +3,60 @@ use super::Memory;
 use crate::common::{ReadArray, WriteArray};
 use crate::register::BitTwiddle;
 
-macro_rules! identifier_size {
-    (read_word) => {{
-        SizeIdentifier::Word
-    }};
-    (read_half) => {{
-        SizeIdentifier::Half
-    }};
-    (read_byte) => {{
-        SizeIdentifier::Byte
-    }};
-    (write_word) => {{
-        SizeIdentifier::Word
-    }};
-    (write_half) => {{
-        SizeIdentifier::Half
-    }};
-    (write_byte) => {{
-        SizeIdentifier::Byte
-    }};
+//the number of bytes accessed by each read and write function
+#[macro_export]
+macro_rules! access_width {
+    (read_byte) => {
+        1
+    };
+    (read_half) => {
+        2
+    };
+    (read_word) => {
+        4
+    };
+    (write_byte) => {
+        1
+    };
+    (write_half) => {
+        2
+    };
+    (write_word) => {
+        4
+    };
 }
 
 #[macro_export]
 macro_rules! get_io_response {
     ($address:expr, $function:ident, $self:expr) => {{
         let aligned_address = $address & 0xffff_fffc;
-        let aligned_offset = aligned_address - Memory::IO_PORTS;
         let offset = $address - Memory::IO_PORTS;
         let value = $self.io_ports.as_ref().$function(offset);
         match aligned_address {
-            Memory::INTERRUPT_STAT => {
-                //println!("read interrupt stat");
-                MemResponse::Value(value)
-            },
-            Memory::INTERRUPT_MASK => {
-                //println!("read interrupt mask");
-                MemResponse::Value(value)
-            },
-            Memory::TIMER_VALUE_0 => {
-                //println!("read timer value 0");
-                MemResponse::Value(value)
-            },
-            Memory::TIMER_MODE_0 => {
-                //println!("read timer mode 0");
-                MemResponse::Value(value)
-            },
-            Memory::TIMER_TARGET_0 => {
-                //println!("read timer target 0");
-                MemResponse::Value(value)
-            },
-            Memory::TIMER_VALUE_1 => {
-                //println!("read timer value 1");
-                MemResponse::Value(value)
-            },
-            Memory::TIMER_MODE_1 => {
-                //println!("read timemode r 1");
-                MemResponse::Value(value)
-            },
-            Memory::TIMER_TARGET_1 => {
-                //println!("read timer target 1");
-                MemResponse::Value(value)
-            },
-            Memory::TIMER_VALUE_2 => {
-                //println!("read timer value 2");
-                MemResponse::Value(value)
-            },
-            Memory::TIMER_MODE_2 => {
-                //println!("read timer mode 2");
-                MemResponse::Value(value)
-            },
-            Memory::TIMER_TARGET_2 => {
-                //println!("read timer target 2");
-                MemResponse::Value(value)
-            },
-            Memory::CD_PORT => {
-                let index = $self
-                    .io_ports
-                    .as_ref()
-                    .read_byte(aligned_offset)
-                    .lowest_bits(2);
-                let ret = match $address.lowest_bits(2) {
-                    //could read word, half, byte
-                    0 => {
-                        MemResponse::Value(*value.clone().clear_mask(0x0000_00fc).set(3).set(4))
-                        //match identifier_size!($function) {
-                        //  SizeIdentifier::Word => {
-                        //  },
-                        //  SizeIdentifier::Half => {
-                        //  },
-                        //  SizeIdentifier::Byte => {
-                        //  },
-                        //}
-                    },
-                    //could read byte
-                    1 => MemResponse::CDResponse,
-                    //could read half, byte
-                    2 => MemResponse::Value(value),
-                    //could read byte
-                    3 => MemResponse::Value(value),
-                    _ => {
-                        unreachable!("");
-                    },
-                };
-                //println!("CD {} {:x?} from {:#x}", stringify!($function), ret, $address);
-                ret
-            },
+            Memory::INTERRUPT_STAT | Memory::INTERRUPT_MASK => {
+                MemResponse::InterruptControl(aligned_address)
+            },
+            Memory::TIMER_VALUE_0 |
+            Memory::TIMER_MODE_0 |
+            Memory::TIMER_TARGET_0 |
+            Memory::TIMER_VALUE_1 |
+            Memory::TIMER_MODE_1 |
+            Memory::TIMER_TARGET_1 |
+            Memory::TIMER_VALUE_2 |
+            Memory::TIMER_MODE_2 |
+            Memory::TIMER_TARGET_2 => MemResponse::Timer(aligned_address),
+            //the CD controller's registers are all a byte wide
+            Memory::CD_PORT => MemResponse::CD($address.lowest_bits(2)),
+            (Memory::SPU..=Memory::SPU_END) => {
+                MemResponse::SPU($address, access_width!($function))
+            },
+            (Memory::SIO0..=Memory::SIO0_END) => {
+                MemResponse::SIO($address, access_width!($function))
+            },
+            Memory::MDEC_COMMAND | Memory::MDEC_CONTROL => {
+                MemResponse::MDEC((aligned_address - Memory::MDEC_COMMAND) / 4)
+            },
+            Memory::DMA_INTERRUPT => MemResponse::DMAInterrupt,
             Memory::GPU_GP0 => MemResponse::GPUREAD,
             Memory::GPU_GP1 => MemResponse::GPUSTAT,
             _ => {
@@ -124,140 +73,56 @@ macro_rules! get_io_action {
         let aligned_address = $address & 0xffff_fffc;
         let aligned_offset = aligned_address - Memory::IO_PORTS;
         match aligned_address {
-            //Memory::INTERRUPT_STAT => {
-            //  //check for interrupt requests
-            //  let mut irq = Vec::new();
-            //  let new_interrupt_stat = $self.io_ports.as_ref().read_half(aligned_offset);
-            //  let interrupt_mask = $self.io_ports.as_ref().read_half(Memory::INTERRUPT_MASK -
-            // Memory::IO_PORTS);  for i in 0..=10 {
-            //    if !$self.old_interrupt_stat.nth_bit_bool(i) &&
-            //      new_interrupt_stat.nth_bit_bool(i) &&
-            //      interrupt_mask.nth_bit_bool(i) {
-            //      irq.push(MemAction::Interrupt(i));
-            //    }
-            //  }
-            //  $self.old_interrupt_stat = $self.io_ports.as_ref().read_half(aligned_offset);
-            //  //println!("wrote {:#x} to interrupt stat", $value);
-            //  match irq.len() {
-            //    0 => {
-            //      None
-            //    },
-            //    _ => {
-            //      Some(irq)
-            //    },
-            //  }
-            //},
-            Memory::INTERRUPT_MASK => {
-                $self.io_ports.as_mut().write_word(
-                    aligned_offset,
-                    *$value
-                        .clone()
-                        .clear(11)
-                        .clear(12)
-                        .clear(13)
-                        .clear(14)
-                        .clear(15),
-                );
-                //println!("wrote {:#x} to interrupt mask", $value);
-                MemAction::None
-            },
-            Memory::TIMER_VALUE_0 => {
-                //println!("wrote {:#x} to timer value 0", $value);
-                MemAction::None
-            },
-            Memory::TIMER_MODE_0 => {
-                //println!("wrote {:#x} to timer mode 0", $value);
-                MemAction::None
-            },
-            Memory::TIMER_TARGET_0 => {
-                //println!("wrote {:#x} to timer target 0", $value);
-                MemAction::None
-            },
-            Memory::TIMER_VALUE_1 => {
-                //println!("wrote {:#x} to timer value 1", $value);
-                MemAction::None
-            },
-            Memory::TIMER_MODE_1 => {
-                //println!("wrote {:#x} to timemode r 1", $value);
-                MemAction::None
-            },
-            Memory::TIMER_TARGET_1 => {
-                //println!("wrote {:#x} to timer target 1", $value);
-                MemAction::None
-            },
-            Memory::TIMER_VALUE_2 => {
-                //println!("wrote {:#x} to timer value 2", $value);
-                MemAction::None
-            },
-            Memory::TIMER_MODE_2 => {
-                //println!("wrote {:#x} to timer mode 2", $value);
-                MemAction::None
-            },
-            Memory::TIMER_TARGET_2 => {
-                //println!("wrote {:#x} to timer target 2", $value);
-                MemAction::None
-            },
-            Memory::CD_PORT => {
-                //println!("CD {} {:#x} to {:#x}", stringify!($function), $value, $address);
-                let index = $self
-                    .io_ports
-                    .as_ref()
-                    .read_byte(aligned_offset)
-                    .lowest_bits(2);
-                match $address.lowest_bits(2) {
-                    //could write word, half or byte
-                    //1800, 1801, 1802, 1803
-                    0 => match identifier_size!($function) {
-                        SizeIdentifier::Word => MemAction::CDCmdParam(
-                            $self.io_ports.as_ref().read_byte(aligned_offset + 1) as u8,
-                            $self.io_ports.as_ref().read_byte(aligned_offset + 2) as u8,
-                        ),
-                        SizeIdentifier::Half => MemAction::CDCmd(
-                            $self.io_ports.as_ref().read_byte(aligned_offset + 1) as u8,
-                        ),
-                        SizeIdentifier::Byte => MemAction::None,
-                    },
-                    //could write byte
-                    1 => match index {
-                        0 => MemAction::CDCmd(
-                            $self.io_ports.as_ref().read_byte(aligned_offset + 1) as u8,
-                        ),
-                        1 => MemAction::None,
-                        2 => MemAction::None,
-                        3 => MemAction::None,
-                        _ => {
-                            unreachable!("");
-                        },
-                    },
-                    //could write half or byte
-                    //1802, 1803
-                    2 => match index {
-                        0 => MemAction::CDParam(
-                            $self.io_ports.as_ref().read_byte(aligned_offset + 2) as u8,
-                        ),
-                        1 => MemAction::None,
-                        2 => MemAction::None,
-                        3 => MemAction::None,
-                        _ => {
-                            unreachable!("");
-                        },
-                    },
-                    //could write byte
-                    //1803
-                    3 => match index {
-                        0 => MemAction::None,
-                        1 => MemAction::None,
-                        2 => MemAction::None,
-                        3 => MemAction::None,
-                        _ => {
-                            unreachable!("");
-                        },
-                    },
-                    _ => {
-                        unreachable!("");
-                    },
-                }
+            Memory::INTERRUPT_STAT | Memory::INTERRUPT_MASK => MemAction::InterruptControl(
+                aligned_address,
+                $self.io_ports.as_ref().read_word(aligned_offset),
+            ),
+            Memory::TIMER_VALUE_0 |
+            Memory::TIMER_MODE_0 |
+            Memory::TIMER_TARGET_0 |
+            Memory::TIMER_VALUE_1 |
+            Memory::TIMER_MODE_1 |
+            Memory::TIMER_TARGET_1 |
+            Memory::TIMER_VALUE_2 |
+            Memory::TIMER_MODE_2 |
+            Memory::TIMER_TARGET_2 => MemAction::Timer(
+                aligned_address,
+                $self.io_ports.as_ref().read_word(aligned_offset),
+            ),
+            Memory::CD_PORT => MemAction::CD(
+                $address.lowest_bits(2),
+                $self.io_ports.as_ref().read_byte($address - Memory::IO_PORTS),
+            ),
+            //the SPU's registers are a halfword wide so byte writes write the whole
+            // halfword
+            (Memory::SPU..=Memory::SPU_END) => match access_width!($function) {
+                4 => MemAction::SPU(
+                    aligned_address,
+                    $self.io_ports.as_ref().read_word(aligned_offset),
+                    4,
+                ),
+                _ => MemAction::SPU(
+                    $address & !1,
+                    $self
+                        .io_ports
+                        .as_ref()
+                        .read_half(($address & !1) - Memory::IO_PORTS),
+                    2,
+                ),
+            },
+            (Memory::SIO0..=Memory::SIO0_END) => {
+                let offset = $address - Memory::IO_PORTS;
+                let value = match access_width!($function) {
+                    1 => $self.io_ports.as_ref().read_byte(offset),
+                    2 => $self.io_ports.as_ref().read_half(offset),
+                    _ => $self.io_ports.as_ref().read_word(offset),
+                };
+                MemAction::SIO($address, value, access_width!($function))
             },
+            Memory::MDEC_COMMAND | Memory::MDEC_CONTROL => MemAction::MDEC(
+                (aligned_address - Memory::MDEC_COMMAND) / 4,
+                $self.io_ports.as_ref().read_word(aligned_offset),
+            ),
             Memory::GPU_GP0 => MemAction::GpuGp0($self.io_ports.as_ref().read_word(aligned_offset)),
             Memory::GPU_GP1 => MemAction::GpuGp1($self.io_ports.as_ref().read_word(aligned_offset)),
             Memory::DMA_CHANNEL_0 |
@@ -286,8 +151,9 @@ macro_rules! get_io_action {
                     MemAction::None
                 }
             },
-            //DMA interrupt register
-            0x1f80_10f4 => MemAction::None,
+            Memory::DMA_INTERRUPT => {
+                MemAction::DMAInterrupt($self.io_ports.as_ref().read_word(aligned_offset))
+            },
             _ => {
                 //println!("unhandled IO port {} {:#x} at {:#x}", stringify!($function),
                 // $value, $address);
@@ -298,6 +164,13 @@ macro_rules! get_io_action {
 }
 
 impl Memory {
+    //DPCR holds the priority and enable bits for each channel
+    pub fn dma_control(&self) -> u32 {
+        self.io_ports
+            .as_ref()
+            .read_word(Memory::DMA_CONTROL - Memory::IO_PORTS)
+    }
+
     pub fn reset_dma_channel(&mut self, channel: u8) {
         let address = Memory::DMA_CHANNEL_0 + ((channel as u32) * 0x10) - Memory::IO_PORTS;
         let mut control_register = self.io_ports.as_ref().read_word(address);
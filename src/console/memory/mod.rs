@@ -1,4 +1,4 @@
-use super::dma::{DMAChannel, Transfer};
+use super::dma::Transfer;
 use crate::common::{ReadArray, WriteArray};
 use crate::register::BitTwiddle;
 use std::fs::{metadata, File};
@@ -14,10 +14,15 @@ pub enum MemAction {
     DMA(Transfer),
     GpuGp0(u32),
     GpuGp1(u32),
-    CDCmd(u8),
-    CDParam(u8),
-    CDCmdParam(u8, u8),
-    Interrupt(u32),
+    CD(u32, u32),
+    MDEC(u32, u32),
+    //the address, value and width in bytes of an access
+    SPU(u32, u32, u32),
+    //the address, value and width in bytes of an access to the joypad port
+    SIO(u32, u32, u32),
+    Timer(u32, u32),
+    InterruptControl(u32, u32),
+    DMAInterrupt(u32),
     None,
 }
 
@@ -26,7 +31,13 @@ pub enum MemResponse {
     Value(u32),
     GPUREAD,
     GPUSTAT,
-    CDResponse,
+    CD(u32),
+    MDEC(u32),
+    SPU(u32, u32),
+    SIO(u32, u32),
+    Timer(u32),
+    InterruptControl(u32),
+    DMAInterrupt,
 }
 
 enum RWIdentifier {
@@ -34,12 +45,6 @@ enum RWIdentifier {
     Write,
 }
 
-enum SizeIdentifier {
-    Word,
-    Half,
-    Byte,
-}
-
 pub const KB: usize = 1024;
 pub const MB: usize = 1024 * KB;
 const PHYS_MASK: [u32; 8] = [
@@ -200,20 +205,6 @@ pub struct Memory {
     expansion_3: Box<[u8]>,
     bios: Box<[u8; 512 * KB]>,
     cache_control: [u8; 512],
-
-    //these are copies of certain edge-triggered I/O port registers
-    //although this is a u32, only the previous half word is stored
-    old_interrupt_stat: u32,
-}
-
-impl DMAChannel for Memory {
-    fn send(&mut self, data: Vec<u32>) {
-        todo!("implement DMAChannel for Memory")
-    }
-
-    fn receive(&self) -> u32 {
-        todo!("implement DMAChannel for Memory")
-    }
 }
 
 impl Memory {
@@ -247,8 +238,14 @@ impl Memory {
     const IO_PORTS_END: u32 = Memory::IO_PORTS + (8 * KB as u32) - 1;
     const MAIN_RAM: u32 = 0;
     const MAIN_RAM_END: u32 = Memory::MAIN_RAM + (2 * MB as u32) - 1;
+    const MDEC_COMMAND: u32 = 0x1f80_1820;
+    const MDEC_CONTROL: u32 = 0x1f80_1824;
     const SCRATCHPAD: u32 = 0x1f80_0000;
     const SCRATCHPAD_END: u32 = Memory::SCRATCHPAD + (KB as u32) - 1;
+    const SIO0: u32 = 0x1f80_1040;
+    const SIO0_END: u32 = Memory::SIO0 + 0xf;
+    const SPU: u32 = 0x1f80_1c00;
+    const SPU_END: u32 = Memory::SPU + (KB as u32) - 1;
     const TIMER_MODE_0: u32 = 0x1f80_1104;
     const TIMER_MODE_1: u32 = 0x1f80_1114;
     const TIMER_MODE_2: u32 = 0x1f80_1124;
@@ -283,7 +280,6 @@ impl Memory {
             expansion_3: vec![0; 2 * MB].into_boxed_slice(),
             bios,
             cache_control: [0; 512],
-            old_interrupt_stat: 0,
         })
     }
 
@@ -370,7 +366,6 @@ mod tests {
                 expansion_3: vec![0; 2 * MB].into_boxed_slice(),
                 bios: Box::new([0; 512 * KB]),
                 cache_control: [0; 512],
-                old_interrupt_stat: 0,
             }
         }
     }
@@ -1,29 +1,46 @@
+use crate::common::{get_imm25, get_primary_field};
 use crate::register::BitTwiddle;
-use cd::CD;
+use audio::{Audio, AudioConfig};
+use cd::{Disc, CD};
 use cop0::Cop0;
 use cop0::Cop0Exception;
-use gpu::GPU;
+use dma::DMA;
+use gpu::{VBlank, GPU};
 use gte::GTE;
+use input::Input;
+use interrupts::{Interrupts, IRQ_CD, IRQ_CONTROLLER, IRQ_DMA, IRQ_SPU, IRQ_VBLANK};
+use mdec::MDEC;
 use memory::{MemAction, MemResponse, Memory};
 use r3000::R3000;
+use scheduler::{Event as ScheduledEvent, Scheduler};
 use screen::Screen;
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
+use sio::{MemoryCard, SIO};
+use spu::SPU;
 use std::collections::{HashSet, VecDeque};
-use std::convert::TryInto;
 use std::fs::{metadata, File};
 use std::io;
-use std::io::Read;
+use std::io::{Error, ErrorKind, Read};
+use timers::Timers;
 
+pub mod audio;
 mod cd;
 pub mod cop0;
 mod dma;
 mod gpu;
 mod gte;
 mod handle_dma;
+mod input;
+pub mod interrupts;
+mod mdec;
 mod memory;
 pub mod r3000;
+mod scheduler;
 mod screen;
+mod sio;
+mod spu;
+mod timers;
 
 pub trait MaybeSet {
     fn maybe_set(self, value: u32) -> Option<Name>;
@@ -66,22 +83,42 @@ macro_rules! handle_action {
     ($write:expr, $self:ident) => {
         match $write {
             MemAction::DMA(transfer) => {
-                $self.handle_dma(transfer);
+                $self.dma.request(transfer);
+                $self.start_next_dma();
             },
             MemAction::GpuGp0(value) => $self.gpu.write_to_gp0(value),
             MemAction::GpuGp1(value) => $self.gpu.write_to_gp1(value),
-            MemAction::CDCmd(value) => {
-                $self.cd.send_command(value);
+            MemAction::CD(register, value) => {
+                $self.cd.write(register, value, &mut $self.scheduler);
+                $self.update_cd_interrupt();
             },
-            MemAction::CDParam(value) => {
-                $self.cd.send_parameter(value);
+            MemAction::MDEC(register, value) => {
+                $self.mdec.write(register, value);
+                $self.start_next_dma();
             },
-            MemAction::CDCmdParam(cmd, param) => {
-                $self.cd.send_command(cmd);
-                $self.cd.send_parameter(param);
+            MemAction::SPU(address, value, width) => {
+                $self.sync_spu();
+                $self.spu.write(address, value, width);
+                $self.sync_spu();
             },
-            MemAction::Interrupt(irq) => {
-                $self.cop0.request_interrupt(irq);
+            MemAction::SIO(address, value, width) => {
+                $self.sio.write(address, value, width, &mut $self.scheduler);
+                $self.update_sio_interrupt();
+            },
+            MemAction::Timer(address, value) => {
+                //writes take effect at the current cycle so catch up first
+                $self.sync_timers();
+                $self.timers.write(address, value);
+                $self.sync_timers();
+            },
+            MemAction::InterruptControl(address, value) => {
+                $self.interrupts.write(address, value);
+                $self.update_interrupt_line();
+            },
+            MemAction::DMAInterrupt(value) => {
+                if $self.dma.write_interrupt(value) {
+                    $self.request_interrupt(IRQ_DMA);
+                }
             },
             MemAction::None => {},
         };
@@ -94,7 +131,19 @@ macro_rules! handle_response {
             MemResponse::Value(value) => value,
             MemResponse::GPUREAD => $self.gpu.gpuread(),
             MemResponse::GPUSTAT => $self.gpu.gpustat(),
-            MemResponse::CDResponse => $self.cd.read_response(),
+            MemResponse::CD(register) => $self.cd.read(register),
+            MemResponse::MDEC(register) => $self.mdec.read(register),
+            MemResponse::SPU(address, width) => {
+                $self.sync_spu();
+                $self.spu.read(address, width)
+            },
+            MemResponse::SIO(address, width) => $self.sio.read(address, width),
+            MemResponse::Timer(address) => {
+                $self.sync_timers();
+                $self.timers.read(address)
+            },
+            MemResponse::InterruptControl(address) => $self.interrupts.read(address),
+            MemResponse::DMAInterrupt => $self.dma.read_interrupt(),
         }
     };
 }
@@ -103,30 +152,50 @@ pub struct Console {
     //these correspond to physical components
     pub r3000: R3000,
     pub cop0: Cop0,
+    pub interrupts: Interrupts,
     pub memory: Memory,
+    pub dma: DMA,
     pub gpu: GPU,
     pub gte: GTE,
     pub cd: CD,
+    pub mdec: MDEC,
+    pub spu: SPU,
+    pub sio: SIO,
+    pub timers: Timers,
     pub screen: Screen,
+    pub audio: Audio,
+    pub input: Input,
+    pub scheduler: Scheduler,
 
     pub next_pc: Option<u32>,
     pub delayed_writes: VecDeque<DelayedWrite>,
     pub modified_register: Option<Name>,
     pub overwritten: HashSet<u32>,
     pub i: u32,
+    //the executable and stack that fast boot loads once the BIOS reaches the shell
+    fast_boot_exe: Option<(Vec<u8>, u32)>,
 }
 
 impl Console {
-    pub const REFRESH_RATE: i64 = 550_000;
+    //the BIOS jumps here to start the shell after the kernel is initialized
+    const SHELL_ENTRY: u32 = 0x8003_0000;
 
     pub fn new(
-        bios_filename: &String, infile: Option<&String>, gpu_logging: bool, wx: u32, wy: u32,
+        bios_filename: &String, infile: Option<&String>, fast_boot: bool, audio: &AudioConfig,
+        memory_cards: [Option<&String>; 2], gpu_logging: bool, wx: u32, wy: u32,
     ) -> io::Result<Self> {
         let mut r3000 = R3000::new();
         let cop0: Cop0 = Default::default();
         let mut memory = Memory::new(bios_filename)?;
+        let mut overwritten = HashSet::new();
 
-        infile.map(|name| {
+        //disc images go in the CD drive and anything else is loaded as a PS-EXE
+        let disc = match infile {
+            Some(name) if Disc::is_image(name) => Some(Disc::open(name)?),
+            _ => None,
+        };
+        let exe = infile.filter(|name| !Disc::is_image(name));
+        if let Some(name) = exe {
             let mut file = File::open(name).expect("Unable to open input file");
             let filesize = metadata(name)
                 .expect("Unable to get input file metadata")
@@ -136,117 +205,292 @@ impl Console {
             }
             let mut buf = Vec::new();
             file.read_to_end(&mut buf).unwrap();
-            let words = buf
-                .chunks(4)
-                .map(|c| {
-                    u32::from_ne_bytes(c.try_into().expect("Couldn't turn 4-byte chunk into a u32"))
-                })
-                .collect::<Vec<u32>>();
-            *r3000.pc_mut() = words[0x10 / 4];
-            r3000.nth_reg_mut(28).maybe_set(words[0x14 / 4]);
-            r3000
-                .nth_reg_mut(29)
-                .maybe_set(words[0x30 / 4] + words[0x34 / 4]);
-            r3000
-                .nth_reg_mut(30)
-                .maybe_set(words[0x30 / 4] + words[0x34 / 4]);
-
-            let dest_addr = words[0x18 / 4];
-            let filesize = words[0x1C / 4] >> 2;
-            for (n, word) in words[0x800 / 4..].iter().enumerate() {
-                memory.write_word(dest_addr + (4 * n as u32), *word);
+            Console::load_exe(&mut r3000, &mut memory, &mut overwritten, &buf, None);
+        }
+        //fast boot skips the BIOS shell and starts the executable named in SYSTEM.CNF
+        let fast_boot_exe = if fast_boot {
+            let disc = disc
+                .as_ref()
+                .ok_or_else(|| Error::new(ErrorKind::NotFound, "Fast boot requires a disc"))?;
+            let config = disc
+                .system_config()
+                .ok_or_else(|| Error::new(ErrorKind::NotFound, "Unable to read SYSTEM.CNF"))?;
+            let exe = disc
+                .read_file(&config.boot)
+                .ok_or_else(|| Error::new(ErrorKind::NotFound, "Unable to read boot executable"))?;
+            Console::check_exe(&exe)?;
+            //the BIOS only resizes the kernel's tables when it boots the disc itself
+            if !config.default_kernel() {
+                println!(
+                    "Warning: fast boot ignores TCB={:x} and EVENT={:x} from SYSTEM.CNF",
+                    config.tcb, config.event
+                );
             }
-        });
+            Some((exe, config.stack))
+        } else {
+            None
+        };
 
-        let gpu = GPU::new(gpu_logging);
+        let mut gpu = GPU::new(gpu_logging);
         let gte = Default::default();
-        let cd = CD::new(infile);
+        let cd = CD::new(disc);
+        let timers = Timers::new();
         let screen = Screen::new(wx, wy);
+        let audio = Audio::new(&screen, audio)?;
+        let input = Input::new(screen.sdl());
+        let mut sio = SIO::new();
+        for (slot, filename) in memory_cards.iter().enumerate() {
+            if let Some(filename) = filename {
+                sio.insert_card(slot, MemoryCard::open(filename)?);
+            }
+        }
+        let mut scheduler: Scheduler = Default::default();
+        scheduler.schedule(ScheduledEvent::HBlank, gpu.cycles_until_hblank());
+        scheduler.schedule(ScheduledEvent::Scanline, gpu.cycles_per_line());
+        scheduler.schedule(ScheduledEvent::Timers, timers.cycles_until_irq());
+        scheduler.schedule(ScheduledEvent::SPU, SPU::SYNC_PERIOD);
         let delayed_writes = VecDeque::new();
         Ok(Self {
             r3000,
             cop0,
+            interrupts: Default::default(),
             memory,
+            dma: Default::default(),
             gpu,
             gte,
             cd,
+            mdec: MDEC::new(),
+            spu: SPU::new(),
+            sio,
+            timers,
             screen,
+            audio,
+            input,
+            scheduler,
             next_pc: None,
             delayed_writes,
             modified_register: None,
             i: 0,
-            overwritten: Default::default(),
+            overwritten,
+            fast_boot_exe,
         })
     }
 
-    pub fn handle_events(&mut self) -> bool {
-        let event_rate: u32 = 100_000;
-        if self.i % event_rate == 0 {
-            for event in self.screen.event_pump().poll_iter() {
-                match event {
-                    Event::KeyDown {
-                        keycode: Some(Keycode::Escape),
-                        ..
-                    } => {
-                        println!("Executed {} steps", self.i);
+    //the BIOS has set up the kernel by the time it reaches the shell so the boot
+    // executable is loaded in its place
+    //backends call this before fetching each instruction or block
+    pub fn check_fast_boot(&mut self) {
+        if self.r3000.pc() != Console::SHELL_ENTRY {
+            return
+        }
+        if let Some((exe, stack)) = self.fast_boot_exe.take() {
+            //the header was checked when the console was created
+            Console::load_exe(
+                &mut self.r3000,
+                &mut self.memory,
+                &mut self.overwritten,
+                &exe,
+                Some(stack),
+            );
+        }
+    }
+
+    //fast boot checks the executable up front since it's only loaded once the BIOS
+    // reaches the shell
+    fn check_exe(buf: &[u8]) -> io::Result<()> {
+        //the header takes up the first 0x800 bytes and the rest is copied as words
+        if buf.len() < 0x800 || buf.len() % 4 != 0 || &buf[0..8] != b"PS-X EXE" {
+            return Err(Error::new(ErrorKind::InvalidData, "Invalid PS-EXE"))
+        }
+        Ok(())
+    }
+
+    //copies a PS-EXE into RAM and sets up the registers from its header
+    //the stack from SYSTEM.CNF replaces the one in the header if there is one
+    //every word written is marked as overwritten so stale compiled blocks get
+    // invalidated
+    fn load_exe(
+        r3000: &mut R3000, memory: &mut Memory, overwritten: &mut HashSet<u32>, buf: &[u8],
+        stack: Option<u32>,
+    ) {
+        let words = buf
+            .chunks(4)
+            .map(|c| u32::from_ne_bytes([c[0], c[1], c[2], c[3]]))
+            .collect::<Vec<u32>>();
+        let stack = stack.unwrap_or(words[0x30 / 4] + words[0x34 / 4]);
+        *r3000.pc_mut() = words[0x10 / 4];
+        r3000.nth_reg_mut(28).maybe_set(words[0x14 / 4]);
+        r3000.nth_reg_mut(29).maybe_set(stack);
+        r3000.nth_reg_mut(30).maybe_set(stack);
+
+        let dest_addr = words[0x18 / 4];
+        for (n, word) in words[0x800 / 4..].iter().enumerate() {
+            let address = dest_addr + (4 * n as u32);
+            memory.write_word(address, *word);
+            overwritten.insert(Console::physical(address));
+        }
+    }
+
+    //handles every event that's due and returns false if the emulator should stop
+    //backends run until the scheduler's next event is due and then call this so they
+    // only need to keep the scheduler's cycle count up to date
+    pub fn run_events(&mut self) -> bool {
+        while let Some((timestamp, event)) = self.scheduler.pop_due_event() {
+            match event {
+                ScheduledEvent::HBlank => {
+                    for irq in self.timers.hblank_start() {
+                        self.request_interrupt(irq);
+                    }
+                },
+                ScheduledEvent::Scanline => {
+                    if !self.end_scanline(timestamp) {
                         return false
-                    },
-                    Event::KeyDown {
-                        keycode: Some(Keycode::S),
-                        ..
-                    } => {
-                        println!("You pressed X");
-                    },
-                    Event::KeyDown {
-                        keycode: Some(Keycode::D),
-                        ..
-                    } => {
-                        println!("You pressed ◯");
-                    },
-                    Event::KeyDown {
-                        keycode: Some(Keycode::A),
-                        ..
-                    } => {
-                        println!("You pressed □");
-                    },
-                    Event::KeyDown {
-                        keycode: Some(Keycode::W),
-                        ..
-                    } => {
-                        println!("You pressed △");
-                    },
-                    Event::KeyDown {
-                        keycode: Some(Keycode::K),
-                        ..
-                    } => {
-                        println!("You pressed down");
-                    },
-                    Event::KeyDown {
-                        keycode: Some(Keycode::L),
-                        ..
-                    } => {
-                        println!("You pressed right");
-                    },
-                    Event::KeyDown {
-                        keycode: Some(Keycode::J),
-                        ..
-                    } => {
-                        println!("You pressed left");
-                    },
-                    Event::KeyDown {
-                        keycode: Some(Keycode::I),
-                        ..
-                    } => {
-                        println!("You pressed up");
-                    },
-                    Event::Quit { .. } => panic!(""),
-                    _ => {},
-                }
+                    }
+                },
+                ScheduledEvent::Timers => self.sync_timers(),
+                ScheduledEvent::CD(event) => {
+                    self.cd.run_event(event, &mut self.scheduler);
+                    self.update_cd_interrupt();
+                },
+                ScheduledEvent::DMA(channel) => self.finish_dma(channel),
+                ScheduledEvent::SPU => self.sync_spu(),
+                ScheduledEvent::SIO(event) => {
+                    self.sio.run_event(event, &mut self.scheduler);
+                    self.update_sio_interrupt();
+                },
             }
         }
         true
     }
 
+    //feeds the dot clock and hblank to the timers and handles vblank
+    //returns false if the emulator should stop
+    fn end_scanline(&mut self, timestamp: u64) -> bool {
+        let dots = self.gpu.dots_per_line();
+        for irq in self.timers.tick_dot_clock(dots) {
+            self.request_interrupt(irq);
+        }
+        self.timers.hblank_end();
+        let vblank = self.gpu.next_scanline();
+        let cycles_per_line = self.gpu.cycles_per_line();
+        //the next line starts when this one was due, not when the event was handled
+        self.scheduler
+            .schedule_at(ScheduledEvent::Scanline, timestamp + cycles_per_line);
+        self.scheduler.schedule_at(
+            ScheduledEvent::HBlank,
+            timestamp + self.gpu.cycles_until_hblank(),
+        );
+        match vblank {
+            Some(VBlank::Start) => {
+                self.timers.vblank_start();
+                self.request_interrupt(IRQ_VBLANK);
+                //one frame is shown per vblank
+                self.refresh_screen();
+                self.play_audio();
+                self.handle_events()
+            },
+            Some(VBlank::End) => {
+                self.timers.vblank_end();
+                true
+            },
+            None => true,
+        }
+    }
+
+    //brings the timers up to date and schedules the next time one may interrupt
+    fn sync_timers(&mut self) {
+        for irq in self.timers.sync(self.scheduler.cycles()) {
+            self.request_interrupt(irq);
+        }
+        self.scheduler
+            .schedule(ScheduledEvent::Timers, self.timers.cycles_until_irq());
+    }
+
+    //generates samples up to the current cycle and schedules the next time to catch
+    // up
+    fn sync_spu(&mut self) {
+        self.spu.sync(self.scheduler.cycles(), &mut self.cd);
+        if self.spu.take_interrupt() {
+            self.request_interrupt(IRQ_SPU);
+        }
+        self.scheduler.schedule(ScheduledEvent::SPU, SPU::SYNC_PERIOD);
+    }
+
+    fn update_cd_interrupt(&mut self) {
+        if self.cd.take_interrupt() {
+            self.request_interrupt(IRQ_CD);
+        }
+    }
+
+    fn update_sio_interrupt(&mut self) {
+        if self.sio.take_interrupt() {
+            self.request_interrupt(IRQ_CONTROLLER);
+        }
+    }
+
+    pub fn request_interrupt(&mut self, irq: u32) {
+        self.interrupts.request(irq);
+        self.update_interrupt_line();
+    }
+
+    //the interrupt controller's output is wired to bit 10 of CAUSE
+    fn update_interrupt_line(&mut self) {
+        self.cop0.set_interrupt_line(self.interrupts.active());
+    }
+
+    //jumps to the exception handler if an interrupt is pending and enabled
+    pub fn check_interrupts(&mut self) {
+        if self.cop0.interrupt_pending() {
+            let pc = self.r3000.pc();
+            let op = self.read_word(pc);
+            *self.r3000.pc_mut() = Console::enter_interrupt(&mut self.cop0, &mut self.gte, pc, op);
+        }
+    }
+
+    //the BIOS handler returns past a GTE command at EPC as if it already ran so
+    // it has to run before the exception
+    fn enter_interrupt(cop0: &mut Cop0, gte: &mut GTE, pc: u32, op: u32) -> u32 {
+        if get_primary_field(op) == 0x12 && op.nth_bit_bool(25) {
+            gte.execute_command(get_imm25(op));
+        }
+        cop0.generate_exception(Cop0Exception::Interrupt, pc)
+    }
+
+    //the screen only shows what's in the display area of VRAM
+    pub fn refresh_screen(&mut self) {
+        let (frame, width, height) = self.gpu.display_frame();
+        self.screen.draw_frame(&frame, width, height);
+        self.screen.refresh_window();
+        self.gpu.end_frame();
+    }
+
+    //sends the frame's samples to the audio output which may wait for them to play
+    fn play_audio(&mut self) {
+        self.sync_spu();
+        let samples = self.spu.take_samples();
+        self.audio.queue(&samples);
+        self.audio.wait();
+    }
+
+    //polled once per frame
+    fn handle_events(&mut self) -> bool {
+        for event in self.screen.event_pump().poll_iter() {
+            match event {
+                Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => {
+                    println!("Executed {} steps", self.i);
+                    return false
+                },
+                Event::Quit { .. } => panic!(""),
+                _ => self.input.handle_event(&event, &mut self.sio),
+            }
+        }
+        self.input.update_rumble(&self.sio);
+        true
+    }
+
     pub extern "C" fn read_byte_sign_extended(&mut self, address: u32) -> u32 {
         handle_response!(self.memory.read_byte_sign_extended(address), self)
     }
@@ -282,6 +526,18 @@ impl Console {
         handle_action!(self.memory.write_word(address, value), self);
     }
 
+    //LWC2 loads a word from memory into a GTE data register
+    pub extern "C" fn load_gte_word(&mut self, address: u32, idx: u32) {
+        let value = self.read_word(address);
+        self.gte.nth_data_reg_mut(idx).maybe_set(value);
+    }
+
+    //SWC2 stores a GTE data register to memory
+    pub extern "C" fn store_gte_word(&mut self, address: u32, idx: u32) {
+        let value = self.gte.nth_data_reg(idx);
+        self.write_word(address, value);
+    }
+
     pub extern "C" fn print_value(value: u32) {
         println!("{:#x?}", value);
     }
@@ -305,3 +561,47 @@ impl Console {
         self.cop0.generate_exception(kind, current_pc)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //sets up COP0 with interrupts enabled and the line raised
+    fn cop0() -> Cop0 {
+        let mut cop0: Cop0 = Default::default();
+        cop0.nth_data_reg_mut(12).maybe_set(1 | (1 << 10));
+        cop0.set_interrupt_line(true);
+        cop0
+    }
+
+    //NCLIP of a triangle with twice the area 100
+    fn gte() -> GTE {
+        let mut gte: GTE = Default::default();
+        gte.nth_data_reg_mut(12).maybe_set(0);
+        gte.nth_data_reg_mut(13).maybe_set(10);
+        gte.nth_data_reg_mut(14).maybe_set(10 << 16);
+        gte
+    }
+
+    #[test]
+    fn pending_gte_command_runs_before_the_interrupt() {
+        let mut cop0 = cop0();
+        let mut gte = gte();
+        let pc = Console::enter_interrupt(&mut cop0, &mut gte, 0x8001_0000, 0x4b40_0006);
+        assert_eq!(pc, 0x8000_0080);
+        assert_eq!(cop0.nth_data_reg(14), 0x8001_0000);
+        assert_eq!(gte.nth_data_reg(24), 100);
+    }
+
+    #[test]
+    fn other_ops_are_left_for_the_handler() {
+        //MFC2 and a plain ADDIU
+        for &op in &[0x4802_c000, 0x2408_0001] {
+            let mut cop0 = cop0();
+            let mut gte = gte();
+            let pc = Console::enter_interrupt(&mut cop0, &mut gte, 0x8001_0000, op);
+            assert_eq!(pc, 0x8000_0080);
+            assert_eq!(gte.nth_data_reg(24), 0);
+        }
+    }
+}
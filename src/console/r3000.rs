@@ -1,6 +1,7 @@
 use super::DelayedWrite;
 use super::MaybeSet;
 use super::Name;
+use crate::common::*;
 use std::collections::VecDeque;
 
 pub struct MutReg<'a> {
@@ -41,6 +42,22 @@ impl R3000 {
         R3000 { registers }
     }
 
+    //an approximation of the number of cycles taken by an instruction
+    //most instructions take a single cycle in the pipeline but stalls on loads,
+    // multiplies and divides make the average closer to two
+    pub fn cycles(op: u32) -> u32 {
+        let base = 2;
+        match get_primary_field(op) {
+            0x00 => match get_secondary_field(op) {
+                0x18 | 0x19 => base + 7,
+                0x1a | 0x1b => base + 34,
+                _ => base,
+            },
+            0x20..=0x26 | 0x32 => base + 3,
+            _ => base,
+        }
+    }
+
     //general purpose MIPS registers are referred to as R0..R31
     //this method is used to address registers R0 through R31
     pub fn nth_reg(&self, idx: u32) -> u32 {
@@ -0,0 +1,142 @@
+use super::cd::CDEvent;
+use super::sio::SIOEvent;
+
+//things that happen at a specific point in time rather than after each instruction
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    HBlank,
+    //the end of a scanline, which is also where hblank ends
+    Scanline,
+    Timers,
+    CD(CDEvent),
+    DMA(u8),
+    SPU,
+    SIO(SIOEvent),
+}
+
+//keeps track of the number of CPU cycles since the console was turned on
+#[derive(Default)]
+pub struct Scheduler {
+    cycles: u64,
+    //there are only a handful of events pending at any time so a Vec is enough
+    events: Vec<(u64, Event)>,
+}
+
+impl Scheduler {
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn advance(&mut self, cycles: u32) {
+        self.cycles += cycles as u64;
+    }
+
+    //each event can only be pending once so scheduling it again replaces it
+    pub fn schedule(&mut self, event: Event, delay: u64) {
+        self.schedule_at(event, self.cycles + delay);
+    }
+
+    //periodic events are scheduled from when they were due rather than when they
+    // were handled so they don't drift
+    pub fn schedule_at(&mut self, event: Event, timestamp: u64) {
+        self.cancel(event);
+        self.events.push((timestamp, event));
+    }
+
+    //backends run until this cycle before handling events
+    pub fn next_event_at(&self) -> u64 {
+        self.events
+            .iter()
+            .map(|&(timestamp, _)| timestamp)
+            .min()
+            .unwrap_or(u64::MAX)
+    }
+
+    pub fn cancel(&mut self, event: Event) {
+        self.events.retain(|&(_, e)| e != event);
+    }
+
+    //removes the earliest event that is due and returns it with the cycle it was due
+    // at
+    pub fn pop_due_event(&mut self) -> Option<(u64, Event)> {
+        let cycles = self.cycles;
+        let (idx, _) = self
+            .events
+            .iter()
+            .enumerate()
+            .filter(|(_, &(timestamp, _))| timestamp <= cycles)
+            .min_by_key(|(_, &(timestamp, _))| timestamp)?;
+        Some(self.events.remove(idx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_are_popped_in_order() {
+        let mut scheduler: Scheduler = Default::default();
+        scheduler.schedule(Event::SPU, 30);
+        scheduler.schedule(Event::HBlank, 10);
+        scheduler.schedule(Event::Scanline, 20);
+        assert_eq!(scheduler.next_event_at(), 10);
+        assert_eq!(scheduler.pop_due_event(), None);
+        scheduler.advance(100);
+        assert_eq!(scheduler.pop_due_event(), Some((10, Event::HBlank)));
+        assert_eq!(scheduler.pop_due_event(), Some((20, Event::Scanline)));
+        assert_eq!(scheduler.pop_due_event(), Some((30, Event::SPU)));
+        assert_eq!(scheduler.pop_due_event(), None);
+        assert_eq!(scheduler.next_event_at(), u64::MAX);
+    }
+
+    #[test]
+    fn scheduling_again_replaces_the_event() {
+        let mut scheduler: Scheduler = Default::default();
+        scheduler.schedule(Event::Timers, 10);
+        scheduler.schedule(Event::Timers, 50);
+        assert_eq!(scheduler.next_event_at(), 50);
+        scheduler.advance(50);
+        assert_eq!(scheduler.pop_due_event(), Some((50, Event::Timers)));
+        assert_eq!(scheduler.pop_due_event(), None);
+    }
+
+    #[test]
+    fn cancel_only_removes_that_event() {
+        let mut scheduler: Scheduler = Default::default();
+        scheduler.schedule(Event::DMA(2), 10);
+        scheduler.schedule(Event::DMA(6), 20);
+        scheduler.cancel(Event::DMA(2));
+        assert_eq!(scheduler.next_event_at(), 20);
+        scheduler.advance(20);
+        assert_eq!(scheduler.pop_due_event(), Some((20, Event::DMA(6))));
+        assert_eq!(scheduler.pop_due_event(), None);
+    }
+
+    #[test]
+    fn only_due_events_are_popped() {
+        let mut scheduler: Scheduler = Default::default();
+        scheduler.schedule(Event::Scanline, 5);
+        scheduler.schedule(Event::HBlank, 5);
+        scheduler.schedule(Event::SPU, 8);
+        scheduler.schedule(Event::Timers, 12);
+        scheduler.advance(9);
+        let mut due = Vec::new();
+        while let Some(event) = scheduler.pop_due_event() {
+            due.push(event);
+        }
+        assert_eq!(due.len(), 3);
+        assert!(due[..2].contains(&(5, Event::Scanline)));
+        assert!(due[..2].contains(&(5, Event::HBlank)));
+        assert_eq!(due[2], (8, Event::SPU));
+        assert_eq!(scheduler.next_event_at(), 12);
+    }
+
+    #[test]
+    fn schedule_at_uses_absolute_time() {
+        let mut scheduler: Scheduler = Default::default();
+        scheduler.advance(100);
+        scheduler.schedule_at(Event::Scanline, 90);
+        assert_eq!(scheduler.pop_due_event(), Some((90, Event::Scanline)));
+    }
+}
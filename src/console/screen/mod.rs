@@ -1,4 +1,4 @@
-use gl::types::GLuint;
+use gl::types::{GLint, GLuint};
 use std::ffi::CString;
 extern crate gl;
 extern crate sdl2;
@@ -6,30 +6,6 @@ extern crate sdl2;
 mod shader;
 use shader::Shader;
 
-#[derive(Debug)]
-pub struct Drawable {
-    positions: Vec<i16>,
-    colors: Vec<i16>,
-}
-
-impl Drawable {
-    pub fn new(positions: Vec<i16>, colors: Vec<i16>) -> Self {
-        Drawable { positions, colors }
-    }
-
-    pub fn n_points(&self) -> i32 {
-        (self.positions.len() as i32) / 2
-    }
-
-    pub fn positions(&self) -> &Vec<i16> {
-        &self.positions
-    }
-
-    pub fn colors(&self) -> &Vec<i16> {
-        &self.colors
-    }
-}
-
 pub struct Screen {
     sdl: sdl2::Sdl,
     video_subsystem: sdl2::VideoSubsystem,
@@ -40,6 +16,8 @@ pub struct Screen {
     vertex_shader: Shader,
     fragment_shader: Shader,
     program_id: GLuint,
+    frame_texture: GLuint,
+    frame_buffer: GLuint,
 }
 
 impl Screen {
@@ -77,6 +55,15 @@ impl Screen {
             gl::Clear(gl::COLOR_BUFFER_BIT);
         }
         window.gl_swap_window();
+        let mut frame_texture = 0;
+        let mut frame_buffer = 0;
+        unsafe {
+            gl::GenTextures(1, &mut frame_texture);
+            gl::BindTexture(gl::TEXTURE_2D, frame_texture);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as GLint);
+            gl::GenFramebuffers(1, &mut frame_buffer);
+        }
         Screen {
             sdl,
             video_subsystem,
@@ -86,95 +73,50 @@ impl Screen {
             vertex_shader,
             fragment_shader,
             program_id,
+            frame_texture,
+            frame_buffer,
         }
     }
 
-    pub fn draw(&mut self, object: Drawable) {
-        let vertices: Vec<i16> = match object.n_points() {
-            3 => vec![object.positions().clone(), object.colors().clone()]
-                .into_iter()
-                .flatten()
-                .collect(),
-            4 => {
-                let pos_t1 = object
-                    .positions()
-                    .clone()
-                    .into_iter()
-                    .skip(0)
-                    .cycle()
-                    .take(3 * 2);
-                let pos_t2 = object
-                    .positions()
-                    .clone()
-                    .into_iter()
-                    .skip(2)
-                    .cycle()
-                    .take(3 * 2);
-                let col_t1 = object
-                    .colors()
-                    .clone()
-                    .into_iter()
-                    .skip(0)
-                    .cycle()
-                    .take(3 * 3);
-                let col_t2 = object
-                    .colors()
-                    .clone()
-                    .into_iter()
-                    .skip(3)
-                    .cycle()
-                    .take(3 * 3);
-                vec![pos_t1, pos_t2, col_t1, col_t2]
-                    .into_iter()
-                    .flatten()
-                    .collect()
-            },
-            _ => {
-                panic!("drawing this object is not implemented {:?}", object);
-            },
-        };
-        let n_vertices = vertices.len() / 5;
-        let mut vbo: gl::types::GLuint = 0;
+    //copies a 24-bit RGB frame to the window, stretching it to fit
+    pub fn draw_frame(&mut self, pixels: &[u8], width: u32, height: u32) {
+        let (wx, wy) = self.window.drawable_size();
         unsafe {
-            gl::GenBuffers(1, &mut vbo);
-            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
-            gl::BufferData(
-                gl::ARRAY_BUFFER,
-                (vertices.len() * std::mem::size_of::<i16>()) as gl::types::GLsizeiptr,
-                vertices.as_ptr() as *const gl::types::GLvoid,
-                gl::STATIC_DRAW,
+            gl::BindTexture(gl::TEXTURE_2D, self.frame_texture);
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::RGB8 as GLint,
+                width as GLint,
+                height as GLint,
+                0,
+                gl::RGB,
+                gl::UNSIGNED_BYTE,
+                pixels.as_ptr() as *const gl::types::GLvoid,
             );
-            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
-        }
-        let mut vao: gl::types::GLuint = 0;
-        unsafe {
-            gl::GenVertexArrays(1, &mut vao);
-            gl::BindVertexArray(vao);
-            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
-            gl::EnableVertexAttribArray(0);
-            gl::VertexAttribPointer(
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.frame_buffer);
+            gl::FramebufferTexture2D(
+                gl::READ_FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::TEXTURE_2D,
+                self.frame_texture,
                 0,
-                2,
-                gl::SHORT,
-                gl::FALSE,
-                (2 * std::mem::size_of::<i16>()) as gl::types::GLint,
-                std::ptr::null(),
             );
-            gl::EnableVertexAttribArray(1);
-            gl::VertexAttribPointer(
-                1,
-                3,
-                gl::SHORT,
-                gl::FALSE,
-                (3 * std::mem::size_of::<i16>()) as gl::types::GLint,
-                (2 * n_vertices * std::mem::size_of::<i16>()) as *const gl::types::GLvoid,
+            //the first line in VRAM is the top of the screen so the frame is flipped
+            gl::BlitFramebuffer(
+                0,
+                0,
+                width as GLint,
+                height as GLint,
+                0,
+                wy as GLint,
+                wx as GLint,
+                0,
+                gl::COLOR_BUFFER_BIT,
+                gl::NEAREST,
             );
-            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
-            gl::BindVertexArray(0);
-        }
-        unsafe {
-            gl::BindVertexArray(vao);
-            gl::DrawArrays(gl::TRIANGLES, 0, n_vertices as i32);
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
         }
     }
 
@@ -182,6 +124,11 @@ impl Screen {
         self.window.gl_swap_window();
     }
 
+    //the audio output opens its device through the same SDL context
+    pub fn sdl(&self) -> &sdl2::Sdl {
+        &self.sdl
+    }
+
     pub fn event_pump(&mut self) -> &mut sdl2::EventPump {
         &mut self.event_pump
     }
@@ -189,6 +136,10 @@ impl Screen {
 
 impl Drop for Screen {
     fn drop(&mut self) {
-        unsafe { gl::DeleteProgram(self.program_id) }
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.frame_buffer);
+            gl::DeleteTextures(1, &self.frame_texture);
+            gl::DeleteProgram(self.program_id);
+        }
     }
 }
@@ -0,0 +1,326 @@
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom, Write};
+
+const SECTOR_LEN: usize = 128;
+const CARD_LEN: usize = 1024 * SECTOR_LEN;
+
+//the commands after the first byte of 0x81
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Command {
+    Read,
+    Write,
+    GetID,
+}
+
+//a 128KB memory card that's saved to a raw .mcr file
+pub struct MemoryCard {
+    file: File,
+    data: Vec<u8>,
+    //bit 3 is set until the first write to tell games the card was swapped
+    flag: u8,
+    command: Option<Command>,
+    //the number of bytes exchanged since the card was selected
+    position: usize,
+    sector: u16,
+    checksum: u8,
+    //the card echoes each byte one byte late while it's being written
+    previous: u8,
+    buffer: [u8; SECTOR_LEN],
+}
+
+impl MemoryCard {
+    //missing cards are created and formatted
+    pub fn open(filename: &str) -> io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(filename)?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        match data.len() {
+            0 => {
+                data = MemoryCard::formatted();
+                file.write_all(&data)?;
+                file.flush()?;
+            },
+            CARD_LEN => {},
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("{} is not a 128KB memory card", filename),
+                ))
+            },
+        }
+        Ok(MemoryCard {
+            file,
+            data,
+            flag: 0x08,
+            command: None,
+            position: 0,
+            sector: 0,
+            checksum: 0,
+            previous: 0,
+            buffer: [0; SECTOR_LEN],
+        })
+    }
+
+    //an empty card with a header, 15 free directory entries and no broken sectors
+    fn formatted() -> Vec<u8> {
+        let mut data = vec![0; CARD_LEN];
+        let mut frame = |n: usize, bytes: &[u8]| {
+            let sector = &mut data[n * SECTOR_LEN..(n + 1) * SECTOR_LEN];
+            sector[..bytes.len()].copy_from_slice(bytes);
+            sector[SECTOR_LEN - 1] = sector[..SECTOR_LEN - 1].iter().fold(0, |acc, b| acc ^ b);
+        };
+        frame(0, b"MC");
+        for n in 1..16 {
+            frame(n, &[0xa0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff]);
+        }
+        for n in 16..36 {
+            frame(n, &[0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0, 0xff, 0xff]);
+        }
+        //the last frame of the header block is used to test writes
+        frame(63, b"MC");
+        data
+    }
+
+    //called when the console releases the card's /SEL line
+    pub fn deselect(&mut self) {
+        self.command = None;
+        self.position = 0;
+    }
+
+    //returns the byte the card sends back and whether it pulses /ACK to ask for
+    // another one
+    pub fn exchange(&mut self, byte: u8) -> (u8, bool) {
+        let response = match (self.position, self.command) {
+            (0, _) => (0xff, true),
+            (1, _) => {
+                self.command = match byte {
+                    b'R' => Some(Command::Read),
+                    b'W' => Some(Command::Write),
+                    b'S' => Some(Command::GetID),
+                    _ => None,
+                };
+                (self.flag, self.command.is_some())
+            },
+            (2, _) => (0x5a, true),
+            (3, _) => (0x5d, true),
+            (_, Some(Command::Read)) => self.read(byte),
+            (_, Some(Command::Write)) => self.write(byte),
+            (n, _) => match [0x5c, 0x5d, 0x04, 0x00, 0x00, 0x80].get(n - 4) {
+                Some(&response) => (response, n < 9),
+                None => (0xff, false),
+            },
+        };
+        self.position += 1;
+        if !response.1 {
+            self.deselect();
+        }
+        response
+    }
+
+    fn valid_sector(&self) -> bool {
+        (self.sector as usize) < CARD_LEN / SECTOR_LEN
+    }
+
+    //the sector number comes first and is then confirmed before the data
+    fn read(&mut self, byte: u8) -> (u8, bool) {
+        let [msb, lsb] = self.sector.to_be_bytes();
+        match self.position {
+            4 => {
+                self.sector = (byte as u16) << 8;
+                (0x00, true)
+            },
+            5 => {
+                self.sector |= byte as u16;
+                (msb, true)
+            },
+            6 => (0x5c, true),
+            7 => (0x5d, true),
+            8 if !self.valid_sector() => (0xff, false),
+            8 => {
+                self.checksum = msb ^ lsb;
+                (msb, true)
+            },
+            9 => (lsb, true),
+            n @ 10..=137 => {
+                let data = self.data[self.sector as usize * SECTOR_LEN + n - 10];
+                self.checksum ^= data;
+                (data, true)
+            },
+            138 => (self.checksum, true),
+            //the end byte is G for good
+            _ => (b'G', false),
+        }
+    }
+
+    fn write(&mut self, byte: u8) -> (u8, bool) {
+        let response = match self.position {
+            4 => {
+                self.sector = (byte as u16) << 8;
+                self.checksum = byte;
+                (0x00, true)
+            },
+            5 => {
+                self.sector |= byte as u16;
+                self.checksum ^= byte;
+                (self.previous, true)
+            },
+            n @ 6..=133 => {
+                self.buffer[n - 6] = byte;
+                self.checksum ^= byte;
+                (self.previous, true)
+            },
+            134 => {
+                //the checksum byte is compared once the whole sector is in
+                self.checksum ^= byte;
+                (self.previous, true)
+            },
+            135 => (0x5c, true),
+            136 => (0x5d, true),
+            _ => (self.finish_write(), false),
+        };
+        self.previous = byte;
+        response
+    }
+
+    //returns the end byte which is G for good, N for a bad checksum or 0xff for a
+    // bad sector
+    fn finish_write(&mut self) -> u8 {
+        if !self.valid_sector() {
+            return 0xff
+        }
+        if self.checksum != 0 {
+            return b'N'
+        }
+        let offset = self.sector as usize * SECTOR_LEN;
+        self.data[offset..offset + SECTOR_LEN].copy_from_slice(&self.buffer);
+        self.flag = 0;
+        if let Err(e) = self.flush(offset) {
+            println!("Unable to save memory card: {}", e);
+        }
+        b'G'
+    }
+
+    //each sector is saved as soon as it's written so nothing is lost if the
+    // emulator is closed
+    fn flush(&mut self, offset: usize) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(offset as u64))?;
+        self.file.write_all(&self.data[offset..offset + SECTOR_LEN])?;
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    //each test gets its own card so they can run in parallel
+    fn card(name: &str) -> (MemoryCard, String) {
+        let path = std::env::temp_dir().join(format!("guayaba_{}.mcr", name));
+        let path = path.to_str().unwrap().to_string();
+        let _ = fs::remove_file(&path);
+        (MemoryCard::open(&path).unwrap(), path)
+    }
+
+    fn send(card: &mut MemoryCard, bytes: &[u8]) -> Vec<u8> {
+        let responses = bytes.iter().map(|&byte| card.exchange(byte).0).collect();
+        card.deselect();
+        responses
+    }
+
+    fn write_command(sector: u16, data: &[u8], checksum: u8) -> Vec<u8> {
+        let [msb, lsb] = sector.to_be_bytes();
+        let mut bytes = vec![0x81, b'W', 0, 0, msb, lsb];
+        bytes.extend(data);
+        bytes.extend(&[checksum, 0, 0, 0]);
+        bytes
+    }
+
+    fn read_command(sector: u16) -> Vec<u8> {
+        let [msb, lsb] = sector.to_be_bytes();
+        let mut bytes = vec![0x81, b'R', 0, 0, msb, lsb];
+        bytes.extend(&[0; 134]);
+        bytes
+    }
+
+    //the checksum covers the sector number's MSB and LSB and then the data
+    fn checksum(sector: u16, data: &[u8]) -> u8 {
+        let [msb, lsb] = sector.to_be_bytes();
+        data.iter().fold(msb ^ lsb, |acc, b| acc ^ b)
+    }
+
+    fn sector_data() -> Vec<u8> {
+        (0..SECTOR_LEN).map(|i| (i as u8).wrapping_mul(3)).collect()
+    }
+
+    #[test]
+    fn new_cards_are_formatted() {
+        let (_, path) = card("formatted");
+        let data = fs::read(&path).unwrap();
+        assert_eq!(data.len(), CARD_LEN);
+        assert_eq!(&data[..2], b"MC");
+        assert_eq!(data[SECTOR_LEN - 1], b'M' ^ b'C');
+        assert_eq!(data[SECTOR_LEN], 0xa0);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn get_id() {
+        let (mut card, path) = card("get_id");
+        let responses = send(&mut card, &[0x81, b'S', 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(responses, [0xff, 0x08, 0x5a, 0x5d, 0x5c, 0x5d, 0x04, 0x00, 0x00, 0x80]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn write_and_read_back() {
+        let (mut card, path) = card("write_and_read_back");
+        let data = sector_data();
+        let checksum = checksum(5, &data);
+        let responses = send(&mut card, &write_command(5, &data, checksum));
+        assert_eq!(responses[responses.len() - 3..], [0x5c, 0x5d, b'G']);
+        //the flag is cleared by the first good write
+        assert_eq!(send(&mut card, &[0x81, b'S'])[1], 0x00);
+
+        let responses = send(&mut card, &read_command(5));
+        assert_eq!(responses[6..10], [0x5c, 0x5d, 0x00, 0x05]);
+        assert_eq!(responses[10..138], data[..]);
+        assert_eq!(responses[138..], [checksum, b'G']);
+        drop(card);
+        let saved = fs::read(&path).unwrap();
+        assert_eq!(saved[5 * SECTOR_LEN..6 * SECTOR_LEN], data[..]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn bad_checksum() {
+        let (mut card, path) = card("bad_checksum");
+        let data = sector_data();
+        let checksum = checksum(5, &data);
+        let responses = send(&mut card, &write_command(5, &data, !checksum));
+        assert_eq!(responses[responses.len() - 1], b'N');
+        assert_eq!(send(&mut card, &[0x81, b'S'])[1], 0x08);
+        let responses = send(&mut card, &read_command(5));
+        assert_eq!(responses[10..138], MemoryCard::formatted()[5 * SECTOR_LEN..6 * SECTOR_LEN]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn invalid_sector() {
+        let (mut card, path) = card("invalid_sector");
+        let responses = send(&mut card, &read_command(0x400));
+        assert_eq!(responses.len(), 140);
+        assert_eq!(responses[8], 0xff);
+        let data = sector_data();
+        let checksum = checksum(0x400, &data);
+        let responses = send(&mut card, &write_command(0x400, &data, checksum));
+        assert_eq!(responses[responses.len() - 1], 0xff);
+        fs::remove_file(path).unwrap();
+    }
+}
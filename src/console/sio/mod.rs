@@ -0,0 +1,372 @@
+use super::scheduler::{Event, Scheduler};
+use crate::register::BitTwiddle;
+use std::collections::VecDeque;
+
+mod memcard;
+mod pad;
+
+pub use memcard::MemoryCard;
+pub use pad::{Button, Pad, Stick};
+
+//things that happen some time after a byte is written to JOY_DATA
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SIOEvent {
+    //the byte finished shifting out and the response was shifted in
+    Transfer,
+    //the device pulled /ACK low to ask for the next byte
+    Ack,
+    //the device let go of /ACK
+    AckEnd,
+}
+
+//the device that's currently talking on the selected port
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Device {
+    //the first byte after /SEL goes low picks the device
+    None,
+    Pad,
+    MemoryCard,
+    //nothing answered so the rest of the bytes are ignored until /SEL goes high
+    Ignored,
+}
+
+//the serial port that the controllers and memory cards are connected to
+pub struct SIO {
+    pads: [Pad; 2],
+    cards: [Option<MemoryCard>; 2],
+    device: Device,
+    //the byte being sent to the device
+    tx: Option<u8>,
+    rx_fifo: VecDeque<u8>,
+    mode: u32,
+    control: u32,
+    baud: u32,
+    ack: bool,
+    irq: bool,
+    irq_line: bool,
+}
+
+impl SIO {
+    pub const PORT: u32 = 0x1f80_1040;
+    const RX_FIFO_LEN: usize = 8;
+    //roughly how long a device takes to pulse /ACK after it receives a byte
+    const ACK_DELAY: u64 = 338;
+    //roughly how long /ACK stays low
+    const ACK_LEN: u64 = 100;
+
+    pub fn new() -> Self {
+        SIO {
+            //there's only a pad in the first slot unless a second controller is found
+            pads: [Pad::new(true), Pad::new(false)],
+            cards: [None, None],
+            device: Device::None,
+            tx: None,
+            rx_fifo: VecDeque::new(),
+            mode: 0,
+            control: 0,
+            baud: 0,
+            ack: false,
+            irq: false,
+            irq_line: false,
+        }
+    }
+
+    pub fn insert_card(&mut self, slot: usize, card: MemoryCard) {
+        self.cards[slot] = Some(card);
+    }
+
+    pub fn pad(&self, slot: usize) -> &Pad {
+        &self.pads[slot]
+    }
+
+    pub fn pad_mut(&mut self, slot: usize) -> &mut Pad {
+        &mut self.pads[slot]
+    }
+
+    //bit 13 of JOY_CTRL picks which port /SEL goes to
+    fn slot(&self) -> usize {
+        self.control.nth_bit(13) as usize
+    }
+
+    fn selected(&self) -> bool {
+        self.control.nth_bit_bool(1)
+    }
+
+    fn status(&self) -> u32 {
+        let mut status: u32 = 0;
+        if self.tx.is_none() {
+            status.set(0).set(2);
+        }
+        if !self.rx_fifo.is_empty() {
+            status.set(1);
+        }
+        if self.ack {
+            status.set(7);
+        }
+        if self.irq {
+            status.set(9);
+        }
+        status
+    }
+
+    pub fn read(&mut self, address: u32, width: u32) -> u32 {
+        let offset = address - SIO::PORT;
+        match width {
+            4 => self.read_half(offset) | (self.read_half(offset + 2) << 16),
+            2 => self.read_half(offset),
+            _ => (self.read_half(offset & !1) >> (8 * (offset & 1))) & 0xff,
+        }
+    }
+
+    fn read_half(&mut self, offset: u32) -> u32 {
+        match offset {
+            //reading an empty FIFO returns the last byte on the bus which is usually 0xff
+            0 => self.rx_fifo.pop_front().unwrap_or(0xff) as u32,
+            4 => self.status().lowest_bits(16),
+            8 => self.mode,
+            0xa => self.control,
+            0xe => self.baud,
+            _ => 0,
+        }
+    }
+
+    pub fn write(&mut self, address: u32, value: u32, width: u32, scheduler: &mut Scheduler) {
+        let offset = address - SIO::PORT;
+        match width {
+            4 => {
+                self.write_half(offset, value.lowest_bits(16), scheduler);
+                self.write_half(offset + 2, value >> 16, scheduler);
+            },
+            _ => self.write_half(offset & !1, value.lowest_bits(16), scheduler),
+        }
+    }
+
+    fn write_half(&mut self, offset: u32, value: u32, scheduler: &mut Scheduler) {
+        match offset {
+            0 => self.transmit(value as u8, scheduler),
+            8 => self.mode = value,
+            0xa => self.write_control(value, scheduler),
+            0xe => self.baud = value,
+            _ => {},
+        }
+    }
+
+    fn write_control(&mut self, value: u32, scheduler: &mut Scheduler) {
+        let previous_slot = self.slot();
+        //the acknowledge and reset bits aren't stored
+        let mut control = value;
+        self.control = *control.clear(4).clear(6);
+        if value.nth_bit_bool(4) {
+            self.irq = false;
+        }
+        if value.nth_bit_bool(6) {
+            self.control = 0;
+            self.mode = 0;
+            self.baud = 0;
+            self.tx = None;
+            self.rx_fifo.clear();
+            self.ack = false;
+            self.irq = false;
+            scheduler.cancel(Event::SIO(SIOEvent::Transfer));
+            scheduler.cancel(Event::SIO(SIOEvent::Ack));
+            scheduler.cancel(Event::SIO(SIOEvent::AckEnd));
+        }
+        if !self.selected() || self.slot() != previous_slot {
+            self.deselect();
+        }
+    }
+
+    fn deselect(&mut self) {
+        self.device = Device::None;
+        for pad in self.pads.iter_mut() {
+            pad.deselect();
+        }
+        for card in self.cards.iter_mut().flatten() {
+            card.deselect();
+        }
+    }
+
+    //each bit takes BAUD cycles with the usual MUL1 setting
+    fn transmit(&mut self, byte: u8, scheduler: &mut Scheduler) {
+        self.tx = Some(byte);
+        self.ack = false;
+        scheduler.cancel(Event::SIO(SIOEvent::Ack));
+        scheduler.cancel(Event::SIO(SIOEvent::AckEnd));
+        let delay = (self.baud.max(1) * 8) as u64;
+        scheduler.schedule(Event::SIO(SIOEvent::Transfer), delay);
+    }
+
+    pub fn run_event(&mut self, event: SIOEvent, scheduler: &mut Scheduler) {
+        match event {
+            SIOEvent::Transfer => {
+                if let Some(byte) = self.tx.take() {
+                    let (response, ack) = self.exchange(byte);
+                    if self.rx_fifo.len() == SIO::RX_FIFO_LEN {
+                        self.rx_fifo.pop_front();
+                    }
+                    self.rx_fifo.push_back(response);
+                    if ack {
+                        scheduler.schedule(Event::SIO(SIOEvent::Ack), SIO::ACK_DELAY);
+                    }
+                }
+            },
+            //the interrupt stays latched after /ACK goes back high
+            SIOEvent::Ack => {
+                self.ack = true;
+                if self.control.nth_bit_bool(12) {
+                    self.irq = true;
+                }
+                scheduler.schedule(Event::SIO(SIOEvent::AckEnd), SIO::ACK_LEN);
+            },
+            SIOEvent::AckEnd => self.ack = false,
+        }
+    }
+
+    //returns the byte the device sent back and whether it acknowledged
+    fn exchange(&mut self, byte: u8) -> (u8, bool) {
+        if !self.selected() {
+            return (0xff, false)
+        }
+        if self.device == Device::None {
+            self.device = match byte {
+                0x01 => Device::Pad,
+                0x81 => Device::MemoryCard,
+                _ => Device::Ignored,
+            };
+        }
+        let slot = self.slot();
+        let (response, ack) = match self.device {
+            Device::Pad => self.pads[slot].exchange(byte),
+            Device::MemoryCard => match &mut self.cards[slot] {
+                Some(card) => card.exchange(byte),
+                None => (0xff, false),
+            },
+            _ => (0xff, false),
+        };
+        //the device stops listening once it's done with the command
+        if !ack {
+            self.device = Device::Ignored;
+        }
+        (response, ack)
+    }
+
+    //returns true if the interrupt line to the CPU went high
+    pub fn take_interrupt(&mut self) -> bool {
+        let rising = self.irq && !self.irq_line;
+        self.irq_line = self.irq;
+        rising
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATA: u32 = SIO::PORT;
+    const STAT: u32 = SIO::PORT + 4;
+    const CTRL: u32 = SIO::PORT + 0xa;
+    const BAUD: u32 = SIO::PORT + 0xe;
+
+    //selects port 1 with the /ACK interrupt enabled
+    fn sio(scheduler: &mut Scheduler) -> SIO {
+        let mut sio = SIO::new();
+        sio.write(CTRL, 0x1003, 2, scheduler);
+        sio.write(BAUD, 0x88, 2, scheduler);
+        sio
+    }
+
+    //runs the next event and returns it along with when it happened
+    fn run_next_event(sio: &mut SIO, scheduler: &mut Scheduler) -> Option<(u64, SIOEvent)> {
+        let timestamp = scheduler.next_event_at();
+        if timestamp == u64::MAX {
+            return None
+        }
+        scheduler.advance((timestamp - scheduler.cycles()) as u32);
+        match scheduler.pop_due_event() {
+            Some((_, Event::SIO(event))) => {
+                sio.run_event(event, scheduler);
+                Some((timestamp, event))
+            },
+            _ => None,
+        }
+    }
+
+    //sends a byte and returns the response once /ACK is released
+    fn exchange(sio: &mut SIO, scheduler: &mut Scheduler, byte: u8) -> (u8, bool) {
+        sio.write(DATA, byte as u32, 1, scheduler);
+        run_next_event(sio, scheduler);
+        let ack = run_next_event(sio, scheduler).is_some();
+        run_next_event(sio, scheduler);
+        (sio.read(DATA, 1) as u8, ack)
+    }
+
+    #[test]
+    fn ack_is_a_pulse() {
+        let mut scheduler: Scheduler = Default::default();
+        let mut sio = sio(&mut scheduler);
+        sio.write(DATA, 0x01, 1, &mut scheduler);
+        assert!(!sio.read(STAT, 2).nth_bit_bool(0));
+        //8 bits at BAUD cycles each
+        let transfer = run_next_event(&mut sio, &mut scheduler);
+        assert_eq!(transfer, Some((0x88 * 8, SIOEvent::Transfer)));
+        assert_eq!(sio.read(STAT, 2) & 0x283, 0x003);
+        let ack = run_next_event(&mut sio, &mut scheduler);
+        assert_eq!(ack, Some((0x88 * 8 + SIO::ACK_DELAY, SIOEvent::Ack)));
+        assert_eq!(sio.read(STAT, 2) & 0x280, 0x280);
+        assert!(sio.take_interrupt());
+        let ack_end = run_next_event(&mut sio, &mut scheduler);
+        let released = 0x88 * 8 + SIO::ACK_DELAY + SIO::ACK_LEN;
+        assert_eq!(ack_end, Some((released, SIOEvent::AckEnd)));
+        //the interrupt is latched until it's acknowledged
+        assert_eq!(sio.read(STAT, 2) & 0x280, 0x200);
+        sio.write(CTRL, 0x1013, 2, &mut scheduler);
+        assert_eq!(sio.read(STAT, 2) & 0x280, 0);
+        assert!(!sio.take_interrupt());
+    }
+
+    #[test]
+    fn sending_cuts_the_pulse_short() {
+        let mut scheduler: Scheduler = Default::default();
+        let mut sio = sio(&mut scheduler);
+        sio.write(DATA, 0x01, 1, &mut scheduler);
+        run_next_event(&mut sio, &mut scheduler);
+        run_next_event(&mut sio, &mut scheduler);
+        sio.write(DATA, 0x42, 1, &mut scheduler);
+        assert!(!sio.read(STAT, 2).nth_bit_bool(7));
+        assert_eq!(run_next_event(&mut sio, &mut scheduler).unwrap().1, SIOEvent::Transfer);
+    }
+
+    #[test]
+    fn digital_read() {
+        let mut scheduler: Scheduler = Default::default();
+        let mut sio = sio(&mut scheduler);
+        sio.pad_mut(0).set_button(Button::Start, true);
+        let responses = [0x01, 0x42, 0, 0, 0]
+            .iter()
+            .map(|&byte| exchange(&mut sio, &mut scheduler, byte))
+            .collect::<Vec<(u8, bool)>>();
+        let expected = [(0xff, true), (0x41, true), (0x5a, true), (0xf7, true), (0xff, false)];
+        assert_eq!(responses, expected);
+    }
+
+    #[test]
+    fn nothing_answers_without_sel() {
+        let mut scheduler: Scheduler = Default::default();
+        let mut sio = sio(&mut scheduler);
+        sio.write(CTRL, 0x1001, 2, &mut scheduler);
+        assert_eq!(exchange(&mut sio, &mut scheduler, 0x01), (0xff, false));
+        //the second port has no pad
+        sio.write(CTRL, 0x3003, 2, &mut scheduler);
+        assert_eq!(exchange(&mut sio, &mut scheduler, 0x01), (0xff, false));
+    }
+
+    #[test]
+    fn reset_cancels_the_transfer() {
+        let mut scheduler: Scheduler = Default::default();
+        let mut sio = sio(&mut scheduler);
+        sio.write(DATA, 0x01, 1, &mut scheduler);
+        sio.write(CTRL, 0x40, 2, &mut scheduler);
+        assert_eq!(scheduler.next_event_at(), u64::MAX);
+        assert_eq!(sio.read(STAT, 2), 0x5);
+    }
+}
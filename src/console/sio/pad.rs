@@ -0,0 +1,369 @@
+//the bits of the button state in the order the pad sends them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Button {
+    Select = 0,
+    L3,
+    R3,
+    Start,
+    Up,
+    Right,
+    Down,
+    Left,
+    L2,
+    R2,
+    L1,
+    R1,
+    Triangle,
+    Circle,
+    Cross,
+    Square,
+}
+
+//the analog axes in the order the pad sends them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stick {
+    RightX = 0,
+    RightY,
+    LeftX,
+    LeftY,
+}
+
+//a DualShock plugged into one of the joypad ports which acts like a digital pad until
+// it's switched to analog mode
+pub struct Pad {
+    connected: bool,
+    //the bits are set for buttons that are held down
+    pressed: u16,
+    //0x80 is the center of each axis
+    sticks: [u8; 4],
+    analog: bool,
+    //games can stop the analog button from switching modes
+    analog_locked: bool,
+    config_mode: bool,
+    //which motor each byte after a read command drives where 0 is the small motor, 1
+    // is the large motor and 0xff is unused
+    rumble_config: [u8; 6],
+    small_motor: bool,
+    large_motor: u8,
+    //the number of bytes exchanged since the pad was selected
+    position: usize,
+    command: u8,
+    //the ID sent for the current command
+    id: u8,
+    //the total number of bytes in the current command
+    len: usize,
+    //the first byte after the header which some commands use as an index
+    parameter: u8,
+}
+
+impl Pad {
+    const DIGITAL_ID: u8 = 0x41;
+    const ANALOG_ID: u8 = 0x73;
+    const CONFIG_ID: u8 = 0xf3;
+
+    pub fn new(connected: bool) -> Self {
+        Pad {
+            connected,
+            pressed: 0,
+            sticks: [0x80; 4],
+            analog: false,
+            analog_locked: false,
+            config_mode: false,
+            rumble_config: [0xff; 6],
+            small_motor: false,
+            large_motor: 0,
+            position: 0,
+            command: 0,
+            id: Pad::DIGITAL_ID,
+            len: 0,
+            parameter: 0,
+        }
+    }
+
+    pub fn connect(&mut self) {
+        self.connected = true;
+    }
+
+    pub fn disconnect(&mut self) {
+        self.connected = false;
+    }
+
+    //lets go of everything when the controller driving the pad goes away
+    pub fn release(&mut self) {
+        self.pressed = 0;
+        self.sticks = [0x80; 4];
+    }
+
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        let bit = 1 << button as u16;
+        if pressed {
+            self.pressed |= bit;
+        } else {
+            self.pressed &= !bit;
+        }
+    }
+
+    pub fn set_stick(&mut self, stick: Stick, value: u8) {
+        self.sticks[stick as usize] = value;
+    }
+
+    //the analog button on the pad itself
+    pub fn toggle_analog(&mut self) {
+        if !self.analog_locked {
+            self.analog = !self.analog;
+        }
+    }
+
+    //the state of the small motor and the speed of the large motor
+    pub fn motors(&self) -> (bool, u8) {
+        (self.small_motor, self.large_motor)
+    }
+
+    //called when the console releases the pad's /SEL line
+    pub fn deselect(&mut self) {
+        self.position = 0;
+    }
+
+    fn current_id(&self) -> u8 {
+        if self.config_mode {
+            Pad::CONFIG_ID
+        } else if self.analog {
+            Pad::ANALOG_ID
+        } else {
+            Pad::DIGITAL_ID
+        }
+    }
+
+    //returns the byte the pad sends back and whether it pulses /ACK to ask for
+    // another one
+    pub fn exchange(&mut self, byte: u8) -> (u8, bool) {
+        if !self.connected {
+            return (0xff, false)
+        }
+        let response = match self.position {
+            0 => (0xff, true),
+            1 => {
+                //only reading the buttons and entering config mode work outside of
+                // config mode
+                let valid = match byte {
+                    0x42 | 0x43 => true,
+                    0x40..=0x4f => self.config_mode,
+                    _ => false,
+                };
+                self.command = byte;
+                self.id = self.current_id();
+                //the lower bits of the ID are the number of halfwords that follow
+                self.len = 3 + 2 * (self.id & 0xf) as usize;
+                if valid {
+                    (self.id, true)
+                } else {
+                    (0xff, false)
+                }
+            },
+            2 => (0x5a, true),
+            position => {
+                let n = position - 3;
+                let response = self.payload(n);
+                self.receive(n, byte);
+                //the last byte isn't acknowledged
+                (response, position + 1 < self.len)
+            },
+        };
+        self.position += 1;
+        if !response.1 {
+            self.position = 0;
+        }
+        response
+    }
+
+    //the nth byte of the current command's response after the header
+    fn payload(&self, n: usize) -> u8 {
+        let table = |tables: [[u8; 6]; 2]| tables[(self.parameter == 1) as usize][n];
+        match self.command {
+            0x42 | 0x43 if self.id != Pad::CONFIG_ID || self.command == 0x42 => {
+                //the buttons are sent active low
+                let buttons = (!self.pressed).to_le_bytes();
+                match n {
+                    0 | 1 => buttons[n],
+                    _ => self.sticks[n - 2],
+                }
+            },
+            //the model, whether the pad is in analog mode and a few constants
+            0x45 => [0x01, 0x02, self.analog as u8, 0x02, 0x01, 0x00][n],
+            0x46 if n > 0 => table([[0, 0, 1, 2, 0, 0x0a], [0, 0, 1, 1, 1, 0x14]]),
+            0x47 => [0, 0, 2, 0, 1, 0][n],
+            0x4c if n > 0 => table([[0, 0, 0, 4, 0, 0], [0, 0, 0, 7, 0, 0]]),
+            //the old mapping is sent back while the new one comes in
+            0x4d => self.rumble_config[n],
+            _ => 0,
+        }
+    }
+
+    //handles the nth byte the console sent after the header
+    fn receive(&mut self, n: usize, byte: u8) {
+        if n == 0 {
+            self.parameter = byte;
+        }
+        match (self.command, n) {
+            (0x42, _) => match self.rumble_config[n] {
+                0x00 => self.small_motor = byte & 1 != 0,
+                0x01 => self.large_motor = byte,
+                _ => {},
+            },
+            (0x43, 0) => self.config_mode = byte == 1,
+            (0x44, 0) if byte <= 1 => self.analog = byte == 1,
+            (0x44, 1) => self.analog_locked = byte == 3,
+            (0x4d, _) => {
+                self.rumble_config[n] = byte;
+                self.small_motor = false;
+                self.large_motor = 0;
+            },
+            _ => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn send(pad: &mut Pad, bytes: &[u8]) -> Vec<(u8, bool)> {
+        let responses = bytes.iter().map(|&byte| pad.exchange(byte)).collect();
+        pad.deselect();
+        responses
+    }
+
+    fn replies(pad: &mut Pad, bytes: &[u8]) -> Vec<u8> {
+        send(pad, bytes).iter().map(|&(response, _)| response).collect()
+    }
+
+    #[test]
+    fn digital_read() {
+        let mut pad = Pad::new(true);
+        pad.set_button(Button::Cross, true);
+        pad.set_button(Button::Up, true);
+        let responses = send(&mut pad, &[0x01, 0x42, 0, 0, 0]);
+        let expected = [(0xff, true), (0x41, true), (0x5a, true), (0xef, true), (0xbf, false)];
+        assert_eq!(responses, expected);
+        pad.set_button(Button::Cross, false);
+        assert_eq!(replies(&mut pad, &[0x01, 0x42, 0, 0, 0])[3..], [0xef, 0xff]);
+    }
+
+    #[test]
+    fn unknown_commands_are_not_acknowledged() {
+        let mut pad = Pad::new(true);
+        assert_eq!(send(&mut pad, &[0x01, 0x44]), [(0xff, true), (0xff, false)]);
+        assert_eq!(send(&mut pad, &[0x01, 0x00]), [(0xff, true), (0xff, false)]);
+        //the pad starts over after it stops acknowledging
+        assert_eq!(replies(&mut pad, &[0x01, 0x42, 0]), [0xff, 0x41, 0x5a]);
+    }
+
+    #[test]
+    fn disconnected_pads_never_answer() {
+        let mut pad = Pad::new(false);
+        assert_eq!(send(&mut pad, &[0x01]), [(0xff, false)]);
+        pad.connect();
+        assert_eq!(send(&mut pad, &[0x01]), [(0xff, true)]);
+    }
+
+    //config mode commands are padded to 9 bytes
+    fn config_command(command: u8, parameters: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0x01, command, 0x00];
+        bytes.extend(parameters);
+        bytes.resize(9, 0x00);
+        bytes
+    }
+
+    fn enter_config(pad: &mut Pad) {
+        replies(pad, &[0x01, 0x43, 0x00, 0x01, 0x00]);
+    }
+
+    fn exit_config(pad: &mut Pad) {
+        replies(pad, &config_command(0x43, &[0x00]));
+    }
+
+    #[test]
+    fn config_mode() {
+        let mut pad = Pad::new(true);
+        pad.set_button(Button::Select, true);
+        //entering config mode from digital mode still sends the buttons
+        let responses = replies(&mut pad, &[0x01, 0x43, 0x00, 0x01, 0x00]);
+        assert_eq!(responses, [0xff, 0x41, 0x5a, 0xfe, 0xff]);
+        let responses = replies(&mut pad, &config_command(0x43, &[0x01]));
+        assert_eq!(responses, [0xff, 0xf3, 0x5a, 0, 0, 0, 0, 0, 0]);
+        let responses = replies(&mut pad, &config_command(0x42, &[]));
+        assert_eq!(responses[..5], [0xff, 0xf3, 0x5a, 0xfe, 0xff]);
+        exit_config(&mut pad);
+        assert_eq!(replies(&mut pad, &[0x01, 0x42, 0, 0, 0]), [0xff, 0x41, 0x5a, 0xfe, 0xff]);
+    }
+
+    #[test]
+    fn set_and_lock_analog_mode() {
+        let mut pad = Pad::new(true);
+        enter_config(&mut pad);
+        replies(&mut pad, &config_command(0x44, &[0x01, 0x03]));
+        exit_config(&mut pad);
+        //the analog button is locked
+        pad.toggle_analog();
+        pad.set_stick(Stick::LeftX, 0x20);
+        pad.set_stick(Stick::RightY, 0xe0);
+        let responses = send(&mut pad, &config_command(0x42, &[]));
+        let bytes = responses.iter().map(|&(byte, _)| byte).collect::<Vec<u8>>();
+        assert_eq!(bytes, [0xff, 0x73, 0x5a, 0xff, 0xff, 0x80, 0xe0, 0x20, 0x80]);
+        //all 9 bytes are acknowledged except the last one
+        assert_eq!(responses.iter().filter(|&&(_, ack)| ack).count(), 8);
+        assert!(!responses[8].1);
+        //switch back to digital mode without the lock
+        enter_config(&mut pad);
+        replies(&mut pad, &config_command(0x44, &[0x00, 0x02]));
+        exit_config(&mut pad);
+        assert_eq!(replies(&mut pad, &[0x01, 0x42])[1], 0x41);
+        pad.toggle_analog();
+        assert_eq!(replies(&mut pad, &[0x01, 0x42])[1], 0x73);
+    }
+
+    #[test]
+    fn status_and_constants() {
+        let mut pad = Pad::new(true);
+        pad.toggle_analog();
+        enter_config(&mut pad);
+        let responses = replies(&mut pad, &config_command(0x45, &[]));
+        assert_eq!(responses[3..], [0x01, 0x02, 0x01, 0x02, 0x01, 0x00]);
+        let responses = replies(&mut pad, &config_command(0x46, &[0x00]));
+        assert_eq!(responses[3..], [0x00, 0x00, 0x01, 0x02, 0x00, 0x0a]);
+        let responses = replies(&mut pad, &config_command(0x46, &[0x01]));
+        assert_eq!(responses[3..], [0x00, 0x00, 0x01, 0x01, 0x01, 0x14]);
+        let responses = replies(&mut pad, &config_command(0x47, &[]));
+        assert_eq!(responses[3..], [0x00, 0x00, 0x02, 0x00, 0x01, 0x00]);
+        let responses = replies(&mut pad, &config_command(0x4c, &[0x00]));
+        assert_eq!(responses[3..], [0x00, 0x00, 0x00, 0x04, 0x00, 0x00]);
+        let responses = replies(&mut pad, &config_command(0x4c, &[0x01]));
+        assert_eq!(responses[3..], [0x00, 0x00, 0x00, 0x07, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn rumble_mapping() {
+        let mut pad = Pad::new(true);
+        enter_config(&mut pad);
+        replies(&mut pad, &config_command(0x44, &[0x01, 0x02]));
+        //the old mapping comes back while the new one is sent
+        let mapping = [0x00, 0x01, 0xff, 0xff, 0xff, 0xff];
+        let responses = replies(&mut pad, &config_command(0x4d, &mapping));
+        assert_eq!(responses[3..], [0xff; 6]);
+        let responses = replies(&mut pad, &config_command(0x4d, &mapping));
+        assert_eq!(responses[3..], mapping);
+        exit_config(&mut pad);
+        replies(&mut pad, &config_command(0x42, &[0x01, 0xc0]));
+        assert_eq!(pad.motors(), (true, 0xc0));
+        replies(&mut pad, &config_command(0x42, &[0x00, 0x00]));
+        assert_eq!(pad.motors(), (false, 0x00));
+    }
+
+    #[test]
+    fn unmapped_pads_dont_rumble() {
+        let mut pad = Pad::new(true);
+        pad.toggle_analog();
+        replies(&mut pad, &config_command(0x42, &[0x01, 0xc0]));
+        assert_eq!(pad.motors(), (false, 0x00));
+    }
+}
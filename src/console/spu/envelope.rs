@@ -0,0 +1,187 @@
+use crate::register::BitTwiddle;
+
+//the settings for one phase of an ADSR envelope or volume sweep
+#[derive(Clone, Copy, Debug)]
+struct Settings {
+    exponential: bool,
+    decrease: bool,
+    shift: u32,
+    //7-n when increasing and -8+n when decreasing
+    step: i32,
+}
+
+impl Settings {
+    fn new(exponential: bool, decrease: bool, shift: u32, step: u32) -> Self {
+        let step = if decrease {
+            -8 + step as i32
+        } else {
+            7 - step as i32
+        };
+        Settings {
+            exponential,
+            decrease,
+            shift,
+            step,
+        }
+    }
+}
+
+//counts the samples between each change in an envelope's level
+#[derive(Clone, Copy, Debug, Default)]
+struct Envelope {
+    wait: u32,
+}
+
+impl Envelope {
+    fn reset(&mut self) {
+        self.wait = 0;
+    }
+
+    //returns the level after one sample
+    fn tick(&mut self, level: i32, settings: Settings) -> i32 {
+        let mut cycles = 1 << settings.shift.saturating_sub(11);
+        let mut step = settings.step << 11u32.saturating_sub(settings.shift);
+        //exponential increases slow down near the top and exponential decreases are
+        // proportional to the current level
+        if settings.exponential {
+            if settings.decrease {
+                step = step * level / 0x8000;
+            } else if level > 0x6000 {
+                cycles *= 4;
+            }
+        }
+        self.wait += 1;
+        if self.wait < cycles {
+            return level
+        }
+        self.wait = 0;
+        (level + step).clamp(0, 0x7fff)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Phase {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    Off,
+}
+
+//the ADSR registers are combined into one word with the upper half at bits 16-31
+#[derive(Clone, Copy, Debug)]
+pub struct ADSR {
+    pub config: u32,
+    pub phase: Phase,
+    pub level: i32,
+    envelope: Envelope,
+}
+
+impl Default for ADSR {
+    fn default() -> Self {
+        ADSR {
+            config: 0,
+            phase: Phase::Off,
+            level: 0,
+            envelope: Default::default(),
+        }
+    }
+}
+
+impl ADSR {
+    pub fn key_on(&mut self) {
+        self.phase = Phase::Attack;
+        self.level = 0;
+        self.envelope.reset();
+    }
+
+    pub fn key_off(&mut self) {
+        if self.phase != Phase::Off {
+            self.phase = Phase::Release;
+            self.envelope.reset();
+        }
+    }
+
+    fn sustain_level(&self) -> i32 {
+        ((self.config.lowest_bits(4) + 1) * 0x800) as i32
+    }
+
+    pub fn tick(&mut self) {
+        let config = self.config;
+        let settings = match self.phase {
+            Phase::Attack => Settings::new(
+                config.nth_bit_bool(15),
+                false,
+                config.range(10, 14),
+                config.range(8, 9),
+            ),
+            Phase::Decay => Settings::new(true, true, config.range(4, 7), 0),
+            Phase::Sustain => Settings::new(
+                config.nth_bit_bool(31),
+                config.nth_bit_bool(30),
+                config.range(24, 28),
+                config.range(22, 23),
+            ),
+            Phase::Release => Settings::new(config.nth_bit_bool(21), true, config.range(16, 20), 0),
+            Phase::Off => return,
+        };
+        self.level = self.envelope.tick(self.level, settings);
+        match self.phase {
+            Phase::Attack if self.level >= 0x7fff => {
+                self.phase = Phase::Decay;
+                self.envelope.reset();
+            },
+            Phase::Decay if self.level <= self.sustain_level() => {
+                self.phase = Phase::Sustain;
+                self.envelope.reset();
+            },
+            Phase::Release if self.level == 0 => self.phase = Phase::Off,
+            _ => {},
+        }
+    }
+}
+
+//a volume register which is either fixed or sweeps up or down over time
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Volume {
+    pub register: u32,
+    pub level: i32,
+    envelope: Envelope,
+}
+
+impl Volume {
+    fn sweep(&self) -> bool {
+        self.register.nth_bit_bool(15)
+    }
+
+    pub fn set(&mut self, value: u32) {
+        self.register = value;
+        self.envelope.reset();
+        //fixed volumes are stored as half of a signed 16-bit value
+        if !self.sweep() {
+            self.level = (value << 17) as i32 >> 16;
+        }
+    }
+
+    pub fn tick(&mut self) {
+        if self.sweep() {
+            let settings = Settings::new(
+                self.register.nth_bit_bool(14),
+                self.register.nth_bit_bool(13),
+                self.register.range(2, 6),
+                self.register.lowest_bits(2),
+            );
+            let level = self.envelope.tick(self.level.abs(), settings);
+            //the phase bit inverts the sweep
+            self.level = if self.register.nth_bit_bool(12) {
+                -level
+            } else {
+                level
+            };
+        }
+    }
+
+    pub fn apply(&self, sample: i32) -> i32 {
+        (sample * self.level) >> 15
+    }
+}
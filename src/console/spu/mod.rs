@@ -0,0 +1,411 @@
+use super::cd::CD;
+use super::dma::DMAChannel;
+use super::memory::KB;
+use crate::register::BitTwiddle;
+use std::collections::VecDeque;
+
+mod envelope;
+mod reverb;
+mod voice;
+use reverb::Reverb;
+use voice::Voice;
+
+pub struct SPU {
+    ram: Box<[u8]>,
+    voices: [Voice; 24],
+    main_volume: [envelope::Volume; 2],
+    reverb_volume: [i32; 2],
+    cd_volume: [i32; 2],
+    reverb: Reverb,
+    //each of these has one bit per voice
+    pitch_modulation: u32,
+    noise: u32,
+    reverb_enable: u32,
+    endx: u32,
+    control: u32,
+    irq_address: u32,
+    irq_flag: bool,
+    irq_line: bool,
+    //halfwords written to the FIFO go to the transfer address once a manual write
+    // starts
+    transfer_address: u32,
+    transfer_fifo: Vec<u16>,
+    noise_level: i32,
+    noise_timer: i32,
+    //the position in the CD and voice 1 and 3 capture buffers
+    capture_index: u32,
+    //the registers that read back whatever was last written
+    registers: Box<[u16]>,
+    synced_at: u64,
+    //44.1kHz stereo samples waiting to be played
+    output: VecDeque<[i16; 2]>,
+}
+
+impl SPU {
+    pub const RAM_SIZE: usize = 512 * KB;
+    //the SPU makes one sample every 768 CPU cycles
+    const CYCLES_PER_SAMPLE: u64 = 768;
+    //how often the SPU catches up when no registers are accessed
+    pub const SYNC_PERIOD: u64 = 32 * SPU::CYCLES_PER_SAMPLE;
+    const OUTPUT_LEN: usize = 8192;
+    const REGISTERS: u32 = 0x1f80_1c00;
+
+    pub fn new() -> Self {
+        SPU {
+            ram: vec![0; SPU::RAM_SIZE].into_boxed_slice(),
+            voices: [Default::default(); 24],
+            main_volume: Default::default(),
+            reverb_volume: [0; 2],
+            cd_volume: [0; 2],
+            reverb: Default::default(),
+            pitch_modulation: 0,
+            noise: 0,
+            reverb_enable: 0,
+            endx: 0,
+            control: 0,
+            irq_address: 0,
+            irq_flag: false,
+            irq_line: false,
+            transfer_address: 0,
+            transfer_fifo: Vec::new(),
+            noise_level: 1,
+            noise_timer: 0,
+            capture_index: 0,
+            registers: vec![0; 0x200].into_boxed_slice(),
+            synced_at: 0,
+            output: VecDeque::new(),
+        }
+    }
+
+    fn enabled(&self) -> bool {
+        self.control.nth_bit_bool(15)
+    }
+
+    fn irq_enabled(&self) -> bool {
+        self.control.nth_bit_bool(6)
+    }
+
+    //0 is stopped, 1 is a manual write, 2 is a DMA write and 3 is a DMA read
+    fn transfer_mode(&self) -> u32 {
+        self.control.range(4, 5)
+    }
+
+    //any access to the IRQ address sets the interrupt flag
+    fn check_irq(&mut self, address: u32, len: u32) {
+        if self.irq_enabled() && (address..address + len).contains(&self.irq_address) {
+            self.irq_flag = true;
+        }
+    }
+
+    //returns true if the interrupt line to the CPU went high
+    pub fn take_interrupt(&mut self) -> bool {
+        let rising = self.irq_flag && !self.irq_line;
+        self.irq_line = self.irq_flag;
+        rising
+    }
+
+    fn write_ram(&mut self, address: u32, value: u16) {
+        let address = address & (SPU::RAM_SIZE as u32 - 2);
+        self.ram[address as usize..address as usize + 2].copy_from_slice(&value.to_le_bytes());
+        self.check_irq(address, 2);
+    }
+
+    fn read_ram(&mut self, address: u32) -> u16 {
+        let address = address & (SPU::RAM_SIZE as u32 - 2);
+        self.check_irq(address, 2);
+        u16::from_le_bytes([self.ram[address as usize], self.ram[address as usize + 1]])
+    }
+
+    //writes the next halfword of a transfer
+    fn transfer_write(&mut self, value: u16) {
+        self.write_ram(self.transfer_address, value);
+        self.transfer_address = (self.transfer_address + 2) & (SPU::RAM_SIZE as u32 - 1);
+    }
+
+    fn transfer_read(&mut self) -> u16 {
+        let value = self.read_ram(self.transfer_address);
+        self.transfer_address = (self.transfer_address + 2) & (SPU::RAM_SIZE as u32 - 1);
+        value
+    }
+
+    //word accesses are split into two halfword accesses
+    pub fn read(&mut self, address: u32, width: u32) -> u32 {
+        match width {
+            4 => self.read_half(address) | (self.read_half(address + 2) << 16),
+            2 => self.read_half(address),
+            _ => (self.read_half(address & !1) >> ((address & 1) * 8)) & 0xff,
+        }
+    }
+
+    pub fn write(&mut self, address: u32, value: u32, width: u32) {
+        match width {
+            4 => {
+                self.write_half(address, value.lowest_bits(16));
+                self.write_half(address + 2, value >> 16);
+            },
+            _ => self.write_half(address & !1, value.lowest_bits(16)),
+        }
+    }
+
+    fn read_half(&mut self, address: u32) -> u32 {
+        let offset = address - SPU::REGISTERS;
+        match offset {
+            //the current ADSR volume of each voice
+            0x000..=0x17f if offset & 0xf == 0xc => {
+                self.voices[offset as usize / 0x10].adsr.level as u32
+            },
+            0x19c => self.endx.lowest_bits(16),
+            0x19e => self.endx >> 16,
+            0x1ae => self.status(),
+            0x1b8 => self.main_volume[0].level as u16 as u32,
+            0x1ba => self.main_volume[1].level as u16 as u32,
+            //the current volume of each voice
+            0x200..=0x25f => {
+                let voice = &self.voices[(offset as usize - 0x200) / 4];
+                voice.volume[(offset as usize / 2) & 1].level as u16 as u32
+            },
+            0x000..=0x3ff => self.registers[offset as usize / 2] as u32,
+            _ => 0,
+        }
+    }
+
+    fn write_half(&mut self, address: u32, value: u32) {
+        let offset = address - SPU::REGISTERS;
+        if offset < 0x400 {
+            self.registers[offset as usize / 2] = value as u16;
+        }
+        //the upper halfword of each 24-bit voice mask
+        let upper = (offset & 2) * 8;
+        let voices = value << upper;
+        match offset {
+            0x000..=0x17f => {
+                let voice = &mut self.voices[offset as usize / 0x10];
+                match offset & 0xf {
+                    0x0 => voice.volume[0].set(value),
+                    0x2 => voice.volume[1].set(value),
+                    0x4 => voice.pitch = value,
+                    0x6 => voice.start_address = value * 8,
+                    0x8 => voice.adsr.config = (voice.adsr.config & 0xffff_0000) | value,
+                    0xa => voice.adsr.config = (voice.adsr.config & 0x0000_ffff) | (value << 16),
+                    0xc => voice.adsr.level = value as i16 as i32,
+                    0xe => voice.repeat_address = value * 8,
+                    _ => {},
+                }
+            },
+            0x180 => self.main_volume[0].set(value),
+            0x182 => self.main_volume[1].set(value),
+            0x184 => self.reverb_volume[0] = value as i16 as i32,
+            0x186 => self.reverb_volume[1] = value as i16 as i32,
+            0x188 | 0x18a => self.key_on(voices),
+            0x18c | 0x18e => self.key_off(voices),
+            0x190 | 0x192 => {
+                self.pitch_modulation = SPU::set_voices(self.pitch_modulation, voices, upper)
+            },
+            0x194 | 0x196 => self.noise = SPU::set_voices(self.noise, voices, upper),
+            0x198 | 0x19a => {
+                self.reverb_enable = SPU::set_voices(self.reverb_enable, voices, upper)
+            },
+            0x1a2 => self.reverb.set_base(value),
+            0x1a4 => self.irq_address = value * 8,
+            0x1a6 => self.transfer_address = value * 8,
+            0x1a8 if self.transfer_fifo.len() < 32 => self.transfer_fifo.push(value as u16),
+            0x1aa => {
+                self.control = value;
+                //clearing the IRQ enable bit acknowledges the interrupt
+                if !self.irq_enabled() {
+                    self.irq_flag = false;
+                }
+                if self.transfer_mode() == 1 {
+                    for value in std::mem::take(&mut self.transfer_fifo) {
+                        self.transfer_write(value);
+                    }
+                }
+            },
+            0x1b0 => self.cd_volume[0] = value as i16 as i32,
+            0x1b2 => self.cd_volume[1] = value as i16 as i32,
+            0x1c0..=0x1ff => self.reverb.write((offset as usize - 0x1c0) / 2, value),
+            _ => {},
+        }
+    }
+
+    //replaces either the lower 16 bits or upper 8 bits of a voice mask
+    fn set_voices(mask: u32, voices: u32, upper: u32) -> u32 {
+        let bits = 0xffff << upper;
+        ((mask & !bits) | (voices & bits)) & 0x00ff_ffff
+    }
+
+    fn key_on(&mut self, voices: u32) {
+        for n in 0..24 {
+            if voices.nth_bit_bool(n) {
+                let address = self.voices[n as usize].key_on(&self.ram);
+                self.check_irq(address, 16);
+                self.endx.clear(n);
+            }
+        }
+    }
+
+    fn key_off(&mut self, voices: u32) {
+        for n in 0..24 {
+            if voices.nth_bit_bool(n) {
+                self.voices[n as usize].key_off();
+            }
+        }
+    }
+
+    fn status(&self) -> u32 {
+        let mut status = self.control.lowest_bits(6);
+        if self.irq_flag {
+            status.set(6);
+        }
+        //DMA requests
+        match self.transfer_mode() {
+            2 => {
+                status.set(7).set(8);
+            },
+            3 => {
+                status.set(7).set(9);
+            },
+            _ => {},
+        }
+        //which half of the capture buffers is being written
+        if self.capture_index >= 0x100 {
+            status.set(11);
+        }
+        status
+    }
+
+    //the noise generator is a shift register clocked at a rate set in SPUCNT
+    fn update_noise(&mut self) {
+        let shift = self.control.range(10, 13);
+        let step = self.control.range(8, 9) as i32 + 4;
+        let level = self.noise_level as u32;
+        let parity =
+            level.nth_bit(15) ^ level.nth_bit(12) ^ level.nth_bit(11) ^ level.nth_bit(10) ^ 1;
+        self.noise_timer -= step;
+        if self.noise_timer < 0 {
+            self.noise_level = ((self.noise_level << 1) | parity as i32) as i16 as i32;
+            self.noise_timer += 0x2_0000 >> shift;
+            if self.noise_timer < 0 {
+                self.noise_timer += 0x2_0000 >> shift;
+            }
+        }
+    }
+
+    //the CD and voices 1 and 3 are recorded into the first 4KB of sound RAM
+    fn capture(&mut self, buffer: u32, sample: i32) {
+        let address = buffer * 0x400 + self.capture_index * 2;
+        self.write_ram(address, sample as i16 as u16);
+    }
+
+    fn generate_sample(&mut self, cd: &mut CD) -> [i16; 2] {
+        self.update_noise();
+        let mut output = [0; 2];
+        let mut reverb_input = [0; 2];
+        for n in 0..24 {
+            let mut step = self.voices[n].pitch;
+            //pitch modulation uses the previous voice's output to change the step
+            if n > 0 && self.pitch_modulation.nth_bit_bool(n as u32) {
+                let factor = self.voices[n - 1].output + 0x8000;
+                step = (((step as i16 as i32 * factor) >> 15) as u32).lowest_bits(16);
+            }
+            let step = step.min(0x4000);
+            let voice = &mut self.voices[n];
+            let sample = if self.noise.nth_bit_bool(n as u32) {
+                self.noise_level
+            } else {
+                voice.interpolated_sample()
+            };
+            voice.output = if voice.is_off() {
+                0
+            } else {
+                (sample * voice.adsr.level) >> 15
+            };
+            for side in 0..2 {
+                let sample = voice.volume[side].apply(voice.output);
+                output[side] += sample;
+                if self.reverb_enable.nth_bit_bool(n as u32) {
+                    reverb_input[side] += sample;
+                }
+                voice.volume[side].tick();
+            }
+            voice.adsr.tick();
+            let block = voice.advance(&self.ram, step);
+            if voice.reached_end {
+                voice.reached_end = false;
+                self.endx.set(n as u32);
+            }
+            if let Some(address) = block {
+                self.check_irq(address, 16);
+            }
+        }
+        let (voice1, voice3) = (self.voices[1].output, self.voices[3].output);
+        self.capture(2, voice1);
+        self.capture(3, voice3);
+        //CD audio is mixed in after the volume matrix in the CD controller
+        let cd_sample = cd.audio_sample();
+        self.capture(0, cd_sample[0] as i32);
+        self.capture(1, cd_sample[1] as i32);
+        if self.control.nth_bit_bool(0) {
+            for side in 0..2 {
+                let sample = (cd_sample[side] as i32 * self.cd_volume[side]) >> 15;
+                output[side] += sample;
+                if self.control.nth_bit_bool(2) {
+                    reverb_input[side] += sample;
+                }
+            }
+        }
+        self.capture_index = (self.capture_index + 1) & 0x1ff;
+        let reverb_enabled = self.control.nth_bit_bool(7);
+        let reverb_output = self.reverb.process(&mut self.ram, reverb_input, reverb_enabled);
+        let mut sample = [0; 2];
+        for side in 0..2 {
+            let mixed = output[side] + ((reverb_output[side] * self.reverb_volume[side]) >> 15);
+            let mixed = self.main_volume[side].apply(mixed.clamp(-0x8000, 0x7fff));
+            self.main_volume[side].tick();
+            sample[side] = mixed.clamp(-0x8000, 0x7fff) as i16;
+        }
+        //bit 14 of SPUCNT unmutes the output but everything else keeps running
+        if self.enabled() && self.control.nth_bit_bool(14) {
+            sample
+        } else {
+            [0, 0]
+        }
+    }
+
+    //generates every sample up to the given cycle
+    pub fn sync(&mut self, cycles: u64, cd: &mut CD) {
+        while self.synced_at + SPU::CYCLES_PER_SAMPLE <= cycles {
+            self.synced_at += SPU::CYCLES_PER_SAMPLE;
+            let sample = self.generate_sample(cd);
+            self.output.push_back(sample);
+        }
+        //drop the oldest samples if nothing is playing them
+        while self.output.len() > SPU::OUTPUT_LEN {
+            self.output.pop_front();
+        }
+    }
+
+    //takes every sample that's been generated so far
+    pub fn take_samples(&mut self) -> Vec<[i16; 2]> {
+        self.output.drain(..).collect()
+    }
+}
+
+impl DMAChannel for SPU {
+    fn send(&mut self, data: Vec<u32>) {
+        for word in data {
+            self.transfer_write(word as u16);
+            self.transfer_write((word >> 16) as u16);
+        }
+    }
+
+    fn receive(&mut self, words: u32) -> Vec<u32> {
+        (0..words)
+            .map(|_| {
+                let low = self.transfer_read() as u32;
+                let high = self.transfer_read() as u32;
+                low | (high << 16)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,147 @@
+use super::SPU;
+
+//the reverb unit runs at 22.05kHz and keeps its echo buffers in the end of sound
+// RAM starting at mBASE
+#[derive(Default)]
+pub struct Reverb {
+    //the 32 configuration registers at 0x1f801dc0
+    registers: [u32; 32],
+    base: u32,
+    current: u32,
+    //only every other sample is processed
+    odd: bool,
+    output: [i32; 2],
+}
+
+//offsets into the echo buffers
+const D_APF1: usize = 0;
+const D_APF2: usize = 1;
+const M_LSAME: usize = 10;
+const M_RSAME: usize = 11;
+const M_LCOMB1: usize = 12;
+const M_RCOMB1: usize = 13;
+const M_LCOMB2: usize = 14;
+const M_RCOMB2: usize = 15;
+const D_LSAME: usize = 16;
+const D_RSAME: usize = 17;
+const M_LDIFF: usize = 18;
+const M_RDIFF: usize = 19;
+const M_LCOMB3: usize = 20;
+const M_RCOMB3: usize = 21;
+const M_LCOMB4: usize = 22;
+const M_RCOMB4: usize = 23;
+const D_LDIFF: usize = 24;
+const D_RDIFF: usize = 25;
+const M_LAPF1: usize = 26;
+const M_RAPF1: usize = 27;
+const M_LAPF2: usize = 28;
+const M_RAPF2: usize = 29;
+//volumes
+const V_IIR: usize = 2;
+const V_COMB1: usize = 3;
+const V_COMB2: usize = 4;
+const V_COMB3: usize = 5;
+const V_COMB4: usize = 6;
+const V_WALL: usize = 7;
+const V_APF1: usize = 8;
+const V_APF2: usize = 9;
+const V_LIN: usize = 30;
+const V_RIN: usize = 31;
+
+fn mul(a: i32, b: i32) -> i32 {
+    (a * b) >> 15
+}
+
+fn saturate(value: i32) -> i32 {
+    value.clamp(i16::MIN as i32, i16::MAX as i32)
+}
+
+impl Reverb {
+    pub fn write(&mut self, index: usize, value: u32) {
+        self.registers[index] = value;
+    }
+
+    pub fn set_base(&mut self, value: u32) {
+        self.base = value * 8;
+        self.current = self.base;
+    }
+
+    fn volume(&self, index: usize) -> i32 {
+        self.registers[index] as i16 as i32
+    }
+
+    //buffer addresses are relative to the current address and wrap around within
+    // the reverb work area
+    fn address(&self, index: usize, delta: i32) -> usize {
+        let size = SPU::RAM_SIZE as i64 - self.base as i64;
+        let offset = self.current as i64 - self.base as i64 + self.registers[index] as i64 * 8;
+        (self.base as i64 + (offset + delta as i64).rem_euclid(size)) as usize
+    }
+
+    fn read(&self, ram: &[u8], index: usize, delta: i32) -> i32 {
+        let address = self.address(index, delta);
+        i16::from_le_bytes([ram[address], ram[address + 1]]) as i32
+    }
+
+    fn write_ram(&self, ram: &mut [u8], index: usize, delta: i32, value: i32, enabled: bool) {
+        if enabled {
+            let address = self.address(index, delta);
+            let bytes = (saturate(value) as i16).to_le_bytes();
+            ram[address..address + 2].copy_from_slice(&bytes);
+        }
+    }
+
+    //the buffers are only written if reverb is enabled in SPUCNT
+    pub fn process(&mut self, ram: &mut [u8], input: [i32; 2], enabled: bool) -> [i32; 2] {
+        self.odd = !self.odd;
+        if self.odd {
+            return self.output
+        }
+        let l_in = mul(input[0], self.volume(V_LIN));
+        let r_in = mul(input[1], self.volume(V_RIN));
+        let wall = self.volume(V_WALL);
+        let iir = self.volume(V_IIR);
+        //same side and different side reflections
+        let reflect = |input: i32, m: usize, d: usize| {
+            let previous = self.read(ram, m, -2);
+            mul(input + mul(self.read(ram, d, 0), wall) - previous, iir) + previous
+        };
+        let l_same = reflect(l_in, M_LSAME, D_LSAME);
+        let r_same = reflect(r_in, M_RSAME, D_RSAME);
+        let l_diff = reflect(l_in, M_LDIFF, D_RDIFF);
+        let r_diff = reflect(r_in, M_RDIFF, D_LDIFF);
+        self.write_ram(ram, M_LSAME, 0, l_same, enabled);
+        self.write_ram(ram, M_RSAME, 0, r_same, enabled);
+        self.write_ram(ram, M_LDIFF, 0, l_diff, enabled);
+        self.write_ram(ram, M_RDIFF, 0, r_diff, enabled);
+        //early echo
+        let comb = |m: [usize; 4]| {
+            mul(self.volume(V_COMB1), self.read(ram, m[0], 0)) +
+                mul(self.volume(V_COMB2), self.read(ram, m[1], 0)) +
+                mul(self.volume(V_COMB3), self.read(ram, m[2], 0)) +
+                mul(self.volume(V_COMB4), self.read(ram, m[3], 0))
+        };
+        let mut l_out = comb([M_LCOMB1, M_LCOMB2, M_LCOMB3, M_LCOMB4]);
+        let mut r_out = comb([M_RCOMB1, M_RCOMB2, M_RCOMB3, M_RCOMB4]);
+        //late reverb through two all pass filters
+        let filters = [
+            (D_APF1, V_APF1, M_LAPF1, M_RAPF1),
+            (D_APF2, V_APF2, M_LAPF2, M_RAPF2),
+        ];
+        for &(d, v, ml, mr) in filters.iter() {
+            let volume = self.volume(v);
+            let delay = -(self.registers[d] as i32 * 8);
+            let l_delayed = self.read(ram, ml, delay);
+            let r_delayed = self.read(ram, mr, delay);
+            l_out = saturate(l_out - mul(volume, l_delayed));
+            r_out = saturate(r_out - mul(volume, r_delayed));
+            self.write_ram(ram, ml, 0, l_out, enabled);
+            self.write_ram(ram, mr, 0, r_out, enabled);
+            l_out = mul(l_out, volume) + l_delayed;
+            r_out = mul(r_out, volume) + r_delayed;
+        }
+        self.output = [saturate(l_out), saturate(r_out)];
+        self.current = ((self.current + 2) & 0x7_fffe).max(self.base);
+        self.output
+    }
+}
@@ -0,0 +1,111 @@
+use super::envelope::{Phase, Volume, ADSR};
+use super::SPU;
+
+//sound RAM holds ADPCM blocks of 16 bytes that each decode to 28 samples
+//the first byte has the shift and filter and the second byte has the loop flags
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Voice {
+    pub volume: [Volume; 2],
+    pub pitch: u32,
+    pub start_address: u32,
+    pub repeat_address: u32,
+    pub adsr: ADSR,
+    //the block currently being played and its flags
+    address: u32,
+    flags: u8,
+    //the position in the current block in 4.12 fixed point
+    counter: u32,
+    //the last sample of the previous block followed by the samples in this block
+    samples: [i16; 29],
+    //the last two samples decoded for the ADPCM filters
+    history: [i32; 2],
+    //set when a block with the loop end flag finishes
+    pub reached_end: bool,
+    //the most recent sample after the envelope which is used for pitch modulation
+    pub output: i32,
+}
+
+impl Voice {
+    const POS: [i32; 5] = [0, 60, 115, 98, 122];
+    const NEG: [i32; 5] = [0, 0, -52, -55, -60];
+
+    pub fn key_on(&mut self, ram: &[u8]) -> u32 {
+        self.address = self.start_address;
+        self.counter = 0;
+        self.history = [0, 0];
+        self.samples = [0; 29];
+        self.adsr.key_on();
+        self.decode_block(ram);
+        self.address
+    }
+
+    pub fn key_off(&mut self) {
+        self.adsr.key_off();
+    }
+
+    pub fn is_off(&self) -> bool {
+        self.adsr.phase == Phase::Off
+    }
+
+    fn decode_block(&mut self, ram: &[u8]) {
+        //blocks at the end of sound RAM wrap around to the start
+        let mut block = [0; 16];
+        for (i, byte) in block.iter_mut().enumerate() {
+            *byte = ram[(self.address as usize + i) & (SPU::RAM_SIZE - 1)];
+        }
+        let shift = match block[0] & 0xf {
+            shift if shift > 12 => 9,
+            shift => shift,
+        };
+        let filter = ((block[0] >> 4) & 7).min(4) as usize;
+        self.flags = block[1];
+        //the loop start flag sets the address to return to after the loop end flag
+        if self.flags & (1 << 2) != 0 {
+            self.repeat_address = self.address;
+        }
+        self.samples[0] = self.samples[28];
+        let [mut old, mut older] = self.history;
+        for i in 0..28 {
+            let nibble = (block[2 + i / 2] >> ((i & 1) * 4)) & 0xf;
+            let raw = (((nibble as u16) << 12) as i16 >> shift) as i32;
+            let sample = raw + (old * Voice::POS[filter] + older * Voice::NEG[filter] + 32) / 64;
+            let sample = sample.clamp(i16::MIN as i32, i16::MAX as i32);
+            older = old;
+            old = sample;
+            self.samples[i + 1] = sample as i16;
+        }
+        self.history = [old, older];
+    }
+
+    //linearly interpolates between the current sample and the one before it
+    pub fn interpolated_sample(&self) -> i32 {
+        let index = (self.counter >> 12) as usize;
+        let fraction = (self.counter & 0xfff) as i32;
+        let previous = self.samples[index] as i32;
+        let current = self.samples[index + 1] as i32;
+        previous + (((current - previous) * fraction) >> 12)
+    }
+
+    //moves forward by one output sample and returns the address of the next block
+    // if it was decoded
+    pub fn advance(&mut self, ram: &[u8], step: u32) -> Option<u32> {
+        self.counter += step;
+        if self.counter >> 12 < 28 {
+            return None
+        }
+        self.counter -= 28 << 12;
+        if self.flags & 1 != 0 {
+            self.reached_end = true;
+            self.address = self.repeat_address;
+            //without the loop repeat flag the voice is silenced
+            if self.flags & (1 << 1) == 0 {
+                self.adsr.phase = Phase::Off;
+                self.adsr.level = 0;
+            }
+        } else {
+            self.address = (self.address + 16) & (SPU::RAM_SIZE as u32 - 1);
+        }
+        self.decode_block(ram);
+        Some(self.address)
+    }
+}
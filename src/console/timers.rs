@@ -0,0 +1,438 @@
+use super::interrupts::IRQ_TIMER0;
+use crate::register::BitTwiddle;
+
+//the clock signals that may drive a root counter
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Source {
+    System,
+    SystemDiv8,
+    DotClock,
+    HBlank,
+}
+
+#[derive(Default)]
+struct Timer {
+    index: u32,
+    value: u32,
+    mode: u32,
+    target: u32,
+    //whether we're inside the blanking period that this timer synchronizes to
+    in_blank: bool,
+    //sync mode 3 pauses the counter until the first blank
+    waiting_for_blank: bool,
+    //one-shot timers only interrupt once until the mode is written again
+    irq_done: bool,
+}
+
+impl Timer {
+    fn new(index: u32) -> Self {
+        Timer {
+            index,
+            mode: 1 << 10,
+            ..Default::default()
+        }
+    }
+
+    fn source(&self) -> Source {
+        match (self.index, self.mode.range(8, 9)) {
+            (0, 1) | (0, 3) => Source::DotClock,
+            (1, 1) | (1, 3) => Source::HBlank,
+            (2, 2) | (2, 3) => Source::SystemDiv8,
+            _ => Source::System,
+        }
+    }
+
+    fn sync_enabled(&self) -> bool {
+        self.mode.nth_bit_bool(0)
+    }
+
+    fn sync_mode(&self) -> u32 {
+        self.mode.range(1, 2)
+    }
+
+    fn paused(&self) -> bool {
+        if !self.sync_enabled() {
+            return false
+        }
+        match (self.index, self.sync_mode()) {
+            //timer 2 can only be stopped or free-running
+            (2, 0) | (2, 3) => true,
+            (2, _) => false,
+            (_, 0) => self.in_blank,
+            (_, 1) => false,
+            (_, 2) => !self.in_blank,
+            (_, 3) => self.waiting_for_blank,
+            _ => unreachable!(""),
+        }
+    }
+
+    fn set_mode(&mut self, value: u32) {
+        //writing the mode resets the counter and the interrupt flag
+        self.mode = value.lowest_bits(10) | (1 << 10);
+        self.value = 0;
+        self.irq_done = false;
+        self.waiting_for_blank = self.sync_mode() == 3;
+    }
+
+    //the reached target and reached 0xffff flags are reset after reading
+    fn read_mode(&mut self) -> u32 {
+        let mode = self.mode;
+        self.mode.clear(11).clear(12);
+        mode
+    }
+
+    fn blank_start(&mut self) {
+        self.in_blank = true;
+        if self.sync_enabled() && self.index != 2 {
+            match self.sync_mode() {
+                1 | 2 => self.value = 0,
+                3 => self.waiting_for_blank = false,
+                _ => {},
+            }
+        }
+    }
+
+    fn blank_end(&mut self) {
+        self.in_blank = false;
+    }
+
+    //returns true if the IRQ line went low
+    fn trigger_irq(&mut self) -> bool {
+        let repeat = self.mode.nth_bit_bool(6);
+        if self.irq_done && !repeat {
+            return false
+        }
+        //toggle mode flips bit 10 on every event while pulse mode only clears it
+        // briefly
+        let fired = if self.mode.nth_bit_bool(7) {
+            self.mode ^= 1 << 10;
+            !self.mode.nth_bit_bool(10)
+        } else {
+            true
+        };
+        self.irq_done |= fired;
+        fired
+    }
+
+    fn advance(&mut self, ticks: u32) -> bool {
+        if self.paused() {
+            return false
+        }
+        let mut irq = false;
+        let mut remaining = ticks;
+        while remaining > 0 {
+            let to_target = if self.target > self.value {
+                self.target - self.value
+            } else {
+                u32::MAX
+            };
+            let to_overflow = 0x1_0000 - self.value;
+            let step = remaining.min(to_target).min(to_overflow);
+            self.value += step;
+            remaining -= step;
+            if self.value == self.target {
+                self.mode.set(11);
+                if self.mode.nth_bit_bool(4) {
+                    irq |= self.trigger_irq();
+                }
+                if self.mode.nth_bit_bool(3) {
+                    self.value = 0;
+                }
+            }
+            if self.value == 0x1_0000 {
+                self.mode.set(12);
+                if self.mode.nth_bit_bool(5) {
+                    irq |= self.trigger_irq();
+                }
+                self.value = 0;
+            }
+        }
+        irq
+    }
+
+    //the number of ticks until this timer may request an interrupt
+    fn ticks_until_irq(&self) -> Option<u32> {
+        if self.paused() {
+            return None
+        }
+        let to_target = if self.mode.nth_bit_bool(4) && self.target > self.value {
+            Some(self.target - self.value)
+        } else {
+            None
+        };
+        let to_overflow = if self.mode.nth_bit_bool(5) {
+            Some(0x1_0000 - self.value)
+        } else {
+            None
+        };
+        match (to_target, to_overflow) {
+            (Some(t), Some(o)) => Some(t.min(o)),
+            (t, o) => t.or(o),
+        }
+    }
+}
+
+pub struct Timers {
+    timers: [Timer; 3],
+    //leftover system clock cycles for the sysclock/8 source
+    div8_cycles: u32,
+    //the scheduler timestamp that the timers were last brought up to date at
+    synced_at: u64,
+}
+
+impl Timers {
+    pub fn new() -> Self {
+        Timers {
+            timers: [Timer::new(0), Timer::new(1), Timer::new(2)],
+            div8_cycles: 0,
+            synced_at: 0,
+        }
+    }
+
+    //timers are 16 bytes apart and each register is a word
+    pub fn read(&mut self, address: u32) -> u32 {
+        let timer = &mut self.timers[address.range(4, 5) as usize];
+        match address.range(2, 3) {
+            0 => timer.value,
+            1 => timer.read_mode(),
+            2 => timer.target,
+            _ => 0,
+        }
+    }
+
+    pub fn write(&mut self, address: u32, value: u32) {
+        let timer = &mut self.timers[address.range(4, 5) as usize];
+        match address.range(2, 3) {
+            0 => timer.value = value.half(),
+            1 => timer.set_mode(value),
+            2 => timer.target = value.half(),
+            _ => {},
+        }
+    }
+
+    //returns the IRQs requested by timers that count the given source
+    fn advance(&mut self, source: Source, ticks: u32) -> Vec<u32> {
+        self.timers
+            .iter_mut()
+            .filter(|timer| timer.source() == source)
+            .filter_map(|timer| {
+                if timer.advance(ticks) {
+                    Some(IRQ_TIMER0 + timer.index)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    //catches the timers up to the current cycle count
+    pub fn sync(&mut self, cycles: u64) -> Vec<u32> {
+        let elapsed = cycles - self.synced_at;
+        self.synced_at = cycles;
+        self.tick(elapsed as u32)
+    }
+
+    //the number of cycles until a timer clocked by the CPU may request an interrupt
+    //timers driven by the GPU are synced at each hblank instead
+    pub fn cycles_until_irq(&self) -> u64 {
+        self.timers
+            .iter()
+            .filter_map(|timer| match timer.source() {
+                Source::System => timer.ticks_until_irq().map(|ticks| ticks as u64),
+                Source::SystemDiv8 => timer
+                    .ticks_until_irq()
+                    .map(|ticks| ticks as u64 * 8 - self.div8_cycles as u64),
+                _ => None,
+            })
+            .min()
+            //resync now and then so the counters don't lag too far behind
+            .unwrap_or(0x1_0000)
+    }
+
+    fn tick(&mut self, cycles: u32) -> Vec<u32> {
+        self.div8_cycles += cycles;
+        let div8_ticks = self.div8_cycles / 8;
+        self.div8_cycles %= 8;
+        let mut irqs = self.advance(Source::System, cycles);
+        irqs.extend(self.advance(Source::SystemDiv8, div8_ticks));
+        irqs
+    }
+
+    pub fn tick_dot_clock(&mut self, dots: u32) -> Vec<u32> {
+        self.advance(Source::DotClock, dots)
+    }
+
+    //timer 0 synchronizes to hblank and timer 1 counts it
+    pub fn hblank_start(&mut self) -> Vec<u32> {
+        self.timers[0].blank_start();
+        self.advance(Source::HBlank, 1)
+    }
+
+    pub fn hblank_end(&mut self) {
+        self.timers[0].blank_end();
+    }
+
+    //timer 1 synchronizes to vblank
+    pub fn vblank_start(&mut self) {
+        self.timers[1].blank_start();
+    }
+
+    pub fn vblank_end(&mut self) {
+        self.timers[1].blank_end();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALUE: u32 = 0;
+    const MODE: u32 = 1;
+    const TARGET: u32 = 2;
+
+    fn address(timer: u32, register: u32) -> u32 {
+        0x1f80_1100 + 0x10 * timer + 4 * register
+    }
+
+    //starts a timer with the given mode and target
+    fn start(timer: u32, mode: u32, target: u32) -> Timers {
+        let mut timers = Timers::new();
+        timers.write(address(timer, TARGET), target);
+        timers.write(address(timer, MODE), mode);
+        timers
+    }
+
+    #[test]
+    fn target_reset_and_flags() {
+        //reset and interrupt at the target
+        let mut timers = start(0, (1 << 3) | (1 << 4), 100);
+        assert_eq!(timers.cycles_until_irq(), 100);
+        assert_eq!(timers.sync(99), vec![]);
+        assert_eq!(timers.sync(100), vec![IRQ_TIMER0]);
+        assert_eq!(timers.read(address(0, VALUE)), 0);
+        //the reached target flag is cleared when it's read
+        assert!(timers.read(address(0, MODE)).nth_bit_bool(11));
+        assert!(!timers.read(address(0, MODE)).nth_bit_bool(11));
+    }
+
+    #[test]
+    fn overflow_without_target_reset() {
+        //interrupt on overflow and count past the target
+        let mut timers = start(1, 1 << 5, 100);
+        assert_eq!(timers.sync(0x1_0000 + 5), vec![IRQ_TIMER0 + 1]);
+        assert_eq!(timers.read(address(1, VALUE)), 5);
+        let mode = timers.read(address(1, MODE));
+        assert!(mode.nth_bit_bool(11));
+        assert!(mode.nth_bit_bool(12));
+    }
+
+    #[test]
+    fn one_shot_and_repeat() {
+        let mut timers = start(0, (1 << 3) | (1 << 4), 10);
+        assert_eq!(timers.sync(10), vec![IRQ_TIMER0]);
+        assert_eq!(timers.sync(20), vec![]);
+        //writing the mode rearms a one-shot timer
+        timers.write(address(0, MODE), (1 << 3) | (1 << 4));
+        assert_eq!(timers.sync(30), vec![IRQ_TIMER0]);
+
+        let mut timers = start(0, (1 << 3) | (1 << 4) | (1 << 6), 10);
+        assert_eq!(timers.sync(10), vec![IRQ_TIMER0]);
+        assert_eq!(timers.sync(20), vec![IRQ_TIMER0]);
+    }
+
+    #[test]
+    fn pulse_and_toggle() {
+        //pulse mode leaves bit 10 set
+        let mut timers = start(0, (1 << 3) | (1 << 4) | (1 << 6), 10);
+        assert_eq!(timers.sync(10), vec![IRQ_TIMER0]);
+        assert!(timers.read(address(0, MODE)).nth_bit_bool(10));
+
+        //toggle mode only interrupts when bit 10 goes low
+        let mut timers = start(0, (1 << 3) | (1 << 4) | (1 << 6) | (1 << 7), 10);
+        assert_eq!(timers.sync(10), vec![IRQ_TIMER0]);
+        assert!(!timers.read(address(0, MODE)).nth_bit_bool(10));
+        assert_eq!(timers.sync(20), vec![]);
+        assert!(timers.read(address(0, MODE)).nth_bit_bool(10));
+        assert_eq!(timers.sync(30), vec![IRQ_TIMER0]);
+    }
+
+    #[test]
+    fn sync_mode_0_pauses_during_blank() {
+        let mut timers = start(0, 1, 0);
+        timers.sync(10);
+        timers.hblank_start();
+        timers.sync(20);
+        assert_eq!(timers.read(address(0, VALUE)), 10);
+        timers.hblank_end();
+        timers.sync(30);
+        assert_eq!(timers.read(address(0, VALUE)), 20);
+    }
+
+    #[test]
+    fn sync_mode_1_resets_at_blank() {
+        let mut timers = start(0, 1 | (1 << 1), 0);
+        timers.sync(10);
+        timers.hblank_start();
+        assert_eq!(timers.read(address(0, VALUE)), 0);
+        timers.sync(15);
+        assert_eq!(timers.read(address(0, VALUE)), 5);
+    }
+
+    #[test]
+    fn sync_mode_2_only_counts_during_blank() {
+        let mut timers = start(0, 1 | (2 << 1), 0);
+        timers.sync(10);
+        assert_eq!(timers.read(address(0, VALUE)), 0);
+        timers.hblank_start();
+        timers.sync(15);
+        assert_eq!(timers.read(address(0, VALUE)), 5);
+        timers.hblank_end();
+        timers.sync(20);
+        assert_eq!(timers.read(address(0, VALUE)), 5);
+    }
+
+    #[test]
+    fn sync_mode_3_waits_for_the_first_blank() {
+        let mut timers = start(1, 1 | (3 << 1), 0);
+        timers.sync(10);
+        assert_eq!(timers.read(address(1, VALUE)), 0);
+        timers.vblank_start();
+        timers.vblank_end();
+        timers.sync(15);
+        assert_eq!(timers.read(address(1, VALUE)), 5);
+    }
+
+    #[test]
+    fn timer_2_sync_modes_stop_or_run() {
+        for &(sync_mode, running) in &[(0, false), (1, true), (2, true), (3, false)] {
+            let mut timers = start(2, 1 | (sync_mode << 1), 0);
+            timers.sync(10);
+            let expected = if running { 10 } else { 0 };
+            assert_eq!(timers.read(address(2, VALUE)), expected);
+        }
+    }
+
+    #[test]
+    fn sysclock_div8_keeps_the_remainder() {
+        let mut timers = start(2, (2 << 8) | (1 << 3) | (1 << 4), 2);
+        timers.sync(7);
+        assert_eq!(timers.read(address(2, VALUE)), 0);
+        //the 7 leftover cycles count towards the next tick
+        assert_eq!(timers.cycles_until_irq(), 9);
+        timers.sync(9);
+        assert_eq!(timers.read(address(2, VALUE)), 1);
+        assert_eq!(timers.sync(16), vec![IRQ_TIMER0 + 2]);
+    }
+
+    #[test]
+    fn gpu_clock_sources() {
+        let mut timers = start(0, 1 << 8, 0);
+        timers.tick_dot_clock(300);
+        timers.sync(10);
+        assert_eq!(timers.read(address(0, VALUE)), 300);
+        let mut timers = start(1, 1 << 8, 0);
+        timers.hblank_start();
+        timers.hblank_start();
+        assert_eq!(timers.read(address(1, VALUE)), 2);
+    }
+}
@@ -1,3 +1,5 @@
+use crate::console::audio::AudioConfig;
+use crate::console::r3000::R3000;
 use crate::console::Console;
 use std::io;
 use std::time::Instant;
@@ -12,45 +14,57 @@ impl Interpreter {
     pub fn run(&mut self, n: Option<u32>, logging: bool) {
         println!("running in interpreter mode");
         let start_time = Instant::now();
-        let mut refresh_timer: i64 = Console::REFRESH_RATE;
         loop {
-            if logging {
-                println!("  ");
-                println!("{} ----------------------", self.console.i);
+            //I/O writes may schedule an earlier event so this is checked after each step
+            while self.console.scheduler.cycles() < self.console.scheduler.next_event_at() {
+                if logging {
+                    println!("  ");
+                    println!("{} ----------------------", self.console.i);
+                }
+                self.step(logging);
+                self.console.i += 1;
+                n.map(|n| {
+                    if self.console.i == n {
+                        let end_time = Instant::now();
+                        panic!(
+                            "Executed {} steps in {:?}",
+                            self.console.i,
+                            end_time - start_time
+                        );
+                    };
+                });
             }
-            self.step(logging);
-            self.console.i += 1;
-            n.map(|n| {
-                if self.console.i == n {
-                    let end_time = Instant::now();
-                    panic!(
-                        "Executed {} steps in {:?}",
-                        self.console.i,
-                        end_time - start_time
-                    );
-                };
-            });
-            refresh_timer -= 1;
-            if refresh_timer < 0 {
-                self.console.screen.refresh_window();
-                refresh_timer = Console::REFRESH_RATE;
-            }
-            if !self.console.handle_events() {
+            if !self.console.run_events() {
                 return
             }
         }
     }
 
     pub fn new(
-        bios_filename: &String, infile: Option<&String>, gpu_logging: bool, wx: u32, wy: u32,
+        bios_filename: &String, infile: Option<&String>, fast_boot: bool, audio: &AudioConfig,
+        memory_cards: [Option<&String>; 2], gpu_logging: bool, wx: u32, wy: u32,
     ) -> io::Result<Self> {
-        let console = Console::new(bios_filename, infile, gpu_logging, wx, wy)?;
+        let console = Console::new(
+            bios_filename,
+            infile,
+            fast_boot,
+            audio,
+            memory_cards,
+            gpu_logging,
+            wx,
+            wy,
+        )?;
         Ok(Self { console })
     }
 
     //this steps through the logic pertaining to the physical components of the
     // playstation
     fn step(&mut self, logging: bool) {
+        //interrupts aren't taken in delay slots
+        if self.console.next_pc.is_none() {
+            self.console.check_interrupts();
+        }
+        self.console.check_fast_boot();
         //get opcode from memory at program counter
         let op = self.console.read_word(self.console.r3000.pc());
         if logging {
@@ -67,10 +81,6 @@ impl Interpreter {
             |next_pc| next_pc,
         );
         self.console.next_pc = self.execute_opcode(op, logging);
-        self.console
-            .gpu
-            .exec_next_gp0_command()
-            .map(|object| self.console.screen.draw(object));
-        self.console.cd.exec_command();
+        self.console.scheduler.advance(R3000::cycles(op));
     }
 }
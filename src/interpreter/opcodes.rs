@@ -108,6 +108,33 @@ impl Interpreter {
           None
         }
       };
+      //transfers between memory and GTE data registers
+      (gte rt = [rs + imm16]) => {
+        {
+          let rs = self.console.r3000.nth_reg(get_rs(op));
+          let imm16 = get_imm16(op).half_sign_extended();
+          let rt = get_rt(op);
+          self.console.load_gte_word(rs.wrapping_add(imm16), rt);
+          log!("GTE R{} = [{:#x} + {:#x}] \n  = [{:#x}] \n  = {:#x}",
+                    rt, rs, imm16, rs.wrapping_add(imm16), self.console.gte.nth_data_reg(rt));
+          None
+        }
+      };
+      ([rs + imm16] = gte rt) => {
+        {
+          let rs = self.console.r3000.nth_reg(get_rs(op));
+          let imm16 = get_imm16(op).half_sign_extended();
+          let rt = get_rt(op);
+          log!("[{:#x} + {:#x}] = [{:#x}] \n  = GTE R{}\n  = {:#x}",
+                    rs, imm16, rs.wrapping_add(imm16), rt, self.console.gte.nth_data_reg(rt));
+          if !self.console.cop0.cache_isolated() {
+            self.console.store_gte_word(rs.wrapping_add(imm16), rt);
+          } else {
+            log!("ignoring write while cache is isolated");
+          }
+          None
+        }
+      };
       (lo = rs) => {
         {
           let rs = self.console.r3000.nth_reg(get_rs(op));
@@ -924,7 +951,8 @@ impl Interpreter {
             },
             0x32 => {
                 //LWC2
-                todo!("lwc2")
+                log!("> LWC2");
+                mov!(gte rt = [rs + imm16])
             },
             0x33 => {
                 //LWC3
@@ -940,7 +968,8 @@ impl Interpreter {
             },
             0x3A => {
                 //SWC2
-                todo!("swc2")
+                log!("> SWC2");
+                mov!([rs + imm16] = gte rt)
             },
             0x3B => {
                 //SWC3
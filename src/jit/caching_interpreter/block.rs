@@ -1,3 +1,4 @@
+use crate::console::r3000::R3000;
 use crate::jit::caching_interpreter::stubs::Stub;
 use crate::jit::insn::Insn;
 
@@ -10,6 +11,8 @@ pub struct Block {
     //the number of MIPS opcodes represented by this Block
     //may be more than the length of stubs
     nominal_len: u32,
+    //the number of CPU cycles taken to run the whole block
+    cycles: u32,
 }
 
 impl Block {
@@ -19,6 +22,7 @@ impl Block {
             stubs,
             final_pc,
             nominal_len,
+            cycles: Block::count_cycles(tagged_opcodes),
         }
     }
 
@@ -30,9 +34,18 @@ impl Block {
             stubs,
             final_pc,
             nominal_len,
+            cycles: Block::count_cycles(tagged_opcodes),
         }
     }
 
+    //this uses the opcodes from before optimizations since those are what the CPU runs
+    fn count_cycles(tagged_opcodes: &Vec<Insn>) -> u32 {
+        tagged_opcodes
+            .iter()
+            .map(|insn| R3000::cycles(insn.op()))
+            .sum()
+    }
+
     fn create_stubs(tagged_opcodes: &Vec<Insn>, logging: bool) -> Vec<Stub> {
         let mut ret = Vec::new();
         for insn in tagged_opcodes {
@@ -52,4 +65,8 @@ impl Block {
     pub fn nominal_len(&self) -> u32 {
         self.nominal_len
     }
+
+    pub fn cycles(&self) -> u32 {
+        self.cycles
+    }
 }
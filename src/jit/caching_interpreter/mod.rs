@@ -1,3 +1,4 @@
+use crate::console::audio::AudioConfig;
 use crate::console::Console;
 use crate::jit::insn::Insn;
 use block::Block;
@@ -17,9 +18,19 @@ pub struct CachingInterpreter {
 
 impl CachingInterpreter {
     pub fn new(
-        bios_filename: &String, infile: Option<&String>, gpu_logging: bool, wx: u32, wy: u32,
+        bios_filename: &String, infile: Option<&String>, fast_boot: bool, audio: &AudioConfig,
+        memory_cards: [Option<&String>; 2], gpu_logging: bool, wx: u32, wy: u32,
     ) -> io::Result<Self> {
-        let console = Console::new(bios_filename, infile, gpu_logging, wx, wy)?;
+        let console = Console::new(
+            bios_filename,
+            infile,
+            fast_boot,
+            audio,
+            memory_cards,
+            gpu_logging,
+            wx,
+            wy,
+        )?;
         Ok(Self {
             console,
             blocks: Default::default(),
@@ -30,91 +41,80 @@ impl CachingInterpreter {
     pub fn run(&mut self, n: Option<u32>, optimize: bool, logging: bool) {
         println!("running in caching interpreter mode");
         let start_time = Instant::now();
-        let mut refresh_timer: i64 = Console::REFRESH_RATE;
         loop {
-            let address = Console::physical(self.console.r3000.pc());
-            let maybe_block = self.blocks.get(&address);
-            match maybe_block {
-                Some(block) => {
-                    //println!("ran block from {:#x}", address);
-                    let init_pc = self.console.r3000.pc();
-                    let t0 = Instant::now();
-                    let stubs = block.stubs();
-                    //this is updated if we updated early
-                    let mut steps_taken = block.nominal_len();
-                    for (i, stub) in stubs.iter().enumerate() {
-                        self.console.r3000.flush_write_cache(
-                            &mut self.console.delayed_writes,
-                            &mut self.console.modified_register,
-                        );
-                        match stub.execute(&mut self.console, logging) {
-                            Some(next_pc) => {
-                                steps_taken = i as u32 + 1;
-                                if i + 1 != stubs.len() {
-                                    steps_taken = i as u32 + 2;
-                                    match self.console.gpu.exec_next_gp0_command() {
-                                        Some(object) => self.console.screen.draw(object),
-                                        None => (),
-                                    }
-                                    self.console.cd.exec_command();
-                                    self.console.r3000.flush_write_cache(
-                                        &mut self.console.delayed_writes,
-                                        &mut self.console.modified_register,
-                                    );
-                                    stubs[i + 1].execute(&mut self.console, logging);
-                                    match self.console.gpu.exec_next_gp0_command() {
-                                        Some(object) => self.console.screen.draw(object),
-                                        None => (),
-                                    }
-                                    self.console.cd.exec_command();
-                                };
-                                *self.console.r3000.pc_mut() = next_pc;
-                                break
-                            },
-                            None => (),
+            //run blocks until the next event is due, I/O writes in a block may schedule an
+            // earlier one
+            while self.console.scheduler.cycles() < self.console.scheduler.next_event_at() {
+                //blocks end after a delay slot so interrupts are checked between blocks
+                self.console.check_interrupts();
+                self.console.check_fast_boot();
+                let address = Console::physical(self.console.r3000.pc());
+                let maybe_block = self.blocks.get(&address);
+                match maybe_block {
+                    Some(block) => {
+                        //println!("ran block from {:#x}", address);
+                        let init_pc = self.console.r3000.pc();
+                        let t0 = Instant::now();
+                        let stubs = block.stubs();
+                        //this is updated if we updated early
+                        let mut steps_taken = block.nominal_len();
+                        for (i, stub) in stubs.iter().enumerate() {
+                            self.console.r3000.flush_write_cache(
+                                &mut self.console.delayed_writes,
+                                &mut self.console.modified_register,
+                            );
+                            match stub.execute(&mut self.console, logging) {
+                                Some(next_pc) => {
+                                    steps_taken = i as u32 + 1;
+                                    if i + 1 != stubs.len() {
+                                        steps_taken = i as u32 + 2;
+                                        self.console.r3000.flush_write_cache(
+                                            &mut self.console.delayed_writes,
+                                            &mut self.console.modified_register,
+                                        );
+                                        stubs[i + 1].execute(&mut self.console, logging);
+                                    };
+                                    *self.console.r3000.pc_mut() = next_pc;
+                                    break
+                                },
+                                None => (),
+                            }
                         }
-                        match self.console.gpu.exec_next_gp0_command() {
-                            Some(object) => self.console.screen.draw(object),
-                            None => (),
+                        let final_pc = self.console.r3000.pc();
+                        println!("ran block from {:#x} to {:#x}", init_pc, final_pc);
+                        //blocks that exit early are charged for the fraction that was executed
+                        let cycles = block.cycles() * steps_taken / block.nominal_len();
+                        self.console.scheduler.advance(cycles);
+                        self.console.i += steps_taken;
+                        n.map(|n| {
+                            if self.console.i >= n {
+                                let end_time = Instant::now();
+                                panic!(
+                                    "Executed {} steps in {:?}",
+                                    self.console.i,
+                                    end_time - start_time
+                                );
+                            };
+                        });
+                        let end = block.final_pc();
+                        let block_invalidated = self
+                            .console
+                            .overwritten
+                            .iter()
+                            .any(|&x| address <= x && x <= end);
+                        //if this block was invalidated by a write
+                        if block_invalidated {
+                            self.cache_invalidation(address);
                         }
-                        self.console.cd.exec_command();
-                    }
-                    let final_pc = self.console.r3000.pc();
-                    println!("ran block from {:#x} to {:#x}", init_pc, final_pc);
-                    refresh_timer -= steps_taken as i64;
-                    if refresh_timer < 0 {
-                        self.console.screen.refresh_window();
-                        refresh_timer = Console::REFRESH_RATE;
-                    }
-                    self.console.i += steps_taken;
-                    n.map(|n| {
-                        if self.console.i >= n {
-                            let end_time = Instant::now();
-                            panic!(
-                                "Executed {} steps in {:?}",
-                                self.console.i,
-                                end_time - start_time
-                            );
-                        };
-                    });
-                    let end = block.final_pc();
-                    let block_invalidated = self
-                        .console
-                        .overwritten
-                        .iter()
-                        .any(|&x| address <= x && x <= end);
-                    //if this block was invalidated by a write
-                    if block_invalidated {
-                        self.cache_invalidation(address);
-                    }
-                    self.console.overwritten.clear();
-                    if !self.console.handle_events() {
-                        return
-                    }
-                },
-                None => {
-                    self.translate(optimize, logging);
-                },
+                        self.console.overwritten.clear();
+                    },
+                    None => {
+                        self.translate(optimize, logging);
+                    },
+                }
+            }
+            if !self.console.run_events() {
+                return
             }
         }
     }
@@ -178,6 +178,39 @@ impl Stub {
           }
         }
       };
+      //transfers between memory and GTE data registers
+      (gte rt = [rs + imm16]) => {
+        {
+          let s = get_rs(op);
+          let t = get_rt(op);
+          let imm16 = get_imm16(op).half_sign_extended();
+          Box::new(move |vm| {
+            let rs = vm.r3000.nth_reg(s);
+            vm.load_gte_word(rs.wrapping_add(imm16), t);
+            log!("GTE R{} = [{:#x} + {:#x}] \n  = [{:#x}] \n  = {:#x}",
+                      t, rs, imm16, rs.wrapping_add(imm16), vm.gte.nth_data_reg(t));
+            None
+          })
+        }
+      };
+      ([rs + imm16] = gte rt) => {
+        {
+          let s = get_rs(op);
+          let t = get_rt(op);
+          let imm16 = get_imm16(op).half_sign_extended();
+          Box::new(move |vm| {
+            let rs = vm.r3000.nth_reg(s);
+            log!("[{:#x} + {:#x}] = [{:#x}] \n  = GTE R{}\n  = {:#x}",
+                      rs, imm16, rs.wrapping_add(imm16), t, vm.gte.nth_data_reg(t));
+            if !vm.cop0.cache_isolated() {
+              vm.store_gte_word(rs.wrapping_add(imm16), t);
+            } else {
+              log!("ignoring write while cache is isolated");
+            };
+            None
+          })
+        }
+      };
       (lo = rs) => {
         {
           let s = get_rs(op);
@@ -1103,7 +1136,8 @@ impl Stub {
             },
             0x32 => {
                 //LWC2
-                todo!("lwc2")
+                log!("> LWC2");
+                mov!(gte rt = [rs + imm16])
             },
             0x33 => {
                 //LWC3
@@ -1119,7 +1153,8 @@ impl Stub {
             },
             0x3A => {
                 //SWC2
-                todo!("swc2")
+                log!("> SWC2");
+                mov!([rs + imm16] = gte rt)
             },
             0x3B => {
                 //SWC3
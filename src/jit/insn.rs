@@ -136,6 +136,11 @@ impl Insn {
                 //SB, SH, SWL, SW, SWR
                 (vec![get_rt(op)], Some(get_rs(op)), None)
             },
+            0x32 | 0x3A => {
+                //LWC2, SWC2
+                //rt refers to a GTE register so only rs is a MIPS register
+                (vec![], Some(get_rs(op)), None)
+            },
             _ => {
                 unreachable!("Invalid opcode {:#x}", op);
             },
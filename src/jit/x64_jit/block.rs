@@ -26,6 +26,8 @@ pub struct Block {
     function: JITFn,
     final_phys_pc: u32,
     nominal_len: u32,
+    //the number of CPU cycles taken to run the whole block
+    cycles: u32,
 }
 
 impl Block {
@@ -33,12 +35,14 @@ impl Block {
     pub const COP0_REG_POS: usize = 1;
     pub const DEBUG_POS: usize = 12;
     pub const GEN_EXCEPTION: usize = 11;
+    pub const LOAD_GTE_WORD_POS: usize = 13;
     pub const R3000_REG_POS: usize = 0;
     pub const READ_BYTE_POS: usize = 8;
     pub const READ_BYTE_SIGN_EXTENDED_POS: usize = 10;
     pub const READ_HALF_POS: usize = 7;
     pub const READ_HALF_SIGN_EXTENDED_POS: usize = 9;
     pub const READ_WORD_POS: usize = 6;
+    pub const STORE_GTE_WORD_POS: usize = 14;
     pub const WRITE_BYTE_POS: usize = 5;
     pub const WRITE_HALF_POS: usize = 4;
     pub const WRITE_WORD_POS: usize = 3;
@@ -49,10 +53,15 @@ impl Block {
     ) -> io::Result<Self>
     {
         let function = Block::create_function(tagged_opcodes, &console, initial_pc, logging)?;
+        let cycles = tagged_opcodes
+            .iter()
+            .map(|insn| R3000::cycles(insn.op()))
+            .sum();
         Ok(Block {
             function,
             final_phys_pc,
             nominal_len,
+            cycles,
         })
     }
 
@@ -83,12 +92,16 @@ impl Block {
         self.nominal_len
     }
 
+    pub fn cycles(&self) -> u32 {
+        self.cycles
+    }
+
     fn create_function(
         tagged_opcodes: &Vec<Insn>, console: &Console, initial_pc: u32, logging: bool,
     ) -> io::Result<JITFn> {
         let mut inputs = tagged_opcodes.registers();
         inputs.push(R3000::PC_IDX as u32);
-        let mut ptrs = vec![0; 13];
+        let mut ptrs = vec![0; 15];
         ptrs[Block::R3000_REG_POS] = console.r3000.reg_ptr() as u64;
         ptrs[Block::COP0_REG_POS] = console.cop0.reg_ptr() as u64;
         ptrs[Block::CONSOLE_POS] = console as *const Console as u64;
@@ -102,6 +115,8 @@ impl Block {
         ptrs[Block::READ_BYTE_SIGN_EXTENDED_POS] = Console::read_byte_sign_extended as u64;
         ptrs[Block::GEN_EXCEPTION] = Console::generate_exception as u64;
         ptrs[Block::DEBUG_POS] = Console::print_value as u64;
+        ptrs[Block::LOAD_GTE_WORD_POS] = Console::load_gte_word as u64;
+        ptrs[Block::STORE_GTE_WORD_POS] = Console::store_gte_word as u64;
         let mut rc = Recompiler::new(&inputs, &ptrs);
         let mut next_op = NextOp::Standard;
         let end = rc.new_long_label();
@@ -11,6 +11,7 @@ pub trait DynaRec {
     fn emit_insn(&mut self, insn: &Insn, initial_pc: u32) -> NextOp;
     fn emit_load(&mut self, op: u32, function_ptr: usize);
     fn emit_store(&mut self, op: u32, function_ptr: usize);
+    fn emit_gte_transfer(&mut self, op: u32, function_ptr: usize, is_store: bool);
     fn emit_addi(&mut self, op: u32);
     fn emit_jump_imm26(&mut self, insn: &Insn, initial_pc: u32) -> NextOp;
     fn emit_jump_reg(&mut self, insn: &Insn, initial_pc: u32) -> NextOp;
@@ -604,6 +605,14 @@ impl DynaRec for Recompiler {
                 //SW
                 self.emit_store(op, Block::WRITE_WORD_POS);
             },
+            0x32 => {
+                //LWC2
+                self.emit_gte_transfer(op, Block::LOAD_GTE_WORD_POS, false);
+            },
+            0x3A => {
+                //SWC2
+                self.emit_gte_transfer(op, Block::STORE_GTE_WORD_POS, true);
+            },
             _ => todo!("primary field {:#x}", get_primary_field(op)),
         };
         NextOp::Standard
@@ -679,6 +688,43 @@ impl DynaRec for Recompiler {
         self.define_label(end);
     }
 
+    fn emit_gte_transfer(&mut self, op: u32, function_ptr: usize, is_store: bool) {
+        let s = get_rs(op);
+        let t = get_rt(op);
+        let imm16 = get_imm16(op).half_sign_extended();
+
+        let end = self.new_label();
+        let console = self.new_u64();
+        let address = self.new_u32();
+        let gte_idx = self.new_u32();
+
+        self.load_ptr(console, Block::CONSOLE_POS);
+        match self.reg(s) {
+            Some(rs) => {
+                self.setv_u32(address, rs);
+            },
+            None => {
+                self.seti_u32(address, 0);
+            },
+        }
+        self.addi_u32(address, imm16 as i32);
+        self.seti_u32(gte_idx, t);
+
+        self.set_arg1(console);
+        self.set_arg2(address);
+        self.set_arg3(gte_idx);
+        //stores are ignored while the cache is isolated
+        if is_store {
+            let cop0r12 = self.new_u32();
+            self.load_ptr(cop0r12, Block::COP0_REG_POS);
+            self.deref_u32(cop0r12);
+            self.bti_u32(cop0r12, 16);
+            self.jump_if_carry(end);
+        }
+        self.call_ptr(function_ptr);
+        self.define_label(end);
+    }
+
     fn emit_addi(&mut self, op: u32) {
         let s = get_rs(op);
         let t = get_rt(op);
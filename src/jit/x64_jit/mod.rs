@@ -1,3 +1,4 @@
+use crate::console::audio::AudioConfig;
 use crate::console::Console;
 use crate::jit::insn::Insn;
 use crate::jit::x64_jit::block::Block;
@@ -16,9 +17,19 @@ pub struct X64JIT {
 
 impl X64JIT {
     pub fn new(
-        bios_filename: &String, infile: Option<&String>, gpu_logging: bool, wx: u32, wy: u32,
+        bios_filename: &String, infile: Option<&String>, fast_boot: bool, audio: &AudioConfig,
+        memory_cards: [Option<&String>; 2], gpu_logging: bool, wx: u32, wy: u32,
     ) -> io::Result<Self> {
-        let console = Console::new(bios_filename, infile, gpu_logging, wx, wy)?;
+        let console = Console::new(
+            bios_filename,
+            infile,
+            fast_boot,
+            audio,
+            memory_cards,
+            gpu_logging,
+            wx,
+            wy,
+        )?;
         Ok(Self {
             console,
             blocks: Default::default(),
@@ -31,55 +42,52 @@ impl X64JIT {
         let t0 = Instant::now();
         let mut compile_time = t0 - t0;
         let mut run_time = t0 - t0;
-        let mut refresh_timer: i64 = Console::REFRESH_RATE;
         loop {
-            let address = Console::physical(self.console.r3000.pc());
-            let maybe_block = self.blocks.get(&address);
-            match maybe_block {
-                Some(block) => {
-                    let t0 = Instant::now();
-                    let init_pc = self.console.r3000.pc();
-                    block.run();
-                    let final_pc = self.console.r3000.pc();
-                    //println!("ran block from {:#x} to {:#x}", init_pc, final_pc);
-                    match self.console.gpu.exec_next_gp0_command() {
-                        Some(object) => self.console.screen.draw(object),
-                        None => (),
-                    }
-                    self.console.cd.exec_command();
-                    refresh_timer -= block.nominal_len() as i64;
-                    if refresh_timer < 0 {
-                        self.console.screen.refresh_window();
-                        refresh_timer = Console::REFRESH_RATE;
-                    }
-                    self.console.i += block.nominal_len();
-                    n.map(|n| {
-                        if self.console.i >= n {
-                            panic!(
-                                "Executed {} steps with {:?} of compile time and {:?} of run time",
-                                self.console.i, compile_time, run_time
-                            );
-                        };
-                    });
-                    let block_invalidated = self
-                        .console
-                        .overwritten
-                        .iter()
-                        .any(|&x| address <= x && x <= block.final_phys_pc());
-                    //if this block was invalidated by a write
-                    if block_invalidated {
-                        self.cache_invalidation(address);
-                    }
-                    self.console.overwritten.clear();
-                    if !self.console.handle_events() {
-                        return Ok(())
-                    }
-                    let t1 = Instant::now();
-                    run_time += t1 - t0;
-                },
-                None => {
-                    compile_time += self.translate(optimize, logging)?;
-                },
+            //run blocks until the next event is due, I/O writes in a block may schedule an
+            // earlier one
+            while self.console.scheduler.cycles() < self.console.scheduler.next_event_at() {
+                //blocks end after a delay slot so interrupts are checked between blocks
+                self.console.check_interrupts();
+                self.console.check_fast_boot();
+                let address = Console::physical(self.console.r3000.pc());
+                let maybe_block = self.blocks.get(&address);
+                match maybe_block {
+                    Some(block) => {
+                        let t0 = Instant::now();
+                        let init_pc = self.console.r3000.pc();
+                        block.run();
+                        let final_pc = self.console.r3000.pc();
+                        //println!("ran block from {:#x} to {:#x}", init_pc, final_pc);
+                        self.console.scheduler.advance(block.cycles());
+                        self.console.i += block.nominal_len();
+                        n.map(|n| {
+                            if self.console.i >= n {
+                                panic!(
+                                    "Executed {} steps with {:?} of compile time and {:?} of run time",
+                                    self.console.i, compile_time, run_time
+                                );
+                            };
+                        });
+                        let block_invalidated = self
+                            .console
+                            .overwritten
+                            .iter()
+                            .any(|&x| address <= x && x <= block.final_phys_pc());
+                        //if this block was invalidated by a write
+                        if block_invalidated {
+                            self.cache_invalidation(address);
+                        }
+                        self.console.overwritten.clear();
+                        let t1 = Instant::now();
+                        run_time += t1 - t0;
+                    },
+                    None => {
+                        compile_time += self.translate(optimize, logging)?;
+                    },
+                }
+            }
+            if !self.console.run_events() {
+                return Ok(())
             }
         }
     }
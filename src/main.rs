@@ -1,4 +1,5 @@
 #![feature(llvm_asm)]
+use console::audio::{AudioConfig, AudioOutput};
 use interpreter::Interpreter;
 use jit::caching_interpreter::CachingInterpreter;
 use jit::x64_jit::X64JIT;
@@ -38,6 +39,15 @@ const OPT_FLAGS: [&str; 2] = ["-o", "--optimize"];
 const BIOS_FLAGS: [&str; 2] = ["-b", "--bios"];
 //specify the input file
 const INFILE_FLAGS: [&str; 2] = ["-i", "--input"];
+//boot the executable on the disc without running the BIOS shell
+const FASTBOOT_FLAGS: [&str; 2] = ["-f", "--fast-boot"];
+//send audio to an SDL device, nowhere or a WAV file
+const AUDIO_FLAGS: [&str; 2] = ["-a", "--audio"];
+//pace the emulator with the audio device instead of running as fast as possible
+const AUDIO_SYNC_FLAGS: [&str; 2] = ["-y", "--audio-sync"];
+//put a memory card backed by a .mcr file in slot 1 or 2
+const MEMCARD1_FLAGS: [&str; 2] = ["-m", "--memcard1"];
+const MEMCARD2_FLAGS: [&str; 2] = ["-M", "--memcard2"];
 //run for a given number of steps
 const STEPS_FLAGS: [&str; 2] = ["-n", "--steps"];
 //print logging info
@@ -46,13 +56,18 @@ const LOG_FLAGS: [&str; 2] = ["-l", "--log"];
 const GPULOG_FLAGS: [&str; 2] = ["-g", "--gpu"];
 //set resolution
 const RESOLUTION_FLAGS: [&str; 2] = ["-s", "--size"];
-const ALL_FLAGS: [([&str; 2], Option<&str>); 10] = [
+const ALL_FLAGS: [([&str; 2], Option<&str>); 15] = [
     (HELP_FLAGS, None),
     (CACHE_FLAGS, None),
     (JIT_FLAGS, None),
     (OPT_FLAGS, None),
     (BIOS_FLAGS, Some("BIOS")),
     (INFILE_FLAGS, Some("INFILE")),
+    (FASTBOOT_FLAGS, None),
+    (AUDIO_FLAGS, Some("sdl|null|FILE.wav")),
+    (AUDIO_SYNC_FLAGS, None),
+    (MEMCARD1_FLAGS, Some("FILE.mcr")),
+    (MEMCARD2_FLAGS, Some("FILE.mcr")),
     (LOG_FLAGS, None),
     (GPULOG_FLAGS, None),
     (RESOLUTION_FLAGS, Some("WIDTHxHEIGHT")),
@@ -76,6 +91,15 @@ fn main() -> io::Result<()> {
     let args: Vec<String> = env::args().collect();
     let bios = get_arg(&args, &BIOS_FLAGS);
     let infile = get_arg(&args, &INFILE_FLAGS);
+    let fast_boot = check_flag(&args, &FASTBOOT_FLAGS);
+    let audio = AudioConfig {
+        output: AudioOutput::parse(get_arg(&args, &AUDIO_FLAGS)),
+        sync: check_flag(&args, &AUDIO_SYNC_FLAGS),
+    };
+    let memory_cards = [
+        get_arg(&args, &MEMCARD1_FLAGS),
+        get_arg(&args, &MEMCARD2_FLAGS),
+    ];
     let help = check_flag(&args, &HELP_FLAGS);
     let cache = check_flag(&args, &CACHE_FLAGS);
     let jit = check_flag(&args, &JIT_FLAGS);
@@ -105,14 +129,41 @@ fn main() -> io::Result<()> {
         match bios {
             Some(bios_filename) => {
                 if cache {
-                    CachingInterpreter::new(bios_filename, infile, gpu_logging, wx, wy)?
-                        .run(steps, opt, logging);
+                    CachingInterpreter::new(
+                        bios_filename,
+                        infile,
+                        fast_boot,
+                        &audio,
+                        memory_cards,
+                        gpu_logging,
+                        wx,
+                        wy,
+                    )?
+                    .run(steps, opt, logging);
                 } else if jit {
-                    X64JIT::new(bios_filename, infile, gpu_logging, wx, wy)?
-                        .run(steps, opt, logging)?;
+                    X64JIT::new(
+                        bios_filename,
+                        infile,
+                        fast_boot,
+                        &audio,
+                        memory_cards,
+                        gpu_logging,
+                        wx,
+                        wy,
+                    )?
+                    .run(steps, opt, logging)?;
                 } else {
-                    Interpreter::new(bios_filename, infile, gpu_logging, wx, wy)?
-                        .run(steps, logging);
+                    Interpreter::new(
+                        bios_filename,
+                        infile,
+                        fast_boot,
+                        &audio,
+                        memory_cards,
+                        gpu_logging,
+                        wx,
+                        wy,
+                    )?
+                    .run(steps, logging);
                 }
             },
             None => {